@@ -0,0 +1,39 @@
+extern crate rusty_tokens;
+
+use std::str::FromStr;
+use std::time::Instant;
+use rusty_tokens::jwt::{JsonWebToken, Claim, RegisteredClaim};
+use rusty_tokens::jwt::jwt_ref::JwtRef;
+
+const SAMPLE_TOKEN: &'static str = "eyJraWQiOiJ0ZXN0a2V5LWVzMjU2IiwiYWxnIjoiRVMyNTYifQ.\
+                                    eyJzdWIiOiJ0ZXN0MiIsInNjb3BlIjpbImNuIl0sImlzcyI6IkIiLCJyZWFsbSI6Ii9zZXJ2aWNlcyIsImV4cCI6MTQ1NzMxOTgxNCwiaWF0IjoxNDU3MjkxMDE0fQ.\
+                                    KmDsVB09RAOYwT0Y6E9tdQpg0rAPd8SExYhcZ9tXEO6y9AWX4wBylnmNHVoetWu7MwoexWkaKdpKk09IodMVug";
+
+const ITERATIONS: u32 = 100000;
+
+/// Compares reading the subject of a token with the owned and the borrowed parser.
+fn main() {
+    let subject = Claim::Registered(RegisteredClaim::Subject);
+
+    let owned = JsonWebToken::from_str(SAMPLE_TOKEN).unwrap();
+    let borrowed = JwtRef::parse(SAMPLE_TOKEN).unwrap();
+    assert_eq!(owned.get_payload(&subject).cloned(),
+               borrowed.get_payload(&subject).unwrap());
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let token = JsonWebToken::from_str(SAMPLE_TOKEN).unwrap();
+        assert!(token.get_payload(&subject).is_some());
+    }
+    let owned_elapsed = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let token = JwtRef::parse(SAMPLE_TOKEN).unwrap();
+        assert!(token.get_payload(&subject).unwrap().is_some());
+    }
+    let borrowed_elapsed = start.elapsed();
+
+    println!("JsonWebToken::from_str: {:?} for {} tokens", owned_elapsed, ITERATIONS);
+    println!("JwtRef::parse:          {:?} for {} tokens", borrowed_elapsed, ITERATIONS);
+}
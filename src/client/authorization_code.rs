@@ -0,0 +1,589 @@
+//! The authorization code grant with PKCE for tools that act on behalf of a human.
+//!
+//! The user authenticates in a browser. The provider then redirects to a loopback
+//! listener on `127.0.0.1` as described in [RFC 8252](https://tools.ietf.org/html/rfc8252)
+//! and the captured code is exchanged for an `AccessToken`.
+//!
+//! ```no_run
+//! # extern crate hyper;
+//! # extern crate rusty_tokens;
+//! use rusty_tokens::Scope;
+//! use rusty_tokens::client::authorization_code::AuthorizationCodeFlow;
+//!
+//! # fn main() {
+//! let flow = AuthorizationCodeFlow::new(hyper::Client::new(),
+//!                                       "https://auth.example.org/authorize",
+//!                                       "https://auth.example.org/oauth2/access_token",
+//!                                       "my-cli")
+//!     .with_scope(Scope::new("uid"));
+//! let access_token = flow.authorize(|url| println!("Please open {}", url)).unwrap();
+//! # }
+//! ```
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::time::Duration;
+use hyper;
+use openssl::error::ErrorStack;
+use openssl::hash::{hash, MessageDigest};
+use openssl::rand::rand_bytes;
+use rustc_serialize::base64::{ToBase64, URL_SAFE};
+use url::{self, form_urlencoded, Url};
+use Scope;
+use client::implementation::{AccessToken, RequestAccessTokenError, RequestAccessTokenResult};
+use client::implementation::hypertokenmanager::{append_audiences, evaluate_response,
+                                                post_client_form, TokenFormat};
+
+/// The only supported `code_challenge_method`
+pub const CODE_CHALLENGE_METHOD: &'static str = "S256";
+
+/// Connections of the loopback listener are dropped if no request arrives within this time.
+const REQUEST_TIMEOUT_SECONDS: u64 = 1;
+
+/// A PKCE code verifier and its `S256` challenge as defined by
+/// [RFC 7636](https://tools.ietf.org/html/rfc7636)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pkce {
+    /// Sent with the token request
+    pub verifier: String,
+    /// Sent with the authorization request
+    pub challenge: String,
+}
+
+impl Pkce {
+    /// Generates a verifier from 32 random bytes.
+    pub fn generate() -> Result<Pkce, AuthorizationCodeError> {
+        Pkce::from_verifier(try!{random_string(32)})
+    }
+
+    /// Derives the challenge of a given verifier.
+    pub fn from_verifier<T: Into<String>>(verifier: T) -> Result<Pkce, AuthorizationCodeError> {
+        let verifier = verifier.into();
+        let digest = try!{hash(MessageDigest::sha256(), verifier.as_bytes())};
+        Ok(Pkce {
+            challenge: digest.to_base64(URL_SAFE),
+            verifier: verifier,
+        })
+    }
+}
+
+fn random_string(len: usize) -> Result<String, AuthorizationCodeError> {
+    let mut bytes = vec![0; len];
+    try!{rand_bytes(&mut bytes)};
+    Ok(bytes.to_base64(URL_SAFE))
+}
+
+/// Receives the redirect of the provider on `127.0.0.1`
+pub struct LoopbackListener {
+    listener: TcpListener,
+    redirect_uri: String,
+}
+
+impl LoopbackListener {
+    /// Listens on a free port.
+    pub fn bind() -> io::Result<LoopbackListener> {
+        LoopbackListener::bind_port(0)
+    }
+
+    /// Listens on the given port, e.g. if the provider only accepts registered redirect URIs.
+    pub fn bind_port(port: u16) -> io::Result<LoopbackListener> {
+        let listener = try!{TcpListener::bind(("127.0.0.1", port))};
+        let port = try!{listener.local_addr()}.port();
+        Ok(LoopbackListener {
+            listener: listener,
+            redirect_uri: format!("http://127.0.0.1:{}/callback", port),
+        })
+    }
+
+    /// The `redirect_uri` to send with the authorization request
+    pub fn redirect_uri(&self) -> &str {
+        &self.redirect_uri
+    }
+
+    /// Blocks until the provider redirects to `/callback` and returns the code.
+    ///
+    /// Other requests, e.g. for a `favicon.ico`, are answered with `404` and ignored.
+    /// So are connections that send no valid request within a second, e.g. ones a
+    /// browser opened speculatively. Redirects with a foreign `state` are answered with
+    /// `400`, so that a stale or forged redirect can not end the flow.
+    pub fn wait_for_code(&self, state: &str) -> Result<String, AuthorizationCodeError> {
+        for stream in self.listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("Could not accept a connection: {}", err);
+                    continue;
+                }
+            };
+            let timeout = Some(Duration::from_secs(REQUEST_TIMEOUT_SECONDS));
+            if let Err(err) = stream.set_read_timeout(timeout) {
+                warn!("Could not set the read timeout: {}", err);
+                continue;
+            }
+            let url = match read_request_url(&mut BufReader::new(&mut stream)) {
+                Ok(url) => url,
+                Err(err) => {
+                    debug!("Ignoring a connection without a valid request: {}", err);
+                    continue;
+                }
+            };
+            if url.path() != "/callback" {
+                if let Err(err) = stream.write_all(b"HTTP/1.1 404 Not Found\r\n\
+                                                     Content-Length: 0\r\n\
+                                                     Connection: close\r\n\r\n") {
+                    debug!("Could not answer a request for '{}': {}", url.path(), err);
+                }
+                continue;
+            }
+            let result = callback_result(&url, state);
+            let (status, message) = match result {
+                Ok(_) => ("200 OK", "You have been authenticated. You can close this window now."),
+                Err(AuthorizationCodeError::StateMismatch) => {
+                    ("400 Bad Request",
+                     "This redirect does not belong to the pending authentication.")
+                }
+                Err(_) => ("200 OK", "The authentication failed. Please check the console."),
+            };
+            let written = write!(stream,
+                                 "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\n\
+                                  Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                                 status,
+                                 message.len(),
+                                 message);
+            match result {
+                Err(AuthorizationCodeError::StateMismatch) => {
+                    warn!("Ignoring a redirect with a foreign state.");
+                    if let Err(err) = written {
+                        debug!("Could not answer a redirect with a foreign state: {}", err);
+                    }
+                }
+                result => {
+                    try!{written};
+                    return result;
+                }
+            }
+        }
+        Err(AuthorizationCodeError::IoError(String::from("The listener stopped.")))
+    }
+}
+
+/// Reads the request line and the headers and returns the URL of the request target.
+fn read_request_url<R: BufRead>(reader: &mut R) -> Result<Url, AuthorizationCodeError> {
+    let mut request_line = String::new();
+    try!{reader.read_line(&mut request_line)};
+    loop {
+        let mut header = String::new();
+        if try!{reader.read_line(&mut header)} == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+    match request_line.split_whitespace().nth(1) {
+        Some(target) if target.starts_with('/') => {
+            Ok(try!{Url::parse(&format!("http://127.0.0.1{}", target))})
+        }
+        _ => {
+            Err(AuthorizationCodeError::IoError(format!("Invalid request '{}'",
+                                                        request_line.trim())))
+        }
+    }
+}
+
+fn callback_result(url: &Url, state: &str) -> Result<String, AuthorizationCodeError> {
+    let query_value = |name: &str| {
+        url.query_pairs().find(|&(ref key, _)| key == name).map(|(_, value)| value.into_owned())
+    };
+    if let Some(error) = query_value("error") {
+        return Err(AuthorizationCodeError::Denied {
+            error: error,
+            description: query_value("error_description"),
+        });
+    }
+    if query_value("state").as_ref().map(|s| s.as_ref()) != Some(state) {
+        return Err(AuthorizationCodeError::StateMismatch);
+    }
+    query_value("code").ok_or(AuthorizationCodeError::MissingCode)
+}
+
+/// Requests `AccessToken`s with the authorization code grant and PKCE
+pub struct AuthorizationCodeFlow {
+    client: hyper::Client,
+    authorize_url: String,
+    token_url: String,
+    client_id: String,
+    client_secret: Option<String>,
+    scopes: Vec<Scope>,
+    audiences: Vec<String>,
+}
+
+impl AuthorizationCodeFlow {
+    /// Create a new instance for a public client, i.e. one without a secret
+    pub fn new<A, T, C>(client: hyper::Client,
+                        authorize_url: A,
+                        token_url: T,
+                        client_id: C)
+                        -> AuthorizationCodeFlow
+        where A: Into<String>,
+              T: Into<String>,
+              C: Into<String>
+    {
+        AuthorizationCodeFlow {
+            client: client,
+            authorize_url: authorize_url.into(),
+            token_url: token_url.into(),
+            client_id: client_id.into(),
+            client_secret: None,
+            scopes: Vec::new(),
+            audiences: Vec::new(),
+        }
+    }
+
+    /// Builder method. Authenticate the client with HTTP Basic authentication.
+    pub fn with_client_secret<T: Into<String>>(self, client_secret: T) -> Self {
+        let mut x = self;
+        x.client_secret = Some(client_secret.into());
+        x
+    }
+
+    /// Builder method. Add a `Scope`.
+    pub fn with_scope(self, scope: Scope) -> Self {
+        let mut x = self;
+        x.scopes.push(scope);
+        x
+    }
+
+    /// Builder method. Add an audience the `Token` shall be restricted to.
+    pub fn with_audience<T: Into<String>>(self, audience: T) -> Self {
+        let mut x = self;
+        x.audiences.push(audience.into());
+        x
+    }
+
+    /// The URL to open in the browser of the user.
+    pub fn authorize_url(&self,
+                         redirect_uri: &str,
+                         state: &str,
+                         pkce: &Pkce)
+                         -> Result<String, AuthorizationCodeError> {
+        let mut url = try!{Url::parse(&self.authorize_url)};
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("response_type", "code")
+                .append_pair("client_id", &self.client_id)
+                .append_pair("redirect_uri", redirect_uri);
+            if !self.scopes.is_empty() {
+                query.append_pair("scope", &self.scope_string());
+            }
+            append_audiences(&mut query, &self.audiences);
+            query.append_pair("state", state)
+                .append_pair("code_challenge", &pkce.challenge)
+                .append_pair("code_challenge_method", CODE_CHALLENGE_METHOD);
+        }
+        Ok(url.into_string())
+    }
+
+    /// Exchanges a code for an `AccessToken`.
+    pub fn exchange_code(&self,
+                         code: &str,
+                         redirect_uri: &str,
+                         pkce: &Pkce)
+                         -> RequestAccessTokenResult {
+        let mut form = form_urlencoded::Serializer::new(String::new());
+        form.append_pair("grant_type", "authorization_code")
+            .append_pair("code", code)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("code_verifier", &pkce.verifier);
+        append_audiences(&mut form, &self.audiences);
+        let mut response = try!{post_client_form(&self.client,
+                                                 &self.token_url,
+                                                 &self.client_id,
+                                                 self.client_secret.as_ref().map(|s| s.as_ref()),
+                                                 form)};
+        evaluate_response(&mut response, TokenFormat::PlanBOrOpaque)
+    }
+
+    /// Runs the whole flow.
+    ///
+    /// `open_browser` is called with the authorize URL, e.g. to print it or to launch
+    /// a browser. Blocks until the provider redirected to the loopback listener.
+    pub fn authorize<F>(&self, open_browser: F) -> Result<AccessToken, AuthorizationCodeError>
+        where F: FnOnce(&str)
+    {
+        let listener = try!{LoopbackListener::bind()};
+        let pkce = try!{Pkce::generate()};
+        let state = try!{random_string(16)};
+        open_browser(&try!{self.authorize_url(listener.redirect_uri(), &state, &pkce)});
+        let code = try!{listener.wait_for_code(&state)};
+        Ok(try!{self.exchange_code(&code, listener.redirect_uri(), &pkce)})
+    }
+
+    fn scope_string(&self) -> String {
+        let scopes: Vec<&str> = self.scopes.iter().map(|scope| scope.0.as_ref()).collect();
+        scopes.join(" ")
+    }
+}
+
+/// Errors of the authorization code flow
+#[derive(Debug, Clone)]
+pub enum AuthorizationCodeError {
+    IoError(String),
+    InvalidUrl(String),
+    CryptoError(String),
+    /// The provider redirected with an `error`, e.g. because the user denied the access
+    Denied {
+        error: String,
+        description: Option<String>,
+    },
+    /// The `state` of the redirect is not the one of the authorization request
+    StateMismatch,
+    /// The redirect has neither a `code` nor an `error`
+    MissingCode,
+    /// The code could not be exchanged
+    RequestError(RequestAccessTokenError),
+}
+
+impl fmt::Display for AuthorizationCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AuthorizationCodeError::IoError(ref message) => write!(f, "IoError: {}", message),
+            AuthorizationCodeError::InvalidUrl(ref message) => {
+                write!(f, "Invalid URL: {}", message)
+            }
+            AuthorizationCodeError::CryptoError(ref message) => {
+                write!(f, "Crypto error: {}", message)
+            }
+            AuthorizationCodeError::Denied { ref error, description: Some(ref description) } => {
+                write!(f, "The authorization was denied: {}: {}", error, description)
+            }
+            AuthorizationCodeError::Denied { ref error, description: None } => {
+                write!(f, "The authorization was denied: {}", error)
+            }
+            AuthorizationCodeError::StateMismatch => {
+                write!(f, "The state of the redirect does not match")
+            }
+            AuthorizationCodeError::MissingCode => write!(f, "The redirect has no code"),
+            AuthorizationCodeError::RequestError(ref err) => {
+                write!(f, "The code could not be exchanged: {}", err)
+            }
+        }
+    }
+}
+
+impl Error for AuthorizationCodeError {
+    fn description(&self) -> &str {
+        match *self {
+            AuthorizationCodeError::IoError(ref message) |
+            AuthorizationCodeError::InvalidUrl(ref message) |
+            AuthorizationCodeError::CryptoError(ref message) => message.as_ref(),
+            AuthorizationCodeError::Denied { .. } => "The authorization was denied",
+            AuthorizationCodeError::StateMismatch => "The state of the redirect does not match",
+            AuthorizationCodeError::MissingCode => "The redirect has no code",
+            AuthorizationCodeError::RequestError(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            AuthorizationCodeError::RequestError(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AuthorizationCodeError {
+    fn from(err: io::Error) -> Self {
+        AuthorizationCodeError::IoError(format!("{}", err))
+    }
+}
+
+impl From<url::ParseError> for AuthorizationCodeError {
+    fn from(err: url::ParseError) -> Self {
+        AuthorizationCodeError::InvalidUrl(format!("{}", err))
+    }
+}
+
+impl From<ErrorStack> for AuthorizationCodeError {
+    fn from(err: ErrorStack) -> Self {
+        AuthorizationCodeError::CryptoError(format!("{}", err))
+    }
+}
+
+impl From<RequestAccessTokenError> for AuthorizationCodeError {
+    fn from(err: RequestAccessTokenError) -> Self {
+        AuthorizationCodeError::RequestError(err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+    use hyper;
+    use url::Url;
+    use {Scope, Token};
+    use fake_provider::fake_provider;
+    use super::{AuthorizationCodeFlow, AuthorizationCodeError, LoopbackListener, Pkce};
+
+    fn query_value(url: &str, name: &str) -> String {
+        Url::parse(url)
+            .unwrap()
+            .query_pairs()
+            .find(|&(ref key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+            .unwrap()
+    }
+
+    /// Plays the browser that follows the redirect of the provider.
+    fn follow_redirect(redirect_uri: &str, query: &str) -> thread::JoinHandle<String> {
+        let url = Url::parse(redirect_uri).unwrap();
+        let address = format!("127.0.0.1:{}", url.port().unwrap());
+        let request = format!("GET {}?{} HTTP/1.1\r\nHost: {}\r\n\r\n",
+                              url.path(),
+                              query,
+                              address);
+        thread::spawn(move || {
+            let mut stream = TcpStream::connect(&address[..]).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        })
+    }
+
+    #[test]
+    fn the_challenge_must_match_the_rfc_example() {
+        let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk").unwrap();
+
+        assert_eq!("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM", pkce.challenge);
+    }
+
+    #[test]
+    fn generated_verifiers_must_be_long_enough_and_unique() {
+        let first = Pkce::generate().unwrap();
+        let second = Pkce::generate().unwrap();
+
+        assert_eq!(43, first.verifier.len());
+        assert!(first.verifier != second.verifier);
+    }
+
+    #[test]
+    fn the_authorize_url_must_contain_the_challenge() {
+        let flow = AuthorizationCodeFlow::new(hyper::Client::new(),
+                                              "https://auth.example.org/authorize?realm=/users",
+                                              "https://auth.example.org/token",
+                                              "my-cli")
+            .with_scope(Scope::new("uid"))
+            .with_scope(Scope::new("cn"));
+        let pkce = Pkce::from_verifier("verifier").unwrap();
+
+        let url = flow.authorize_url("http://127.0.0.1:4711/callback", "xyz", &pkce).unwrap();
+
+        assert_eq!(format!("https://auth.example.org/authorize?realm=/users&response_type=code&\
+                            client_id=my-cli&redirect_uri=http%3A%2F%2F127.0.0.1%3A4711%2F\
+                            callback&scope=uid+cn&state=xyz&code_challenge={}&\
+                            code_challenge_method=S256",
+                           pkce.challenge),
+                   url);
+    }
+
+    #[test]
+    fn no_scope_must_be_requested_without_scopes() {
+        let flow = AuthorizationCodeFlow::new(hyper::Client::new(),
+                                              "https://auth.example.org/authorize",
+                                              "https://auth.example.org/token",
+                                              "my-cli");
+        let pkce = Pkce::from_verifier("verifier").unwrap();
+
+        let url = flow.authorize_url("http://127.0.0.1:4711/callback", "xyz", &pkce).unwrap();
+
+        assert!(!url.contains("scope="));
+    }
+
+    #[test]
+    fn the_listener_must_skip_idle_and_malformed_connections() {
+        let listener = LoopbackListener::bind().unwrap();
+        let address = format!("127.0.0.1:{}",
+                              Url::parse(listener.redirect_uri()).unwrap().port().unwrap());
+        let _idle = TcpStream::connect(&address[..]).unwrap();
+        let mut malformed = TcpStream::connect(&address[..]).unwrap();
+        malformed.write_all(b"garbage\r\n\r\n").unwrap();
+
+        let browser = follow_redirect(listener.redirect_uri(), "code=abc&state=xyz");
+
+        assert_eq!("abc", listener.wait_for_code("xyz").unwrap());
+        assert!(browser.join().unwrap().contains("You have been authenticated."));
+    }
+
+    #[test]
+    fn the_authorize_url_must_contain_the_audiences() {
+        let flow = AuthorizationCodeFlow::new(hyper::Client::new(),
+                                              "https://auth.example.org/authorize",
+                                              "https://auth.example.org/token",
+                                              "my-cli")
+            .with_audience("https://api.example.org")
+            .with_audience("billing");
+        let pkce = Pkce::from_verifier("verifier").unwrap();
+
+        let url = flow.authorize_url("http://127.0.0.1:4711/callback", "xyz", &pkce).unwrap();
+
+        assert_eq!("https://api.example.org", query_value(&url, "resource"));
+        assert_eq!("billing", query_value(&url, "audience"));
+    }
+
+    #[test]
+    fn the_listener_must_skip_a_foreign_state_and_report_errors() {
+        let listener = LoopbackListener::bind().unwrap();
+        let redirect_uri = String::from(listener.redirect_uri());
+        let waiting = thread::spawn(move || {
+            listener.wait_for_code("xyz").map(|code| (code, listener))
+        });
+
+        let foreign = follow_redirect(&redirect_uri, "code=forged&state=other");
+        assert!(foreign.join().unwrap().starts_with("HTTP/1.1 400 Bad Request"));
+        let browser = follow_redirect(&redirect_uri, "code=abc&state=xyz");
+        assert!(browser.join().unwrap().starts_with("HTTP/1.1 200 OK"));
+        let (code, listener) = waiting.join().unwrap().unwrap();
+        assert_eq!("abc", code);
+
+        follow_redirect(listener.redirect_uri(),
+                        "error=access_denied&error_description=No+way&state=xyz");
+        match listener.wait_for_code("xyz") {
+            Err(AuthorizationCodeError::Denied { error, description }) => {
+                assert_eq!("access_denied", error);
+                assert_eq!(Some(String::from("No way")), description);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_flow_must_exchange_the_captured_code() {
+        let (url, bodies) = fake_provider(vec![(200,
+                                                "{\"access_token\":\"opaque-token\",\
+                                                 \"expires_in\":3600,\
+                                                 \"refresh_token\":\"rt-1\"}")]);
+        let flow = AuthorizationCodeFlow::new(hyper::Client::new(),
+                                              "https://auth.example.org/authorize",
+                                              format!("{}/oauth2/access_token", url),
+                                              "my-cli")
+            .with_scope(Scope::new("uid"));
+        let mut browser = None;
+
+        let access_token = flow.authorize(|url| {
+                let query = format!("code=the-code&state={}", query_value(url, "state"));
+                browser = Some(follow_redirect(&query_value(url, "redirect_uri"), &query));
+            })
+            .unwrap();
+
+        assert_eq!(Token::new("opaque-token"), access_token.token);
+        assert_eq!(Some(String::from("rt-1")), access_token.refresh_token);
+        assert_eq!(3600,
+                   (access_token.valid_until_utc - access_token.issued_at_utc).num_seconds());
+        let body = bodies.recv().unwrap();
+        assert!(body.starts_with("grant_type=authorization_code&code=the-code&redirect_uri="));
+        assert!(body.contains("&code_verifier="));
+        assert!(body.ends_with("&client_id=my-cli"));
+        assert!(browser.unwrap().join().unwrap().contains("You have been authenticated."));
+    }
+}
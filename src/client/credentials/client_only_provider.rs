@@ -0,0 +1,37 @@
+//! Credentials provider for clients that authenticate without a user
+use super::{Credentials, CredentialsError, CredentialsPair, UserCredentialsProvider,
+            ClientCredentialsProvider, CredentialsPairProvider};
+
+/// Provides only the client `Credentials` of a `ClientCredentialsProvider`.
+///
+/// The user credentials of the `CredentialsPair`s are empty, so this provider only works
+/// with the `client_credentials` grant.
+pub struct ClientOnlyCredentialsProvider<C: ClientCredentialsProvider> {
+    client_credentials_provider: C,
+}
+
+impl<C: ClientCredentialsProvider> ClientOnlyCredentialsProvider<C> {
+    pub fn new(client_credentials_provider: C) -> ClientOnlyCredentialsProvider<C> {
+        ClientOnlyCredentialsProvider { client_credentials_provider: client_credentials_provider }
+    }
+}
+
+impl<C: ClientCredentialsProvider> UserCredentialsProvider for ClientOnlyCredentialsProvider<C> {
+    fn get_user_credentials(&self) -> Result<Credentials, CredentialsError> {
+        Err(CredentialsError::NotAvailable {
+            message: String::from("Only client credentials are provided."),
+        })
+    }
+}
+
+impl<C: ClientCredentialsProvider> ClientCredentialsProvider for ClientOnlyCredentialsProvider<C> {
+    fn get_client_credentials(&self) -> Result<Credentials, CredentialsError> {
+        self.client_credentials_provider.get_client_credentials()
+    }
+}
+
+impl<C: ClientCredentialsProvider> CredentialsPairProvider for ClientOnlyCredentialsProvider<C> {
+    fn get_credentials_pair(&self) -> Result<CredentialsPair, CredentialsError> {
+        Ok(CredentialsPair::client_only(try!{self.get_client_credentials()}))
+    }
+}
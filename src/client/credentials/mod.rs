@@ -6,8 +6,10 @@ use std::fmt;
 
 mod static_provider;
 mod file_credentials_provider;
+mod client_only_provider;
 
 pub use self::static_provider::StaticCredentialsProvider;
+pub use self::client_only_provider::ClientOnlyCredentialsProvider;
 pub use self::file_credentials_provider::{FileCredentialsProvider, UserFileCredentialsProvider,
                                           ClientFileCredentialsProvider};
 
@@ -44,9 +46,20 @@ impl Credentials {
 /// * One for the user
 pub struct CredentialsPair {
     pub client_credentials: Credentials,
+    /// Empty if only the client authenticates, e.g. for the `client_credentials` grant.
     pub user_credentials: Credentials,
 }
 
+impl CredentialsPair {
+    /// A pair with empty user `Credentials` for clients that authenticate without a user.
+    pub fn client_only(client_credentials: Credentials) -> CredentialsPair {
+        CredentialsPair {
+            client_credentials: client_credentials,
+            user_credentials: Credentials::new("", ""),
+        }
+    }
+}
+
 /// A `CredentialsProvider` that provides client `Credentials`
 pub trait ClientCredentialsProvider {
     fn get_client_credentials(&self) -> CredentialsResult;
@@ -111,6 +124,10 @@ pub enum CredentialsError {
     DecodingError {
         message: String,
     },
+    /// The provider has no such `Credentials`
+    NotAvailable {
+        message: String,
+    },
 }
 
 impl From<io::Error> for CredentialsError {
@@ -126,6 +143,9 @@ impl fmt::Display for CredentialsError {
             CredentialsError::DecodingError { ref message } => {
                 write!(f, "Decoding error: {}", message)
             }
+            CredentialsError::NotAvailable { ref message } => {
+                write!(f, "Not available: {}", message)
+            }
         }
     }
 }
@@ -134,7 +154,8 @@ impl Error for CredentialsError {
     fn description(&self) -> &str {
         match *self {
             CredentialsError::IoError { ref message } |
-            CredentialsError::DecodingError { ref message } => message.as_ref(),
+            CredentialsError::DecodingError { ref message } |
+            CredentialsError::NotAvailable { ref message } => message.as_ref(),
         }
     }
 
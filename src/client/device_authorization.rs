@@ -0,0 +1,421 @@
+//! The device authorization grant of [RFC 8628](https://tools.ietf.org/html/rfc8628)
+//! for machines without a browser.
+//!
+//! The user opens the verification URI on another device and enters the user code
+//! while the flow polls the token endpoint.
+//!
+//! ```no_run
+//! # extern crate hyper;
+//! # extern crate rusty_tokens;
+//! use rusty_tokens::Scope;
+//! use rusty_tokens::client::{ManagedToken, SelfUpdatingTokenManager,
+//!                            SelfUpdatingTokenManagerConfig};
+//! use rusty_tokens::client::device_authorization::DeviceAuthorizationFlow;
+//!
+//! # fn main() {
+//! let flow = DeviceAuthorizationFlow::new(hyper::Client::new(),
+//!                                         "https://auth.example.org/device_authorization",
+//!                                         "https://auth.example.org/oauth2/access_token",
+//!                                         "my-cli")
+//!     .with_scope(Scope::new("uid"));
+//! let access_token = flow.authorize(|authorization| {
+//!         println!("Please open {} and enter {}",
+//!                  authorization.verification_uri,
+//!                  authorization.user_code)
+//!     })
+//!     .unwrap();
+//!
+//! // The manager renews the token with its refresh token.
+//! let managed_token = ManagedToken::new("my-token").with_access_token(access_token);
+//! let config = SelfUpdatingTokenManagerConfig::new(vec![managed_token], 0.8, 0.9);
+//! let (manager, _) = SelfUpdatingTokenManager::new_without_credentials(config, flow).unwrap();
+//! # }
+//! ```
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::thread;
+use std::time::{Duration, Instant};
+use hyper;
+use hyper::status::StatusCode;
+use rustc_serialize::json::{self, Json};
+use url::form_urlencoded;
+use Scope;
+use client::credentials::CredentialsPair;
+use client::implementation::{AccessToken, AccessTokenProvider, RequestAccessTokenError,
+                             RequestAccessTokenResult};
+use client::implementation::hypertokenmanager::{append_audiences, evaluate_response,
+                                                post_client_form, TokenFormat};
+
+/// The `grant_type` of the token requests
+pub const DEVICE_CODE_GRANT_TYPE: &'static str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// The polling interval if the provider does not send one
+const DEFAULT_INTERVAL_SECONDS: u64 = 5;
+
+/// The response of the device authorization endpoint
+#[derive(RustcDecodable, Debug, Clone, PartialEq)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    /// The code the user has to enter
+    pub user_code: String,
+    /// Where the user has to enter the `user_code`
+    pub verification_uri: String,
+    /// A URI that already contains the `user_code`, e.g. for a QR code
+    pub verification_uri_complete: Option<String>,
+    /// The lifetime of the codes in seconds
+    pub expires_in: u64,
+    /// The minimum seconds between two token requests
+    pub interval: Option<u64>,
+}
+
+/// Requests `AccessToken`s with the device authorization grant
+///
+/// As an `AccessTokenProvider` it can only renew `AccessToken`s with their refresh token,
+/// since a new device authorization needs the user.
+pub struct DeviceAuthorizationFlow {
+    client: hyper::Client,
+    device_authorization_url: String,
+    token_url: String,
+    client_id: String,
+    client_secret: Option<String>,
+    scopes: Vec<Scope>,
+    audiences: Vec<String>,
+}
+
+impl DeviceAuthorizationFlow {
+    /// Create a new instance for a public client, i.e. one without a secret
+    pub fn new<D, T, C>(client: hyper::Client,
+                        device_authorization_url: D,
+                        token_url: T,
+                        client_id: C)
+                        -> DeviceAuthorizationFlow
+        where D: Into<String>,
+              T: Into<String>,
+              C: Into<String>
+    {
+        DeviceAuthorizationFlow {
+            client: client,
+            device_authorization_url: device_authorization_url.into(),
+            token_url: token_url.into(),
+            client_id: client_id.into(),
+            client_secret: None,
+            scopes: Vec::new(),
+            audiences: Vec::new(),
+        }
+    }
+
+    /// Builder method. Authenticate the client with HTTP Basic authentication.
+    pub fn with_client_secret<T: Into<String>>(self, client_secret: T) -> Self {
+        let mut x = self;
+        x.client_secret = Some(client_secret.into());
+        x
+    }
+
+    /// Builder method. Add a `Scope`.
+    pub fn with_scope(self, scope: Scope) -> Self {
+        let mut x = self;
+        x.scopes.push(scope);
+        x
+    }
+
+    /// Builder method. Add an audience the `Token` shall be restricted to.
+    pub fn with_audience<T: Into<String>>(self, audience: T) -> Self {
+        let mut x = self;
+        x.audiences.push(audience.into());
+        x
+    }
+
+    /// Requests a device code and a user code.
+    pub fn request_device_authorization
+        (&self)
+         -> Result<DeviceAuthorization, DeviceAuthorizationError> {
+        let scopes: Vec<&str> = self.scopes.iter().map(|scope| scope.0.as_ref()).collect();
+        let mut form = form_urlencoded::Serializer::new(String::new());
+        if !scopes.is_empty() {
+            form.append_pair("scope", &scopes.join(" "));
+        }
+        append_audiences(&mut form, &self.audiences);
+        let mut response = try!{self.post(&self.device_authorization_url, form)};
+        let mut body = String::new();
+        try!{response.read_to_string(&mut body)
+            .map_err(|err| RequestAccessTokenError::IoError(format!("{}", err)))};
+        if response.status != StatusCode::Ok {
+            return Err(DeviceAuthorizationError::from(RequestAccessTokenError::RequestError {
+                status: response.status.to_u16(),
+                body: body,
+            }));
+        }
+        json::decode(&body).map_err(|err| {
+            DeviceAuthorizationError::from(RequestAccessTokenError::from(err))
+        })
+    }
+
+    /// Polls the token endpoint until the user completed the authorization.
+    ///
+    /// Waits `interval` seconds between the requests and 5 seconds longer after each
+    /// `slow_down`.
+    pub fn poll_token(&self,
+                      authorization: &DeviceAuthorization)
+                      -> Result<AccessToken, DeviceAuthorizationError> {
+        self.poll_token_with_sleep(authorization, thread::sleep)
+    }
+
+    /// Runs the whole flow.
+    ///
+    /// `show_user_code` is called with the device authorization to tell the user where to
+    /// enter the user code. Blocks until the user completed or denied the authorization.
+    pub fn authorize<F>(&self, show_user_code: F) -> Result<AccessToken, DeviceAuthorizationError>
+        where F: FnOnce(&DeviceAuthorization)
+    {
+        let authorization = try!{self.request_device_authorization()};
+        show_user_code(&authorization);
+        self.poll_token(&authorization)
+    }
+
+    fn poll_token_with_sleep<S>(&self,
+                                authorization: &DeviceAuthorization,
+                                mut sleep: S)
+                                -> Result<AccessToken, DeviceAuthorizationError>
+        where S: FnMut(Duration)
+    {
+        let deadline = Instant::now() + Duration::from_secs(authorization.expires_in);
+        let mut interval = authorization.interval.unwrap_or(DEFAULT_INTERVAL_SECONDS);
+        loop {
+            sleep(Duration::from_secs(interval));
+            if Instant::now() > deadline {
+                return Err(DeviceAuthorizationError::Expired);
+            }
+            let mut form = form_urlencoded::Serializer::new(String::new());
+            form.append_pair("grant_type", DEVICE_CODE_GRANT_TYPE)
+                .append_pair("device_code", &authorization.device_code);
+            let mut response = try!{self.post(&self.token_url, form)};
+            let err = match evaluate_response(&mut response, TokenFormat::PlanBOrOpaque) {
+                Ok(access_token) => return Ok(access_token),
+                Err(err) => err,
+            };
+            let body = match err {
+                RequestAccessTokenError::RequestError { status: 400, ref body } => body.clone(),
+                _ => return Err(DeviceAuthorizationError::RequestError(err)),
+            };
+            match error_code(&body).as_ref().map(|error| error.as_ref()) {
+                Some("authorization_pending") => (),
+                Some("slow_down") => {
+                    interval += 5;
+                    debug!("Slowing down polling to every {} seconds.", interval);
+                }
+                Some("access_denied") => return Err(DeviceAuthorizationError::Denied),
+                Some("expired_token") => return Err(DeviceAuthorizationError::Expired),
+                _ => return Err(DeviceAuthorizationError::RequestError(err)),
+            }
+        }
+    }
+
+    fn post(&self,
+            url: &str,
+            form: form_urlencoded::Serializer<String>)
+            -> Result<hyper::client::response::Response, RequestAccessTokenError> {
+        post_client_form(&self.client,
+                         url,
+                         &self.client_id,
+                         self.client_secret.as_ref().map(|s| s.as_ref()),
+                         form)
+    }
+}
+
+/// The `error` of an OAuth2 error response
+fn error_code(body: &str) -> Option<String> {
+    Json::from_str(body)
+        .ok()
+        .and_then(|json| json.find("error").and_then(|error| error.as_string()).map(String::from))
+}
+
+impl AccessTokenProvider for DeviceAuthorizationFlow {
+    fn get_access_token(&self,
+                        _scopes: &[Scope],
+                        _credentials: &CredentialsPair)
+                        -> RequestAccessTokenResult {
+        Err(RequestAccessTokenError::InvalidCredentials(String::from("A new device \
+                                                                      authorization needs the \
+                                                                      user.")))
+    }
+
+    fn get_access_token_for(&self,
+                            scopes: &[Scope],
+                            _audiences: &[String],
+                            credentials: &CredentialsPair)
+                            -> RequestAccessTokenResult {
+        self.get_access_token(scopes, credentials)
+    }
+
+    fn refresh_access_token(&self,
+                            refresh_token: &str,
+                            scopes: &[Scope],
+                            audiences: &[String],
+                            _credentials: &CredentialsPair)
+                            -> RequestAccessTokenResult {
+        let scopes: Vec<&str> = scopes.iter().map(|scope| scope.0.as_ref()).collect();
+        let mut form = form_urlencoded::Serializer::new(String::new());
+        form.append_pair("grant_type", "refresh_token")
+            .append_pair("refresh_token", refresh_token);
+        if !scopes.is_empty() {
+            form.append_pair("scope", &scopes.join(" "));
+        }
+        append_audiences(&mut form, audiences);
+        let mut response = try!{self.post(&self.token_url, form)};
+        evaluate_response(&mut response, TokenFormat::PlanBOrOpaque)
+    }
+}
+
+/// Errors of the device authorization flow
+#[derive(Debug, Clone)]
+pub enum DeviceAuthorizationError {
+    /// The user denied the authorization
+    Denied,
+    /// The user did not complete the authorization in time
+    Expired,
+    /// A request to the provider failed
+    RequestError(RequestAccessTokenError),
+}
+
+impl fmt::Display for DeviceAuthorizationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeviceAuthorizationError::Denied => write!(f, "The authorization was denied"),
+            DeviceAuthorizationError::Expired => write!(f, "The device code expired"),
+            DeviceAuthorizationError::RequestError(ref err) => {
+                write!(f, "A request failed: {}", err)
+            }
+        }
+    }
+}
+
+impl Error for DeviceAuthorizationError {
+    fn description(&self) -> &str {
+        match *self {
+            DeviceAuthorizationError::Denied => "The authorization was denied",
+            DeviceAuthorizationError::Expired => "The device code expired",
+            DeviceAuthorizationError::RequestError(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            DeviceAuthorizationError::RequestError(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<RequestAccessTokenError> for DeviceAuthorizationError {
+    fn from(err: RequestAccessTokenError) -> Self {
+        DeviceAuthorizationError::RequestError(err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use hyper;
+    use {Scope, Token};
+    use client::credentials::{Credentials, CredentialsPair};
+    use client::implementation::AccessTokenProvider;
+    use fake_provider::fake_provider;
+    use super::{DeviceAuthorizationFlow, DeviceAuthorization, DeviceAuthorizationError};
+
+    fn flow(url: &str) -> DeviceAuthorizationFlow {
+        DeviceAuthorizationFlow::new(hyper::Client::new(),
+                                     format!("{}/device_authorization", url),
+                                     format!("{}/token", url),
+                                     "my-cli")
+    }
+
+    fn authorization(interval: Option<u64>) -> DeviceAuthorization {
+        DeviceAuthorization {
+            device_code: String::from("the-device-code"),
+            user_code: String::from("WDJB-MJHT"),
+            verification_uri: String::from("https://auth.example.org/device"),
+            verification_uri_complete: None,
+            expires_in: 1800,
+            interval: interval,
+        }
+    }
+
+    #[test]
+    fn the_device_authorization_must_be_decoded() {
+        let (url, bodies) = fake_provider(vec![(200,
+                                                "{\"device_code\":\"the-device-code\",\
+                                                 \"user_code\":\"WDJB-MJHT\",\
+                                                 \"verification_uri\":\
+                                                 \"https://auth.example.org/device\",\
+                                                 \"expires_in\":1800}")]);
+
+        let received = flow(&url)
+            .with_scope(Scope::new("uid"))
+            .request_device_authorization()
+            .unwrap();
+
+        assert_eq!(authorization(None), received);
+        assert_eq!("scope=uid&client_id=my-cli", bodies.recv().unwrap());
+    }
+
+    #[test]
+    fn polling_must_honor_the_interval_and_slow_down() {
+        let (url, bodies) = fake_provider(vec![(400, "{\"error\":\"authorization_pending\"}"),
+                                               (400, "{\"error\":\"slow_down\"}"),
+                                               (400, "{\"error\":\"authorization_pending\"}"),
+                                               (200,
+                                                "{\"access_token\":\"opaque-token\",\
+                                                 \"expires_in\":3600,\
+                                                 \"refresh_token\":\"rt-1\"}")]);
+        let mut sleeps = Vec::new();
+
+        let access_token = flow(&url)
+            .poll_token_with_sleep(&authorization(Some(2)), |duration| sleeps.push(duration))
+            .unwrap();
+
+        assert_eq!(Token::new("opaque-token"), access_token.token);
+        assert_eq!(Some(String::from("rt-1")), access_token.refresh_token);
+        assert_eq!(vec![Duration::from_secs(2),
+                        Duration::from_secs(2),
+                        Duration::from_secs(7),
+                        Duration::from_secs(7)],
+                   sleeps);
+        assert_eq!("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Adevice_code&\
+                    device_code=the-device-code&client_id=my-cli",
+                   bodies.recv().unwrap());
+    }
+
+    #[test]
+    fn polling_must_stop_when_the_user_denies() {
+        let (url, _) = fake_provider(vec![(400, "{\"error\":\"access_denied\"}")]);
+
+        match flow(&url).poll_token_with_sleep(&authorization(None), |_| ()) {
+            Err(DeviceAuthorizationError::Denied) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn polling_must_stop_on_other_errors() {
+        let (url, _) = fake_provider(vec![(400, "{\"error\":\"invalid_client\"}")]);
+
+        match flow(&url).poll_token_with_sleep(&authorization(None), |_| ()) {
+            Err(DeviceAuthorizationError::RequestError(_)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn no_scope_must_be_requested_without_scopes() {
+        let (url, bodies) = fake_provider(vec![(200,
+                                                "{\"access_token\":\"opaque-token\",\
+                                                 \"expires_in\":3600}")]);
+        let credentials = CredentialsPair::client_only(Credentials::new("", ""));
+
+        flow(&url).refresh_access_token("rt-1", &[], &[], &credentials).unwrap();
+
+        assert_eq!("grant_type=refresh_token&refresh_token=rt-1&client_id=my-cli",
+                   bodies.recv().unwrap());
+    }
+}
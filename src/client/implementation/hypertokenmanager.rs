@@ -5,15 +5,17 @@ use std::str::FromStr;
 use std::convert::Into;
 use std::thread;
 use std::time::Duration;
-use url::form_urlencoded;
+use url::{form_urlencoded, Url};
 use hyper;
 use hyper::header::{Headers, Authorization, Basic, ContentType};
 use hyper::client::response::Response;
 use hyper::status::StatusCode;
 use rustc_serialize::json;
+use chrono::{Duration as ChronoDuration, UTC};
 use jwt::planb::PlanbToken;
 use {InitializationError, Scope, Token};
-use client::credentials::{CredentialsPair, CredentialsPairProvider, FileCredentialsProvider};
+use client::credentials::{CredentialsPair, CredentialsPairProvider, ClientCredentialsProvider,
+                          ClientOnlyCredentialsProvider, FileCredentialsProvider};
 use client::ManagedToken;
 use super::*;
 
@@ -32,7 +34,26 @@ impl HyperTokenManager {
         where U: CredentialsPairProvider + Send + 'static
     {
         let acccess_token_provider =
-            HyperAccessTokenProvider::new(http_client, format!("{}?realm={}", url, realm));
+            HyperAccessTokenProvider::new(http_client,
+                                          format!("{}?realm={}", url, realm),
+                                          GrantType::Password);
+        SelfUpdatingTokenManager::new(config, credentials_provider, acccess_token_provider)
+    }
+
+    /// Creates a new instance that requests `Token`s with the `client_credentials` grant
+    /// from a standard OAuth2 token endpoint. Only client `Credentials` are needed.
+    #[must_use]
+    pub fn new_with_client_credentials<C>
+        (config: SelfUpdatingTokenManagerConfig,
+         http_client: hyper::Client,
+         client_credentials_provider: C,
+         url: &str)
+         -> Result<(SelfUpdatingTokenManager, JoinHandle<()>), InitializationError>
+        where C: ClientCredentialsProvider + Send + 'static
+    {
+        let credentials_provider = ClientOnlyCredentialsProvider::new(client_credentials_provider);
+        let acccess_token_provider =
+            HyperAccessTokenProvider::new(http_client, url, GrantType::ClientCredentials);
         SelfUpdatingTokenManager::new(config, credentials_provider, acccess_token_provider)
     }
 
@@ -91,39 +112,86 @@ impl HyperTokenManager {
     }
 }
 
+/// The OAuth2 grant used to request `Token`s
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrantType {
+    /// The resource owner password grant of the Plan B services realm.
+    ///
+    /// The user `Credentials` are sent as HTTP Basic authentication and the client
+    /// `Credentials` as `username` and `password` in the form body.
+    Password,
+    /// The `client_credentials` grant of RFC 6749.
+    ///
+    /// The client `Credentials` are sent as HTTP Basic authentication.
+    /// No user `Credentials` are needed.
+    ClientCredentials,
+}
+
+impl GrantType {
+    /// The Plan B services realm only issues Plan B tokens.
+    fn token_format(&self) -> TokenFormat {
+        match *self {
+            GrantType::Password => TokenFormat::PlanB,
+            GrantType::ClientCredentials => TokenFormat::PlanBOrOpaque,
+        }
+    }
+}
+
+/// The tokens `evaluate_response` accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenFormat {
+    /// Only Plan B tokens. Their lifetime is read from the token.
+    PlanB,
+    /// Plan B tokens or opaque tokens. The lifetime of an opaque token is its `expires_in`.
+    PlanBOrOpaque,
+}
+
 struct HyperAccessTokenProvider {
     client: hyper::Client,
     full_url_with_realm: String,
+    grant_type: GrantType,
 }
 
 #[derive(RustcDecodable, Debug)]
 struct PlanBAccessTokenResponse {
     access_token: String,
-    expires_in: u64,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
 }
 
 impl HyperAccessTokenProvider {
     pub fn new<T: Into<String>>(client: hyper::Client,
-                                full_url_with_realm: T)
+                                full_url_with_realm: T,
+                                grant_type: GrantType)
                                 -> HyperAccessTokenProvider {
         HyperAccessTokenProvider {
             client: client,
             full_url_with_realm: full_url_with_realm.into(),
+            grant_type: grant_type,
         }
     }
 
     fn request_access_token(&self,
+                            refresh_token: Option<&str>,
                             scopes: &[Scope],
+                            audiences: &[String],
                             credentials: &CredentialsPair)
                             -> RequestAccessTokenResult {
-        let mut response =
-            try!{self.execute_http_request_with_multiple_attempts(scopes, credentials, 3, None)};
-        evaluate_response(&mut response)
+        let (authorization, form_encoded) = try!{build_token_request(self.grant_type,
+                                                                     refresh_token,
+                                                                     scopes,
+                                                                     audiences,
+                                                                     credentials)};
+        let mut response = try!{self.execute_http_request_with_multiple_attempts(&authorization,
+                                                                             &form_encoded,
+                                                                             3,
+                                                                             None)};
+        evaluate_response(&mut response, self.grant_type.token_format())
     }
 
     fn execute_http_request_with_multiple_attempts(&self,
-                                                   scopes: &[Scope],
-                                                   credentials: &CredentialsPair,
+                                                   authorization: &Basic,
+                                                   form_encoded: &str,
                                                    attempts: u16,
                                                    last_error: Option<RequestAccessTokenError>)
                                                    -> Result<Response, RequestAccessTokenError> {
@@ -136,14 +204,14 @@ impl HyperAccessTokenProvider {
                 }
             }
         } else {
-            let result = self.execute_http_request(scopes, credentials);
+            let result = self.execute_http_request(authorization, form_encoded);
             match result {
                 Ok(res) => Ok(res),
                 Err(err) => {
                     warn!("Failed to request access token(connection error): {}", err);
                     thread::sleep(Duration::from_millis(30));
-                    self.execute_http_request_with_multiple_attempts(scopes,
-                                                                     credentials,
+                    self.execute_http_request_with_multiple_attempts(authorization,
+                                                                     form_encoded,
                                                                      attempts - 1,
                                                                      Some(RequestAccessTokenError::ConnectionError(format!("{}", err))))
                 }
@@ -152,31 +220,17 @@ impl HyperAccessTokenProvider {
     }
 
     fn execute_http_request(&self,
-                            scopes: &[Scope],
-                            credentials: &CredentialsPair)
+                            authorization: &Basic,
+                            form_encoded: &str)
                             -> hyper::error::Result<Response> {
-
         let mut headers = Headers::new();
-        let mut scope_vec = Vec::new();
-        for scope in scopes {
-            scope_vec.push(scope.0.clone());
-        }
-        headers.set(Authorization(Basic {
-            username: credentials.user_credentials.id.clone(),
-            password: Some(credentials.user_credentials.secret.clone()),
-        }));
+        headers.set(Authorization(authorization.clone()));
         headers.set(ContentType::form_url_encoded());
-        let form_encoded = form_urlencoded::Serializer::new(String::new())
-            .append_pair("grant_type", "password")
-            .append_pair("username", &credentials.client_credentials.id)
-            .append_pair("password", &credentials.client_credentials.secret)
-            .append_pair("scope", &scope_vec.join(" "))
-            .finish();
 
         self.client
             .post(&self.full_url_with_realm)
             .headers(headers)
-            .body(&form_encoded)
+            .body(form_encoded)
             .send()
     }
 }
@@ -186,24 +240,162 @@ impl AccessTokenProvider for HyperAccessTokenProvider {
                         scopes: &[Scope],
                         credentials: &CredentialsPair)
                         -> RequestAccessTokenResult {
-        self.request_access_token(scopes, credentials)
+        self.request_access_token(None, scopes, &[], credentials)
+    }
+
+    fn get_access_token_for(&self,
+                            scopes: &[Scope],
+                            audiences: &[String],
+                            credentials: &CredentialsPair)
+                            -> RequestAccessTokenResult {
+        self.request_access_token(None, scopes, audiences, credentials)
+    }
+
+    fn refresh_access_token(&self,
+                            refresh_token: &str,
+                            scopes: &[Scope],
+                            audiences: &[String],
+                            credentials: &CredentialsPair)
+                            -> RequestAccessTokenResult {
+        self.request_access_token(Some(refresh_token), scopes, audiences, credentials)
     }
 }
 
-fn evaluate_response(response: &mut Response) -> RequestAccessTokenResult {
+/// Builds the Basic authentication and the form body of a token request.
+///
+/// With a `refresh_token` the `refresh_token` grant is used instead of `grant_type`.
+/// The client authenticates the same way for both.
+fn build_token_request(grant_type: GrantType,
+                       refresh_token: Option<&str>,
+                       scopes: &[Scope],
+                       audiences: &[String],
+                       credentials: &CredentialsPair)
+                       -> Result<(Basic, String), RequestAccessTokenError> {
+    let authenticated = match grant_type {
+        GrantType::Password if credentials.user_credentials.id.is_empty() => {
+            return Err(RequestAccessTokenError::InvalidCredentials(String::from("The password \
+                                                                                 grant needs \
+                                                                                 user \
+                                                                                 credentials.")))
+        }
+        GrantType::Password => &credentials.user_credentials,
+        GrantType::ClientCredentials => &credentials.client_credentials,
+    };
+    let scope_vec: Vec<&str> = scopes.iter().map(|scope| scope.0.as_ref()).collect();
+    let mut form = form_urlencoded::Serializer::new(String::new());
+    match (refresh_token, grant_type) {
+        (Some(refresh_token), _) => {
+            form.append_pair("grant_type", "refresh_token")
+                .append_pair("refresh_token", refresh_token);
+        }
+        (None, GrantType::Password) => {
+            form.append_pair("grant_type", "password")
+                .append_pair("username", &credentials.client_credentials.id)
+                .append_pair("password", &credentials.client_credentials.secret);
+        }
+        (None, GrantType::ClientCredentials) => {
+            form.append_pair("grant_type", "client_credentials");
+        }
+    }
+    if !scope_vec.is_empty() {
+        form.append_pair("scope", &scope_vec.join(" "));
+    }
+    append_audiences(&mut form, audiences);
+    let authorization = Basic {
+        username: authenticated.id.clone(),
+        password: Some(authenticated.secret.clone()),
+    };
+    Ok((authorization, form.finish()))
+}
+
+/// Absolute URIs become `resource` parameters as defined by RFC 8707.
+/// Any other audience is sent as an `audience` parameter.
+pub fn append_audiences<T>(form: &mut form_urlencoded::Serializer<T>, audiences: &[String])
+    where T: form_urlencoded::Target
+{
+    for audience in audiences {
+        if Url::parse(audience).is_ok() {
+            form.append_pair("resource", audience);
+        } else {
+            form.append_pair("audience", audience);
+        }
+    }
+}
+
+/// Posts a form to an endpoint of the provider on behalf of a client.
+///
+/// A client with a secret authenticates with HTTP Basic authentication. A public client
+/// only identifies itself with the `client_id` parameter.
+pub fn post_client_form(client: &hyper::Client,
+                        url: &str,
+                        client_id: &str,
+                        client_secret: Option<&str>,
+                        form: form_urlencoded::Serializer<String>)
+                        -> Result<Response, RequestAccessTokenError> {
+    let mut form = form;
+    let mut headers = Headers::new();
+    headers.set(ContentType::form_url_encoded());
+    match client_secret {
+        Some(client_secret) => {
+            headers.set(Authorization(Basic {
+                username: client_id.to_owned(),
+                password: Some(client_secret.to_owned()),
+            }))
+        }
+        None => {
+            form.append_pair("client_id", client_id);
+        }
+    }
+    let form_encoded = form.finish();
+
+    client.post(url)
+        .headers(headers)
+        .body(&form_encoded)
+        .send()
+        .map_err(|err| RequestAccessTokenError::ConnectionError(format!("{}", err)))
+}
+
+/// Standard OAuth2 providers may issue opaque tokens. If `token_format` allows them
+/// the lifetime is taken from `expires_in` if the token is not a Plan B token. `expires_in`
+/// is optional for Plan B tokens since they carry their own lifetime.
+pub fn evaluate_response(response: &mut Response,
+                         token_format: TokenFormat)
+                         -> RequestAccessTokenResult {
     match response.status {
         StatusCode::Ok => {
             let mut buf = String::new();
             let _ = try!{response.read_to_string(&mut buf)};
             let decoded_response = try!{json::decode::<PlanBAccessTokenResponse>(&buf)};
-            debug!("Received a token that expires in {} seconds",
-                   decoded_response.expires_in);
-            let planb_token = try!{PlanbToken::from_str(&decoded_response.access_token).map_err(|err|
-                RequestAccessTokenError::ParsingError(format!("Failed to parse response as a Plan B token: {}", err)))};
+            let (issued_at_utc, valid_until_utc) =
+                match PlanbToken::from_str(&decoded_response.access_token) {
+                    Ok(planb_token) => {
+                        (planb_token.payload.issue_date_utc,
+                         planb_token.payload.expiration_date_utc)
+                    }
+                    Err(_) if token_format == TokenFormat::PlanBOrOpaque => {
+                        let expires_in = try!{decoded_response.expires_in.ok_or_else(|| {
+                            RequestAccessTokenError::ParsingError(String::from("The token is \
+                                                                                opaque and \
+                                                                                'expires_in' \
+                                                                                is missing."))
+                        })};
+                        let now = UTC::now().naive_utc();
+                        (now, now + ChronoDuration::seconds(expires_in as i64))
+                    }
+                    Err(err) => {
+                        return Err(RequestAccessTokenError::ParsingError(format!("Failed to \
+                                                                                  parse response \
+                                                                                  as a Plan B \
+                                                                                  token: {}",
+                                                                                 err)))
+                    }
+                };
+            debug!("Received a token that expires at {}", valid_until_utc);
             Ok(AccessToken {
                 token: Token(decoded_response.access_token),
-                issued_at_utc: planb_token.payload.issue_date_utc,
-                valid_until_utc: planb_token.payload.expiration_date_utc,
+                issued_at_utc: issued_at_utc,
+                valid_until_utc: valid_until_utc,
+                refresh_token: decoded_response.refresh_token,
             })
         }
         StatusCode::Unauthorized => {
@@ -252,3 +444,137 @@ fn get_token_provider_url_from_env() -> Result<String, InitializationError> {
 
     }
 }
+
+#[cfg(test)]
+mod test {
+    use url::form_urlencoded;
+    use hyper;
+    use hyper::header::Basic;
+    use Scope;
+    use client::credentials::{Credentials, CredentialsPair};
+    use client::implementation::{RequestAccessTokenError, RequestAccessTokenResult};
+    use fake_provider::fake_provider;
+    use testing::PlanbTokenBuilder;
+    use super::{append_audiences, build_token_request, evaluate_response, GrantType,
+                TokenFormat};
+
+    fn credentials(user_credentials: Option<Credentials>) -> CredentialsPair {
+        let client_credentials = Credentials::new("client-id", "client-secret");
+        match user_credentials {
+            Some(user_credentials) => {
+                CredentialsPair {
+                    client_credentials: client_credentials,
+                    user_credentials: user_credentials,
+                }
+            }
+            None => CredentialsPair::client_only(client_credentials),
+        }
+    }
+
+    fn basic(username: &str, password: &str) -> Basic {
+        Basic {
+            username: String::from(username),
+            password: Some(String::from(password)),
+        }
+    }
+
+    #[test]
+    fn the_password_grant_must_authenticate_the_user() {
+        let pair = credentials(Some(Credentials::new("user-id", "user-secret")));
+
+        let (authorization, form) =
+            build_token_request(GrantType::Password, None, &[Scope::new("uid")], &[], &pair)
+                .unwrap();
+
+        assert_eq!(basic("user-id", "user-secret"), authorization);
+        assert_eq!("grant_type=password&username=client-id&password=client-secret&scope=uid",
+                   form);
+    }
+
+    #[test]
+    fn the_password_grant_must_fail_without_user_credentials() {
+        match build_token_request(GrantType::Password, None, &[], &[], &credentials(None)) {
+            Err(RequestAccessTokenError::InvalidCredentials(_)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_client_credentials_grant_must_authenticate_the_client() {
+        let scopes = [Scope::new("uid"), Scope::new("cn")];
+
+        let (authorization, form) = build_token_request(GrantType::ClientCredentials,
+                                                        None,
+                                                        &scopes,
+                                                        &[String::from("my-service")],
+                                                        &credentials(None))
+            .unwrap();
+
+        assert_eq!(basic("client-id", "client-secret"), authorization);
+        assert_eq!("grant_type=client_credentials&scope=uid+cn&audience=my-service", form);
+    }
+
+    #[test]
+    fn a_refresh_must_authenticate_like_the_primary_grant() {
+        let pair = credentials(Some(Credentials::new("user-id", "user-secret")));
+
+        let (authorization, form) =
+            build_token_request(GrantType::Password, Some("rt-1"), &[Scope::new("uid")], &[], &pair)
+                .unwrap();
+
+        assert_eq!(basic("user-id", "user-secret"), authorization);
+        assert_eq!("grant_type=refresh_token&refresh_token=rt-1&scope=uid", form);
+    }
+
+    #[test]
+    fn no_scope_must_be_requested_without_scopes() {
+        let (_, form) =
+            build_token_request(GrantType::ClientCredentials, None, &[], &[], &credentials(None))
+                .unwrap();
+
+        assert_eq!("grant_type=client_credentials", form);
+    }
+
+    fn evaluate(json: String, token_format: TokenFormat) -> RequestAccessTokenResult {
+        let (url, _) = fake_provider(vec![(200, json)]);
+        let mut response = hyper::Client::new().post(&url).body("").send().unwrap();
+        evaluate_response(&mut response, token_format)
+    }
+
+    #[test]
+    fn expires_in_must_be_optional_for_plan_b_tokens() {
+        let minted = PlanbTokenBuilder::new().build().unwrap();
+
+        let access_token = evaluate(format!("{{\"access_token\":\"{}\"}}", minted.token.0),
+                                    TokenFormat::PlanB)
+            .unwrap();
+
+        assert_eq!(minted.planb_token.payload.expiration_date_utc,
+                   access_token.valid_until_utc);
+    }
+
+    #[test]
+    fn expires_in_must_be_required_for_opaque_tokens() {
+        match evaluate(String::from("{\"access_token\":\"opaque-token\"}"),
+                       TokenFormat::PlanBOrOpaque) {
+            Err(RequestAccessTokenError::ParsingError(_)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        let access_token = evaluate(String::from("{\"access_token\":\"opaque-token\",\
+                                                  \"expires_in\":60}"),
+                                    TokenFormat::PlanBOrOpaque)
+            .unwrap();
+        assert_eq!(60,
+                   (access_token.valid_until_utc - access_token.issued_at_utc).num_seconds());
+    }
+
+    #[test]
+    fn absolute_uris_must_be_requested_as_resource_indicators() {
+        let mut form = form_urlencoded::Serializer::new(String::new());
+        append_audiences(&mut form,
+                         &[String::from("https://api.example.org/"), String::from("my-service")]);
+
+        assert_eq!("resource=https%3A%2F%2Fapi.example.org%2F&audience=my-service",
+                   form.finish());
+    }
+}
@@ -20,6 +20,9 @@ struct TokenData<'a> {
     valid_until: i64,
     warn_after: i64,
     scopes: &'a Vec<Scope>,
+    audiences: &'a Vec<String>,
+    /// The `refresh_token` of the last response, if the provider issued one
+    refresh_token: Option<String>,
 }
 
 pub fn start_manager<T, U>(manager_state: Arc<RwLock<HashMap<String, TokenResult>>>,
@@ -35,7 +38,11 @@ pub fn start_manager<T, U>(manager_state: Arc<RwLock<HashMap<String, TokenResult
 
     let join_handle = thread::spawn(move || {
         let mut managed_token_data = Vec::new();
-        initialize(&mut managed_token_data, &conf.managed_tokens);
+        initialize(&mut managed_token_data,
+                   &conf.managed_tokens,
+                   conf.refresh_percentage_threshold,
+                   conf.warning_percentage_threshold);
+        publish_initial_tokens(&manager_state, &managed_token_data);
 
         manager_loop(manager_state,
                      managed_token_data,
@@ -48,17 +55,40 @@ pub fn start_manager<T, U>(manager_state: Arc<RwLock<HashMap<String, TokenResult
     Ok(join_handle)
 }
 
-fn initialize<'a>(token_data_buffer: &mut Vec<TokenData<'a>>, managed_tokens: &'a [ManagedToken]) {
+fn initialize<'a>(token_data_buffer: &mut Vec<TokenData<'a>>,
+                  managed_tokens: &'a [ManagedToken],
+                  refresh_percentage_threshold: f32,
+                  warning_percentage_threshold: f32) {
     let t = UTC::now().timestamp();
     for managed_token in managed_tokens {
-        token_data_buffer.push(TokenData {
+        let mut token_data = TokenData {
             token_name: &managed_token.name,
             token: None,
             update_latest: t,
             warn_after: t,
             valid_until: t,
             scopes: &managed_token.scopes,
-        });
+            audiences: &managed_token.audiences,
+            refresh_token: None,
+        };
+        if let Some(ref access_token) = managed_token.initial_access_token {
+            update_token_data_with_access_token(t,
+                                                &mut token_data,
+                                                access_token.clone(),
+                                                refresh_percentage_threshold,
+                                                warning_percentage_threshold);
+        }
+        token_data_buffer.push(token_data);
+    }
+}
+
+fn publish_initial_tokens(manager_state: &RwLock<HashMap<String, TokenResult>>,
+                          managed_token_data: &[TokenData]) {
+    let mut unlocked_manager_state = manager_state.write().unwrap();
+    for token_data in managed_token_data {
+        if let Some(ref token) = token_data.token {
+            unlocked_manager_state.insert(token_data.token_name.to_string(), Ok(token.clone()));
+        }
     }
 }
 
@@ -189,8 +219,39 @@ fn update_token_data<T>(token_data: &mut TokenData,
                         -> Result<DateTime<UTC>, RequestAccessTokenError>
     where T: AccessTokenProvider
 {
-    let access_token =
-        try!{access_token_provider.get_access_token(&token_data.scopes, credentials)};
+    let refreshed = match token_data.refresh_token.take() {
+        Some(refresh_token) => {
+            match access_token_provider.refresh_access_token(&refresh_token,
+                                                             &token_data.scopes,
+                                                             &token_data.audiences,
+                                                             credentials) {
+                Ok(access_token) => {
+                    token_data.refresh_token = Some(refresh_token);
+                    Some(access_token)
+                }
+                Err(ref err) if is_rejected(err) => {
+                    warn!("The refresh token of '{}' was rejected. Falling back to the primary \
+                           grant: {}",
+                          token_data.token_name,
+                          err);
+                    None
+                }
+                Err(err) => {
+                    token_data.refresh_token = Some(refresh_token);
+                    return Err(err);
+                }
+            }
+        }
+        None => None,
+    };
+    let access_token = match refreshed {
+        Some(access_token) => access_token,
+        None => {
+            try!{access_token_provider.get_access_token_for(&token_data.scopes,
+                                                            &token_data.audiences,
+                                                            credentials)}
+        }
+    };
 
     let now_utc = UTC::now();
     let now_utc_epoch: i64 = now_utc.timestamp();
@@ -203,6 +264,17 @@ fn update_token_data<T>(token_data: &mut TokenData,
     Ok(now_utc)
 }
 
+/// Whether the provider answered that the refresh token can not be used anymore, e.g. with
+/// `invalid_grant`. Other errors may be transient, so the refresh token is kept.
+fn is_rejected(err: &RequestAccessTokenError) -> bool {
+    match *err {
+        RequestAccessTokenError::RequestError { status: 400, .. } |
+        RequestAccessTokenError::RequestError { status: 401, .. } |
+        RequestAccessTokenError::InvalidCredentials(_) => true,
+        _ => false,
+    }
+}
+
 fn update_token_data_with_access_token(now_utc: i64,
                                        token_data: &mut TokenData,
                                        access_token: AccessToken,
@@ -215,6 +287,9 @@ fn update_token_data_with_access_token(now_utc: i64,
     token_data.warn_after = warn_after;
     token_data.valid_until = valid_until_utc;
     token_data.token = Some(access_token.token);
+    if access_token.refresh_token.is_some() {
+        token_data.refresh_token = access_token.refresh_token;
+    }
     debug!("Updated token data for '{}'. Valid until: {}, Update latest: {}, Warn after: {}",
            &token_data.token_name,
            valid_until_utc,
@@ -1,8 +1,10 @@
-use chrono::NaiveDateTime;
+use chrono::{Duration as CDuration, NaiveDateTime, UTC};
 use std::time::Duration;
 use {Scope, Token};
 use client::implementation::AccessToken;
-use super::{scale_time, update_token_data_with_access_token, TokenData, calc_sleep_duration};
+use client::ManagedToken;
+use super::{scale_time, update_token_data_with_access_token, TokenData, calc_sleep_duration,
+            initialize};
 
 #[test]
 fn calc_sleep_duration_when_next_update_is_overdue() {
@@ -45,6 +47,8 @@ fn update_token_data_with_access_token_must_create_the_correct_result() {
 
     let scopes = vec![Scope(String::from("sc"))];
 
+    let audiences = Vec::new();
+
     let mut sample_token_data = TokenData {
         token_name: "token_data",
         token: None,
@@ -52,12 +56,15 @@ fn update_token_data_with_access_token_must_create_the_correct_result() {
         valid_until: -2,
         warn_after: -3,
         scopes: &scopes,
+        audiences: &audiences,
+        refresh_token: None,
     };
 
     let sample_access_token = AccessToken {
         token: Token::new("token"),
         issued_at_utc: NaiveDateTime::from_timestamp(50, 0),
         valid_until_utc: NaiveDateTime::from_timestamp(200, 0),
+        refresh_token: None,
     };
 
     let expected = TokenData {
@@ -67,6 +74,8 @@ fn update_token_data_with_access_token_must_create_the_correct_result() {
         valid_until: 200,
         warn_after: 180,
         scopes: &scopes,
+        audiences: &audiences,
+        refresh_token: None,
     };
 
     update_token_data_with_access_token(now,
@@ -78,6 +87,27 @@ fn update_token_data_with_access_token_must_create_the_correct_result() {
     assert_eq!(expected, sample_token_data);
 }
 
+#[test]
+fn initialize_must_start_with_the_initial_access_token() {
+    let now = UTC::now().naive_utc();
+    let managed_tokens = vec![ManagedToken::new("seeded").with_access_token(AccessToken {
+                                  token: Token::new("token"),
+                                  issued_at_utc: now,
+                                  valid_until_utc: now + CDuration::seconds(3600),
+                                  refresh_token: Some(String::from("rt-1")),
+                              }),
+                              ManagedToken::new("requested")];
+    let mut token_data = Vec::new();
+
+    initialize(&mut token_data, &managed_tokens, 0.5f32, 1.0f32);
+
+    assert_eq!(Some(Token::new("token")), token_data[0].token);
+    assert_eq!(Some(String::from("rt-1")), token_data[0].refresh_token);
+    assert!(token_data[0].update_latest > now.timestamp() + 1700);
+    assert_eq!(None, token_data[1].token);
+    assert!(token_data[1].update_latest <= UTC::now().timestamp());
+}
+
 #[test]
 fn scale_time_0_percent() {
     let now = 100;
@@ -3,7 +3,7 @@ extern crate env_logger;
 use std::collections::HashMap;
 use std::thread;
 use std::sync::{Arc, RwLock};
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::time::Duration as TDuration;
 use chrono::*;
 use {Scope, Token};
@@ -36,6 +36,8 @@ fn update_token_data_should_update_the_token() {
 
     let scopes = vec![Scope::new("sc")];
 
+    let audiences = Vec::new();
+
 
 
     let mut sample_token_data = TokenData {
@@ -45,12 +47,15 @@ fn update_token_data_should_update_the_token() {
         valid_until: -2,
         warn_after: -3,
         scopes: &scopes,
+        audiences: &audiences,
+        refresh_token: None,
     };
 
     let sample_access_token = AccessToken {
         token: Token::new("token"),
         issued_at_utc: now.naive_utc() - Duration::seconds(60),
         valid_until_utc: now.naive_utc() + Duration::seconds(60),
+        refresh_token: None,
     };
 
     let provider = AccessTokenProviderMock { result: Ok(sample_access_token) };
@@ -81,6 +86,8 @@ fn update_token_data_should_update_the_token() {
         valid_until: (now.naive_utc() + Duration::seconds(60)).timestamp(),
         warn_after: (used_timestamp.naive_utc() + Duration::seconds(60)).timestamp(),
         scopes: &scopes,
+        audiences: &audiences,
+        refresh_token: None,
     };
 
 
@@ -94,6 +101,8 @@ fn update_token_data_should_not_update_the_token_when_the_acess_token_provider_f
 
     let scopes = vec![Scope::new("sc")];
 
+    let audiences = Vec::new();
+
     let mut sample_token_data = TokenData {
         token_name: "token_data",
         token: None,
@@ -101,6 +110,8 @@ fn update_token_data_should_not_update_the_token_when_the_acess_token_provider_f
         valid_until: -2,
         warn_after: -3,
         scopes: &scopes,
+        audiences: &audiences,
+        refresh_token: None,
     };
 
     let provider = AccessTokenProviderMock {
@@ -129,6 +140,141 @@ fn update_token_data_should_not_update_the_token_when_the_acess_token_provider_f
     assert_eq!(result.is_err(), true);
 }
 
+struct RefreshingAccessTokenProviderMock {
+    primary_result: RequestAccessTokenResult,
+    refresh_result: RequestAccessTokenResult,
+    used_refresh_tokens: RefCell<Vec<String>>,
+}
+
+impl AccessTokenProvider for RefreshingAccessTokenProviderMock {
+    fn get_access_token(&self,
+                        _scopes: &[Scope],
+                        _credentials: &CredentialsPair)
+                        -> RequestAccessTokenResult {
+        self.primary_result.clone()
+    }
+
+    fn refresh_access_token(&self,
+                            refresh_token: &str,
+                            _scopes: &[Scope],
+                            _audiences: &[String],
+                            _credentials: &CredentialsPair)
+                            -> RequestAccessTokenResult {
+        self.used_refresh_tokens.borrow_mut().push(String::from(refresh_token));
+        self.refresh_result.clone()
+    }
+}
+
+fn access_token(token: &str, refresh_token: Option<&str>) -> AccessToken {
+    let now = UTC::now().naive_utc();
+    AccessToken {
+        token: Token::new(token),
+        issued_at_utc: now,
+        valid_until_utc: now + Duration::seconds(60),
+        refresh_token: refresh_token.map(String::from),
+    }
+}
+
+#[test]
+fn update_token_data_should_use_and_keep_the_refresh_token() {
+    let scopes = vec![Scope::new("sc")];
+    let audiences = Vec::new();
+    let mut sample_token_data = TokenData {
+        token_name: "token_data",
+        token: Some(Token::new("token_1")),
+        update_latest: -1,
+        valid_until: -2,
+        warn_after: -3,
+        scopes: &scopes,
+        audiences: &audiences,
+        refresh_token: Some(String::from("rt-1")),
+    };
+    let provider = RefreshingAccessTokenProviderMock {
+        primary_result: Ok(access_token("primary", None)),
+        refresh_result: Ok(access_token("refreshed", None)),
+        used_refresh_tokens: RefCell::new(Vec::new()),
+    };
+    let credentials = CredentialsPair::client_only(Credentials::new("", ""));
+
+    update_token_data(&mut sample_token_data, &provider, &credentials, 0.5f32, 1.0f32).unwrap();
+
+    assert_eq!(Some(Token::new("refreshed")), sample_token_data.token);
+    assert_eq!(Some(String::from("rt-1")), sample_token_data.refresh_token);
+    assert_eq!(vec![String::from("rt-1")], *provider.used_refresh_tokens.borrow());
+}
+
+#[test]
+fn update_token_data_should_fall_back_to_the_primary_grant_when_the_refresh_fails() {
+    let scopes = vec![Scope::new("sc")];
+    let audiences = Vec::new();
+    let mut sample_token_data = TokenData {
+        token_name: "token_data",
+        token: Some(Token::new("token_1")),
+        update_latest: -1,
+        valid_until: -2,
+        warn_after: -3,
+        scopes: &scopes,
+        audiences: &audiences,
+        refresh_token: Some(String::from("rt-1")),
+    };
+    let provider = RefreshingAccessTokenProviderMock {
+        primary_result: Ok(access_token("primary", Some("rt-2"))),
+        refresh_result: Err(RequestAccessTokenError::RequestError {
+            status: 400,
+            body: String::from("{\"error\":\"invalid_grant\"}"),
+        }),
+        used_refresh_tokens: RefCell::new(Vec::new()),
+    };
+    let credentials = CredentialsPair::client_only(Credentials::new("", ""));
+
+    update_token_data(&mut sample_token_data, &provider, &credentials, 0.5f32, 1.0f32).unwrap();
+
+    assert_eq!(Some(Token::new("primary")), sample_token_data.token);
+    assert_eq!(Some(String::from("rt-2")), sample_token_data.refresh_token);
+    assert_eq!(vec![String::from("rt-1")], *provider.used_refresh_tokens.borrow());
+}
+
+#[test]
+fn update_token_data_should_keep_the_refresh_token_when_the_refresh_fails_transiently() {
+    let scopes = vec![Scope::new("sc")];
+    let audiences = Vec::new();
+    let mut sample_token_data = TokenData {
+        token_name: "token_data",
+        token: Some(Token::new("token_1")),
+        update_latest: -1,
+        valid_until: -2,
+        warn_after: -3,
+        scopes: &scopes,
+        audiences: &audiences,
+        refresh_token: Some(String::from("rt-1")),
+    };
+    let provider = RefreshingAccessTokenProviderMock {
+        primary_result: Err(RequestAccessTokenError::InternalError(String::from("no grant"))),
+        refresh_result: Err(RequestAccessTokenError::ConnectionError(String::from("timeout"))),
+        used_refresh_tokens: RefCell::new(Vec::new()),
+    };
+    let credentials = CredentialsPair::client_only(Credentials::new("", ""));
+
+    let result = update_token_data(&mut sample_token_data, &provider, &credentials, 0.5f32, 1.0f32);
+
+    match result {
+        Err(RequestAccessTokenError::ConnectionError(_)) => (),
+        other => panic!("Unexpected result {:?}", other),
+    }
+    assert_eq!(Some(Token::new("token_1")), sample_token_data.token);
+    assert_eq!(Some(String::from("rt-1")), sample_token_data.refresh_token);
+
+    let provider = RefreshingAccessTokenProviderMock {
+        refresh_result: Ok(access_token("refreshed", None)),
+        ..provider
+    };
+    update_token_data(&mut sample_token_data, &provider, &credentials, 0.5f32, 1.0f32).unwrap();
+
+    assert_eq!(Some(Token::new("refreshed")), sample_token_data.token);
+    assert_eq!(vec![String::from("rt-1"), String::from("rt-1")],
+               *provider.used_refresh_tokens.borrow());
+}
+
 struct MultipleAccessTokensProviderMock {
     results: Vec<RequestAccessTokenResult>,
     counter: Cell<usize>,
@@ -158,6 +304,35 @@ impl AccessTokenProvider for MultipleAccessTokensProviderMock {
     }
 }
 
+#[test]
+fn update_token_data_should_renew_the_token_if_refresh_tokens_are_not_supported() {
+    let scopes = vec![Scope::new("sc")];
+    let audiences = Vec::new();
+    let mut sample_token_data = TokenData {
+        token_name: "token_data",
+        token: None,
+        update_latest: -1,
+        valid_until: -2,
+        warn_after: -3,
+        scopes: &scopes,
+        audiences: &audiences,
+        refresh_token: None,
+    };
+    let provider =
+        MultipleAccessTokensProviderMock::new(vec![Ok(access_token("token_1", Some("rt-1"))),
+                                                   Ok(access_token("token_2", Some("rt-2")))]);
+    let credentials = CredentialsPair::client_only(Credentials::new("", ""));
+
+    update_token_data(&mut sample_token_data, &provider, &credentials, 0.5f32, 1.0f32).unwrap();
+    assert_eq!(Some(Token::new("token_1")), sample_token_data.token);
+    assert_eq!(Some(String::from("rt-1")), sample_token_data.refresh_token);
+
+    update_token_data(&mut sample_token_data, &provider, &credentials, 0.5f32, 1.0f32).unwrap();
+    assert_eq!(Some(Token::new("token_2")), sample_token_data.token);
+    assert_eq!(Some(String::from("rt-2")), sample_token_data.refresh_token);
+    assert_eq!(2, provider.counter.get());
+}
+
 #[test]
 fn basic_loop_iteration() {
 
@@ -173,16 +348,19 @@ fn basic_loop_iteration() {
                                         token: Token::new("token_1"),
                                         issued_at_utc: now.naive_utc() - Duration::seconds(0),
                                         valid_until_utc: now.naive_utc() + Duration::seconds(10),
+                                        refresh_token: None,
                                     }),
                                     Ok(AccessToken {
                                         token: Token::new("token_2"),
                                         issued_at_utc: now.naive_utc() - Duration::seconds(20),
                                         valid_until_utc: now.naive_utc() + Duration::seconds(20),
+                                        refresh_token: None,
                                     }),
                                     Ok(AccessToken {
                                         token: Token::new("token_3"),
                                         issued_at_utc: now.naive_utc() - Duration::seconds(30),
                                         valid_until_utc: now.naive_utc() + Duration::seconds(30),
+                                        refresh_token: None,
                                     })];
 
     let access_token_provider = MultipleAccessTokensProviderMock::new(sample_access_tokens);
@@ -199,6 +377,7 @@ fn basic_loop_iteration() {
     let stop_requested = stop.clone();
     let join_handle = thread::spawn(move || {
         let scopes = vec![Scope(String::from("sc"))];
+        let audiences = Vec::new();
 
         let managed_token_data = vec![TokenData {
                                           token_name: "my_token",
@@ -207,6 +386,8 @@ fn basic_loop_iteration() {
                                           valid_until: -2,
                                           warn_after: -3,
                                           scopes: &scopes,
+                                          audiences: &audiences,
+                                          refresh_token: None,
                                       }];
 
         manager_loop(manager_state_for_loop,
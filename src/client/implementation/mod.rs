@@ -12,7 +12,9 @@ use rustc_serialize::json::DecoderError;
 use chrono::NaiveDateTime;
 use {Token, Scope, InitializationError};
 use super::{TokenError, TokenManager, ManagedToken, TokenResult};
-use client::credentials::{CredentialsPair, CredentialsPairProvider};
+use client::credentials::{Credentials, CredentialsError, CredentialsPair,
+                          CredentialsPairProvider, ClientCredentialsProvider,
+                          UserCredentialsProvider};
 
 
 mod manager_loop;
@@ -72,6 +74,8 @@ pub struct AccessToken {
     pub token: Token,
     pub issued_at_utc: NaiveDateTime,
     pub valid_until_utc: NaiveDateTime,
+    /// Can be used to request the next `AccessToken` without sending the secrets again
+    pub refresh_token: Option<String>,
 }
 
 pub type RequestAccessTokenResult = Result<AccessToken, RequestAccessTokenError>;
@@ -82,6 +86,35 @@ pub trait AccessTokenProvider {
                         scopes: &[Scope],
                         credentials: &CredentialsPair)
                         -> RequestAccessTokenResult;
+
+    /// Fetches an `AccessToken` restricted to the given audiences.
+    ///
+    /// Delegates to `get_access_token` by default and fails if there are audiences,
+    /// since they would be ignored.
+    fn get_access_token_for(&self,
+                            scopes: &[Scope],
+                            audiences: &[String],
+                            credentials: &CredentialsPair)
+                            -> RequestAccessTokenResult {
+        if audiences.is_empty() {
+            self.get_access_token(scopes, credentials)
+        } else {
+            Err(RequestAccessTokenError::InternalError(String::from("Audiences are not \
+                                                                     supported.")))
+        }
+    }
+
+    /// Renews an `AccessToken` with the `refresh_token` grant.
+    ///
+    /// Ignores the refresh token and uses `get_access_token_for` by default.
+    fn refresh_access_token(&self,
+                            _refresh_token: &str,
+                            scopes: &[Scope],
+                            audiences: &[String],
+                            credentials: &CredentialsPair)
+                            -> RequestAccessTokenResult {
+        self.get_access_token_for(scopes, audiences, credentials)
+    }
 }
 
 /// A `TokenManager` that autonomously updates its `Token`s
@@ -114,6 +147,43 @@ impl SelfUpdatingTokenManager {
                       provider.stop_requested.clone())};
         Ok((provider, join_handle))
     }
+
+    /// Create a new instance for an `AccessTokenProvider` that needs no `Credentials`,
+    /// e.g. a `DeviceAuthorizationFlow` that renews its `Token`s with refresh tokens.
+    ///
+    /// The `AccessTokenProvider` receives empty client `Credentials`.
+    #[must_use]
+    pub fn new_without_credentials<T>
+        (conf: SelfUpdatingTokenManagerConfig,
+         access_token_provider: T)
+         -> Result<(SelfUpdatingTokenManager, JoinHandle<()>), InitializationError>
+        where T: AccessTokenProvider + Send + 'static
+    {
+        SelfUpdatingTokenManager::new(conf, NoCredentialsProvider, access_token_provider)
+    }
+}
+
+/// Provides empty client `Credentials` and no user `Credentials`
+struct NoCredentialsProvider;
+
+impl ClientCredentialsProvider for NoCredentialsProvider {
+    fn get_client_credentials(&self) -> Result<Credentials, CredentialsError> {
+        Ok(Credentials::new("", ""))
+    }
+}
+
+impl UserCredentialsProvider for NoCredentialsProvider {
+    fn get_user_credentials(&self) -> Result<Credentials, CredentialsError> {
+        Err(CredentialsError::NotAvailable {
+            message: String::from("No credentials are provided."),
+        })
+    }
+}
+
+impl CredentialsPairProvider for NoCredentialsProvider {
+    fn get_credentials_pair(&self) -> Result<CredentialsPair, CredentialsError> {
+        Ok(CredentialsPair::client_only(try!{self.get_client_credentials()}))
+    }
 }
 
 impl TokenManager for SelfUpdatingTokenManager {
@@ -213,7 +283,8 @@ mod test {
     use Token;
     use client::{ManagedToken, TokenManager, SelfUpdatingTokenManager,
                  SelfUpdatingTokenManagerConfig, TokenResult};
-    use client::credentials::{CredentialsPair, StaticCredentialsProvider};
+    use client::credentials::{CredentialsPair, CredentialsPairProvider,
+                              StaticCredentialsProvider};
     use client::implementation::{AccessToken, AccessTokenProvider, RequestAccessTokenResult,
                                  RequestAccessTokenError};
     // use super::{TokenData, update_token_data, manager_loop};
@@ -270,16 +341,19 @@ mod test {
                      token: Token(String::from("token_1")),
                      issued_at_utc: now.naive_utc() - Duration::seconds(0),
                      valid_until_utc: now.naive_utc() + Duration::seconds(10),
+                     refresh_token: None,
                  }),
                  Ok(AccessToken {
                      token: Token(String::from("token_2")),
                      issued_at_utc: now.naive_utc() - Duration::seconds(20),
                      valid_until_utc: now.naive_utc() + Duration::seconds(20),
+                     refresh_token: None,
                  }),
                  Ok(AccessToken {
                      token: Token(String::from("token_3")),
                      issued_at_utc: now.naive_utc() - Duration::seconds(30),
                      valid_until_utc: now.naive_utc() + Duration::seconds(30),
+                     refresh_token: None,
                  })];
 
         let access_token_provider = MultipleAccessTokensProviderMock::new(sample_access_tokens);
@@ -325,6 +399,53 @@ mod test {
 
     }
 
+    #[test]
+    fn audiences_must_not_be_ignored_by_default() {
+        let now = UTC::now().naive_utc();
+        let access_token = AccessToken {
+            token: Token::new("token_1"),
+            issued_at_utc: now,
+            valid_until_utc: now + Duration::seconds(60),
+            refresh_token: None,
+        };
+        let provider = MultipleAccessTokensProviderMock::new(vec![Ok(access_token.clone())]);
+        let credentials = StaticCredentialsProvider::new("", "", "", "")
+            .get_credentials_pair()
+            .unwrap();
+
+        assert_eq!(access_token,
+                   provider.get_access_token_for(&[], &[], &credentials).unwrap());
+        match provider.get_access_token_for(&[], &[String::from("my-service")], &credentials) {
+            Err(RequestAccessTokenError::InternalError(_)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn a_manager_without_credentials_must_provide_tokens() {
+        let now = UTC::now();
+        let managed_token = ManagedToken::new("my_token").with_scope(Scope::new("test"));
+        let config = SelfUpdatingTokenManagerConfig::new(vec![managed_token], 0.5, 1.0);
+        let access_token_provider =
+            MultipleAccessTokensProviderMock::new(vec![Ok(AccessToken {
+                                                           token: Token::new("token_1"),
+                                                           issued_at_utc: now.naive_utc(),
+                                                           valid_until_utc: now.naive_utc() +
+                                                                            Duration::seconds(60),
+                                                           refresh_token: None,
+                                                       })]);
+
+        let (manager, join_handle) =
+            SelfUpdatingTokenManager::new_without_credentials(config, access_token_provider)
+                .unwrap();
+        thread::sleep(TDuration::from_secs(1));
+        let token_result = manager.get_token("my_token");
+        manager.stop();
+        join_handle.join().unwrap();
+
+        assert_eq!(Token::new("token_1"), token_result.unwrap());
+    }
+
     #[test]
     fn the_manager_must_change_tokens_over_multiple_threads() {
         let _ = env_logger::init();
@@ -347,16 +468,19 @@ mod test {
                      token: Token(String::from("token_1")),
                      issued_at_utc: now.naive_utc() - Duration::seconds(0),
                      valid_until_utc: now.naive_utc() + Duration::seconds(10),
+                     refresh_token: None,
                  }),
                  Ok(AccessToken {
                      token: Token(String::from("token_2")),
                      issued_at_utc: now.naive_utc() - Duration::seconds(20),
                      valid_until_utc: now.naive_utc() + Duration::seconds(20),
+                     refresh_token: None,
                  }),
                  Ok(AccessToken {
                      token: Token(String::from("token_3")),
                      issued_at_utc: now.naive_utc() - Duration::seconds(30),
                      valid_until_utc: now.naive_utc() + Duration::seconds(30),
+                     refresh_token: None,
                  })];
 
         let access_token_provider = MultipleAccessTokensProviderMock::new(sample_access_tokens);
@@ -12,12 +12,22 @@ pub mod credentials;
 
 mod implementation;
 
+#[cfg(feature = "hyper")]
+pub mod authorization_code;
+
+#[cfg(feature = "hyper")]
+pub mod device_authorization;
+
+#[cfg(feature = "hyper")]
+pub mod token_exchange;
+
 pub use client::implementation::SelfUpdatingTokenManagerConfig;
 pub use client::implementation::SelfUpdatingTokenManager;
 pub use client::implementation::RequestAccessTokenError;
+pub use client::implementation::{AccessToken, AccessTokenProvider};
 
 #[cfg(feature = "hyper")]
-pub use client::implementation::hypertokenmanager::HyperTokenManager;
+pub use client::implementation::hypertokenmanager::{HyperTokenManager, GrantType};
 
 /// Used to configure a `TokenManager`.
 /// Define a name for lookup and the `Scopes` you wish to be granted.
@@ -26,6 +36,14 @@ pub struct ManagedToken {
     pub name: String,
     /// The `Scopes` you wish to be granted with the `Token`.
     pub scopes: Vec<Scope>,
+    /// The services the `Token` shall be restricted to.
+    ///
+    /// Absolute URIs are requested as [resource indicators](https://tools.ietf.org/html/rfc8707),
+    /// any other value with the `audience` parameter.
+    pub audiences: Vec<String>,
+    /// A `Token` obtained elsewhere, e.g. with a device authorization, that the
+    /// `TokenManager` starts with and renews with its refresh token.
+    pub initial_access_token: Option<AccessToken>,
 }
 
 impl ManagedToken {
@@ -34,6 +52,8 @@ impl ManagedToken {
         ManagedToken {
             name: name.into(),
             scopes: Vec::new(),
+            audiences: Vec::new(),
+            initial_access_token: None,
         }
     }
 
@@ -52,6 +72,20 @@ impl ManagedToken {
         }
         x
     }
+
+    /// Builder method. Add an audience the `Token` shall be restricted to.
+    pub fn with_audience<T: Into<String>>(self, audience: T) -> Self {
+        let mut x = self;
+        x.audiences.push(audience.into());
+        x
+    }
+
+    /// Builder method. Start with an `AccessToken` instead of requesting one.
+    pub fn with_access_token(self, access_token: AccessToken) -> Self {
+        let mut x = self;
+        x.initial_access_token = Some(access_token);
+        x
+    }
 }
 
 /// The result returned by a `TokenManager` for queried `Tokens`.
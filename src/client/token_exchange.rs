@@ -0,0 +1,374 @@
+//! OAuth2 Token Exchange as defined by [RFC 8693](https://tools.ietf.org/html/rfc8693).
+//!
+//! A service that received the `Token` of a user exchanges it for a `Token` with other
+//! scopes or audiences to call further services on behalf of that user.
+//!
+//! Exchanged `Token`s are cached per client, subject token, scopes and audiences until
+//! shortly before they expire. The cache only keeps SHA-256 digests of the subject tokens
+//! and of the client secrets.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use chrono::{Duration, UTC};
+use hyper;
+use openssl::sha::sha256;
+use url::form_urlencoded;
+use {Scope, Token};
+use client::credentials::{Credentials, CredentialsPair};
+use client::implementation::{AccessToken, AccessTokenProvider, RequestAccessTokenError,
+                             RequestAccessTokenResult};
+use client::implementation::hypertokenmanager::{append_audiences, evaluate_response,
+                                                post_client_form, TokenFormat};
+
+/// The `grant_type` of a token exchange
+pub const TOKEN_EXCHANGE_GRANT_TYPE: &'static str = "urn:ietf:params:oauth:grant-type:\
+                                                     token-exchange";
+
+/// The default `subject_token_type`
+pub const ACCESS_TOKEN_TYPE: &'static str = "urn:ietf:params:oauth:token-type:access_token";
+
+/// A cached `Token` is not used if it expires within this many seconds.
+const CACHE_LEEWAY_SECONDS: i64 = 30;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    client_id: String,
+    client_secret_digest: [u8; 32],
+    subject_token_digest: [u8; 32],
+    scopes: Vec<String>,
+    audiences: Vec<String>,
+}
+
+impl CacheKey {
+    fn new(client_credentials: &Credentials,
+           subject_token: &Token,
+           scopes: &[Scope],
+           audiences: &[String])
+           -> CacheKey {
+        let mut scopes: Vec<String> = scopes.iter().map(|scope| scope.0.clone()).collect();
+        scopes.sort();
+        scopes.dedup();
+        let mut audiences = audiences.to_vec();
+        audiences.sort();
+        audiences.dedup();
+        CacheKey {
+            client_id: client_credentials.id.clone(),
+            client_secret_digest: sha256(client_credentials.secret.as_bytes()),
+            subject_token_digest: sha256(subject_token.0.as_bytes()),
+            scopes: scopes,
+            audiences: audiences,
+        }
+    }
+}
+
+/// Exchanges subject tokens for `AccessToken`s and caches the results
+pub struct TokenExchangeProvider {
+    client: hyper::Client,
+    token_url: String,
+    subject_token_type: String,
+    cache_capacity: usize,
+    cache: Mutex<HashMap<CacheKey, AccessToken>>,
+}
+
+impl TokenExchangeProvider {
+    /// Create a new instance for access tokens as subject tokens that caches up to
+    /// 1000 `Token`s
+    pub fn new<T: Into<String>>(client: hyper::Client, token_url: T) -> TokenExchangeProvider {
+        TokenExchangeProvider {
+            client: client,
+            token_url: token_url.into(),
+            subject_token_type: String::from(ACCESS_TOKEN_TYPE),
+            cache_capacity: 1000,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Builder method. Set the `subject_token_type`, e.g. `urn:ietf:params:oauth:token-type:jwt`.
+    pub fn with_subject_token_type<T: Into<String>>(self, subject_token_type: T) -> Self {
+        let mut x = self;
+        x.subject_token_type = subject_token_type.into();
+        x
+    }
+
+    /// Builder method. Set the maximum number of cached `Token`s. `0` disables the cache.
+    pub fn with_cache_capacity(self, cache_capacity: usize) -> Self {
+        let mut x = self;
+        x.cache_capacity = cache_capacity;
+        x
+    }
+
+    /// Exchanges `subject_token` for a `Token` with the given scopes and audiences.
+    ///
+    /// The client authenticates with HTTP Basic authentication unless its secret is empty.
+    pub fn exchange(&self,
+                    subject_token: &Token,
+                    scopes: &[Scope],
+                    audiences: &[String],
+                    client_credentials: &Credentials)
+                    -> RequestAccessTokenResult {
+        let key = CacheKey::new(client_credentials, subject_token, scopes, audiences);
+        if let Some(access_token) = try!{self.cached(&key)} {
+            debug!("Using a cached exchanged token valid until {}.",
+                   access_token.valid_until_utc);
+            return Ok(access_token);
+        }
+
+        let mut form = form_urlencoded::Serializer::new(String::new());
+        form.append_pair("grant_type", TOKEN_EXCHANGE_GRANT_TYPE)
+            .append_pair("subject_token", &subject_token.0)
+            .append_pair("subject_token_type", &self.subject_token_type);
+        if !key.scopes.is_empty() {
+            form.append_pair("scope", &key.scopes.join(" "));
+        }
+        append_audiences(&mut form, audiences);
+        let client_secret = if client_credentials.secret.is_empty() {
+            None
+        } else {
+            Some(client_credentials.secret.as_ref())
+        };
+        let mut response = try!{post_client_form(&self.client,
+                                                 &self.token_url,
+                                                 &client_credentials.id,
+                                                 client_secret,
+                                                 form)};
+        let access_token = try!{evaluate_response(&mut response, TokenFormat::PlanBOrOpaque)};
+        try!{self.cache_token(key, &access_token)};
+        Ok(access_token)
+    }
+
+    /// The number of cached `Token`s, including expired ones not yet evicted
+    pub fn cached_tokens(&self) -> usize {
+        self.cache.lock().map(|cache| cache.len()).unwrap_or(0)
+    }
+
+    fn cached(&self, key: &CacheKey) -> Result<Option<AccessToken>, RequestAccessTokenError> {
+        let cache = try!{self.cache.lock().map_err(|err| {
+            RequestAccessTokenError::InternalError(format!("The cache is poisoned: {}", err))
+        })};
+        let usable_until = UTC::now().naive_utc() + Duration::seconds(CACHE_LEEWAY_SECONDS);
+        match cache.get(key) {
+            Some(access_token) if access_token.valid_until_utc > usable_until => {
+                Ok(Some(access_token.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Evicts expired `Token`s and, if the cache is still full, the one expiring first.
+    fn cache_token(&self,
+                   key: CacheKey,
+                   access_token: &AccessToken)
+                   -> Result<(), RequestAccessTokenError> {
+        if self.cache_capacity == 0 {
+            return Ok(());
+        }
+        let mut cache = try!{self.cache.lock().map_err(|err| {
+            RequestAccessTokenError::InternalError(format!("The cache is poisoned: {}", err))
+        })};
+        let now = UTC::now().naive_utc();
+        let expired: Vec<CacheKey> = cache.iter()
+            .filter(|&(_, cached)| cached.valid_until_utc <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            cache.remove(&key);
+        }
+        if cache.len() >= self.cache_capacity && !cache.contains_key(&key) {
+            let first_to_expire = cache.iter()
+                .min_by_key(|&(_, cached)| cached.valid_until_utc)
+                .map(|(key, _)| key.clone());
+            if let Some(first_to_expire) = first_to_expire {
+                cache.remove(&first_to_expire);
+            }
+        }
+        cache.insert(key, access_token.clone());
+        Ok(())
+    }
+}
+
+/// An `AccessTokenProvider` that exchanges a fixed subject token with the client
+/// `Credentials` of its `CredentialsPair`.
+///
+/// Shares the cache of its `TokenExchangeProvider`, so it can be handed to a
+/// `SelfUpdatingTokenManager`.
+pub struct SubjectTokenExchange {
+    provider: Arc<TokenExchangeProvider>,
+    subject_token: Token,
+}
+
+impl SubjectTokenExchange {
+    /// Create a new instance that exchanges `subject_token` with `provider`
+    pub fn new(provider: Arc<TokenExchangeProvider>, subject_token: Token) -> SubjectTokenExchange {
+        SubjectTokenExchange {
+            provider: provider,
+            subject_token: subject_token,
+        }
+    }
+}
+
+impl AccessTokenProvider for SubjectTokenExchange {
+    fn get_access_token(&self,
+                        scopes: &[Scope],
+                        credentials: &CredentialsPair)
+                        -> RequestAccessTokenResult {
+        self.get_access_token_for(scopes, &[], credentials)
+    }
+
+    fn get_access_token_for(&self,
+                            scopes: &[Scope],
+                            audiences: &[String],
+                            credentials: &CredentialsPair)
+                            -> RequestAccessTokenResult {
+        self.provider.exchange(&self.subject_token,
+                               scopes,
+                               audiences,
+                               &credentials.client_credentials)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use hyper;
+    use {Scope, Token};
+    use client::{ManagedToken, SelfUpdatingTokenManager, SelfUpdatingTokenManagerConfig,
+                 TokenManager};
+    use client::credentials::{ClientOnlyCredentialsProvider, Credentials, CredentialsPair,
+                              StaticCredentialsProvider};
+    use openssl::sha::sha256;
+    use fake_provider::fake_provider;
+    use client::implementation::{AccessTokenProvider, RequestAccessTokenError};
+    use super::{SubjectTokenExchange, TokenExchangeProvider};
+
+    const RESPONSE: &'static str = "{\"access_token\":\"downstream-token\",\"expires_in\":3600,\
+                                    \"issued_token_type\":\
+                                    \"urn:ietf:params:oauth:token-type:access_token\",\
+                                    \"token_type\":\"Bearer\"}";
+
+    fn gateway() -> Credentials {
+        Credentials::new("gateway", "gateway-secret")
+    }
+
+    #[test]
+    fn the_subject_token_must_be_exchanged() {
+        let (url, bodies) = fake_provider(vec![(200, RESPONSE)]);
+        let provider = TokenExchangeProvider::new(hyper::Client::new(), url);
+
+        let access_token = provider.exchange(&Token::new("user-token"),
+                      &[Scope::new("orders.read")],
+                      &[String::from("https://orders.example.org/")],
+                      &gateway())
+            .unwrap();
+
+        assert_eq!(Token::new("downstream-token"), access_token.token);
+        assert_eq!("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Atoken-exchange&\
+                    subject_token=user-token&subject_token_type=urn%3Aietf%3Aparams%3Aoauth%3A\
+                    token-type%3Aaccess_token&scope=orders.read&\
+                    resource=https%3A%2F%2Forders.example.org%2F",
+                   bodies.recv().unwrap());
+    }
+
+    #[test]
+    fn repeated_exchanges_must_be_served_from_the_cache() {
+        let (url, bodies) = fake_provider(vec![(200, RESPONSE), (200, RESPONSE)]);
+        let provider = TokenExchangeProvider::new(hyper::Client::new(), url);
+        let scopes = [Scope::new("a"), Scope::new("b")];
+        let reordered = [Scope::new("b"), Scope::new("a")];
+
+        let first = provider.exchange(&Token::new("user-token"), &scopes, &[], &gateway());
+        let second = provider.exchange(&Token::new("user-token"), &reordered, &[], &gateway());
+
+        assert_eq!(first.unwrap(), second.unwrap());
+        assert_eq!(1, provider.cached_tokens());
+        bodies.recv().unwrap();
+        assert!(bodies.try_recv().is_err());
+
+        provider.exchange(&Token::new("other-user-token"), &scopes, &[], &gateway()).unwrap();
+        assert_eq!(2, provider.cached_tokens());
+    }
+
+    #[test]
+    fn each_client_must_exchange_the_subject_token_itself() {
+        let other_response = "{\"access_token\":\"other-downstream-token\",\"expires_in\":3600}";
+        let (url, bodies) = fake_provider(vec![(200, RESPONSE), (200, other_response)]);
+        let provider = TokenExchangeProvider::new(hyper::Client::new(), url);
+        let other_client = Credentials::new("other-gateway", "other-secret");
+
+        let first = provider.exchange(&Token::new("user-token"), &[], &[], &gateway()).unwrap();
+        let second = provider.exchange(&Token::new("user-token"), &[], &[], &other_client)
+            .unwrap();
+
+        assert_eq!(Token::new("downstream-token"), first.token);
+        assert_eq!(Token::new("other-downstream-token"), second.token);
+        assert_eq!(2, provider.cached_tokens());
+        bodies.recv().unwrap();
+        bodies.recv().unwrap();
+    }
+
+    #[test]
+    fn the_cache_must_be_bounded() {
+        let (url, _) = fake_provider(vec![(200, RESPONSE), (200, RESPONSE), (200, RESPONSE)]);
+        let provider = TokenExchangeProvider::new(hyper::Client::new(), url).with_cache_capacity(1);
+
+        provider.exchange(&Token::new("user-token"), &[], &[], &gateway()).unwrap();
+        provider.exchange(&Token::new("other-user-token"), &[], &[], &gateway()).unwrap();
+
+        assert_eq!(1, provider.cached_tokens());
+    }
+
+    #[test]
+    fn failed_exchanges_must_not_be_cached() {
+        let (url, _) = fake_provider(vec![(400, "{\"error\":\"invalid_target\"}"),
+                                          (200, RESPONSE)]);
+        let provider = Arc::new(TokenExchangeProvider::new(hyper::Client::new(), url));
+        let credentials = CredentialsPair::client_only(gateway());
+        let exchange = SubjectTokenExchange::new(provider.clone(), Token::new("user-token"));
+
+        match exchange.get_access_token(&[], &credentials) {
+            Err(RequestAccessTokenError::RequestError { status: 400, .. }) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert_eq!(0, provider.cached_tokens());
+        assert_eq!(Token::new("downstream-token"),
+                   exchange.get_access_token(&[], &credentials).unwrap().token);
+    }
+
+    #[test]
+    fn a_subject_token_exchange_must_be_usable_by_a_token_manager() {
+        let (url, bodies) = fake_provider(vec![(200, RESPONSE)]);
+        let provider = Arc::new(TokenExchangeProvider::new(hyper::Client::new(), url));
+        let managed_token = ManagedToken::new("orders").with_scope(Scope::new("orders.read"));
+        let config = SelfUpdatingTokenManagerConfig::new(vec![managed_token], 0.8, 0.9);
+        let gateway_credentials =
+            StaticCredentialsProvider::new("gateway", "gateway-secret", "", "");
+        let credentials = ClientOnlyCredentialsProvider::new(gateway_credentials);
+        let exchange = SubjectTokenExchange::new(provider.clone(), Token::new("user-token"));
+
+        let (manager, join_handle) =
+            SelfUpdatingTokenManager::new(config, credentials, exchange).unwrap();
+        thread::sleep(Duration::from_secs(1));
+        let token_result = manager.get_token("orders");
+        manager.stop();
+        join_handle.join().unwrap();
+
+        assert_eq!(Token::new("downstream-token"), token_result.unwrap());
+        assert_eq!(1, provider.cached_tokens());
+        assert!(bodies.recv().unwrap().contains("subject_token=user-token"));
+    }
+
+    #[test]
+    fn the_cache_must_not_keep_the_subject_token_or_the_client_secret() {
+        let (url, _) = fake_provider(vec![(200, RESPONSE)]);
+        let provider = TokenExchangeProvider::new(hyper::Client::new(), url);
+
+        provider.exchange(&Token::new("user-token"), &[], &[], &gateway()).unwrap();
+
+        let cache = provider.cache.lock().unwrap();
+        let digests: Vec<[u8; 32]> = cache.keys().map(|key| key.subject_token_digest).collect();
+        assert_eq!(vec![sha256(b"user-token")], digests);
+        let secret_digests: Vec<[u8; 32]> =
+            cache.keys().map(|key| key.client_secret_digest).collect();
+        assert_eq!(vec![sha256(b"gateway-secret")], secret_digests);
+    }
+}
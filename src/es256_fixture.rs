@@ -0,0 +1,49 @@
+//! P-256 keys and ES256 signatures for the tests.
+use openssl::ec::{EcGroup, EcKey};
+use openssl::ecdsa::EcdsaSig;
+use openssl::nid::Nid;
+use openssl::pkey::Private;
+use openssl::sha::sha256;
+use rustc_serialize::base64::{ToBase64, URL_SAFE};
+use jwt::jwk::{JsonWebKey, JwkSet};
+use jwt::signature::VerificationKey;
+use testing::ec_p256_jwk;
+
+/// Generates a new P-256 key.
+pub fn generate_key() -> EcKey<Private> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    EcKey::generate(&group).unwrap()
+}
+
+/// Generates a new P-256 key and the `VerificationKey` of its public key.
+pub fn generate_key_pair() -> (EcKey<Private>, VerificationKey) {
+    let key = generate_key();
+    let public_key = verification_key(&key);
+    (key, public_key)
+}
+
+/// The `VerificationKey` of the public key of `key`.
+pub fn verification_key(key: &EcKey<Private>) -> VerificationKey {
+    ec_p256_jwk(key).unwrap().to_verification_key().unwrap()
+}
+
+/// The public key of `key` as a JWK with the given `kid`.
+pub fn jwk(key: &EcKey<Private>, key_id: &str) -> JsonWebKey {
+    ec_p256_jwk(key).unwrap().with_key_id(key_id)
+}
+
+/// A set with only the public key of `key`.
+pub fn key_set(key: &EcKey<Private>, key_id: &str) -> JwkSet {
+    JwkSet::new(vec![jwk(key, key_id)])
+}
+
+/// Signs `signing_input` and appends the signature in the JWS `r || s` format.
+pub fn sign_es256(signing_input: &str, key: &EcKey<Private>) -> String {
+    let sig = EcdsaSig::sign(&sha256(signing_input.as_bytes()), key).unwrap();
+    let mut raw = vec![0u8; 64];
+    let r = sig.r().to_vec();
+    let s = sig.s().to_vec();
+    raw[32 - r.len()..32].copy_from_slice(&r);
+    raw[64 - s.len()..].copy_from_slice(&s);
+    format!("{}.{}", signing_input, raw.to_base64(URL_SAFE))
+}
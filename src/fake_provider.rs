@@ -0,0 +1,48 @@
+//! A minimal HTTP server that plays the token provider or the token info endpoint in tests.
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+
+/// Answers one request per entry of `responses` with its status and JSON body.
+///
+/// Returns the base URL and a receiver for the bodies of the requests.
+pub fn fake_provider<T>(responses: Vec<(u16, T)>) -> (String, mpsc::Receiver<String>)
+    where T: AsRef<str> + Send + 'static
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for (status, json) in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut content_length = 0;
+            let body = {
+                let mut reader = BufReader::new(&mut stream);
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    let lower = header.to_lowercase();
+                    if lower.starts_with("content-length:") {
+                        content_length = lower[15..].trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                String::from_utf8(body).unwrap()
+            };
+            let _ = tx.send(body);
+            write!(stream,
+                   "HTTP/1.1 {} Fake\r\nContent-Type: application/json\r\nContent-Length: \
+                    {}\r\nConnection: close\r\n\r\n{}",
+                   status,
+                   json.as_ref().len(),
+                   json.as_ref())
+                .unwrap();
+        }
+    });
+    (url, rx)
+}
@@ -0,0 +1,156 @@
+//! Strict base64url decoding as required by [RFC 7515](https://tools.ietf.org/html/rfc7515#section-2)
+//!
+//! Only the URL safe alphabet is accepted. Padding, whitespace and line breaks
+//! are rejected as well as encodings with non-zero trailing bits, so that every
+//! byte sequence has exactly one accepted encoding.
+use std::error::Error;
+use std::fmt;
+
+/// Decodes a base64url encoded String without padding.
+pub fn decode(encoded: &str) -> Result<Vec<u8>, Base64Error> {
+    if encoded.len() % 4 == 1 {
+        return Err(Base64Error::InvalidLength(encoded.len()));
+    }
+    let mut decoded = Vec::with_capacity(encoded.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for (position, character) in encoded.char_indices() {
+        let value = try!{decode_char(character).ok_or(Base64Error::InvalidCharacter {
+            character: character,
+            position: position,
+        })};
+        buffer = (buffer << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if buffer != 0 {
+        return Err(Base64Error::NonZeroTrailingBits);
+    }
+    Ok(decoded)
+}
+
+fn decode_char(character: char) -> Option<u32> {
+    let c = character as u32;
+    if character >= 'A' && character <= 'Z' {
+        Some(c - 'A' as u32)
+    } else if character >= 'a' && character <= 'z' {
+        Some(c - 'a' as u32 + 26)
+    } else if character >= '0' && character <= '9' {
+        Some(c - '0' as u32 + 52)
+    } else if character == '-' {
+        Some(62)
+    } else if character == '_' {
+        Some(63)
+    } else {
+        None
+    }
+}
+
+/// The reasons why a String is not valid base64url.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Base64Error {
+    /// A character outside of the base64url alphabet, e.g. `=`, `+`, `/` or whitespace
+    InvalidCharacter {
+        character: char,
+        /// The byte offset of the character
+        position: usize,
+    },
+    /// The length is not possible for an unpadded encoding
+    InvalidLength(usize),
+    /// The unused bits of the last character are not zero
+    NonZeroTrailingBits,
+}
+
+impl fmt::Display for Base64Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Base64Error::InvalidCharacter { character, position } => {
+                write!(f, "Invalid character {:?} at position {}", character, position)
+            }
+            Base64Error::InvalidLength(len) => write!(f, "Invalid length {}", len),
+            Base64Error::NonZeroTrailingBits => write!(f, "The trailing bits are not zero"),
+        }
+    }
+}
+
+impl Error for Base64Error {
+    fn description(&self) -> &str {
+        match *self {
+            Base64Error::InvalidCharacter { .. } => "Invalid base64url character",
+            Base64Error::InvalidLength(_) => "Invalid base64url length",
+            Base64Error::NonZeroTrailingBits => "Non-zero trailing bits",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rustc_serialize::base64::{ToBase64, URL_SAFE};
+    use super::{decode, Base64Error};
+
+    #[test]
+    fn all_lengths_must_round_trip() {
+        let bytes: Vec<u8> = (0..64).map(|x| (x * 37 + 11) as u8).collect();
+        for len in 0..bytes.len() {
+            let encoded = bytes[..len].to_base64(URL_SAFE);
+            assert_eq!(Ok(bytes[..len].to_vec()), decode(&encoded));
+        }
+    }
+
+    #[test]
+    fn the_url_safe_alphabet_must_be_decoded() {
+        assert_eq!(Ok(vec![0xfb, 0xff, 0xbf]), decode("-_-_"));
+    }
+
+    #[test]
+    fn padding_must_be_rejected() {
+        assert_eq!(Err(Base64Error::InvalidCharacter {
+                       character: '=',
+                       position: 2,
+                   }),
+                   decode("YQ=="));
+    }
+
+    #[test]
+    fn the_standard_alphabet_must_be_rejected() {
+        assert_eq!(Err(Base64Error::InvalidCharacter {
+                       character: '+',
+                       position: 0,
+                   }),
+                   decode("+/+/"));
+        assert_eq!(Err(Base64Error::InvalidCharacter {
+                       character: '/',
+                       position: 1,
+                   }),
+                   decode("_/_/"));
+    }
+
+    #[test]
+    fn whitespace_must_be_rejected() {
+        assert_eq!(Err(Base64Error::InvalidCharacter {
+                       character: '\n',
+                       position: 4,
+                   }),
+                   decode("YWJj\nZGV"));
+        assert!(decode(" YWJj").is_err());
+    }
+
+    #[test]
+    fn an_impossible_length_must_be_rejected() {
+        assert_eq!(Err(Base64Error::InvalidLength(5)), decode("YWJjZ"));
+    }
+
+    #[test]
+    fn non_zero_trailing_bits_must_be_rejected() {
+        assert_eq!(Ok(b"a".to_vec()), decode("YQ"));
+        assert_eq!(Err(Base64Error::NonZeroTrailingBits), decode("YR"));
+    }
+}
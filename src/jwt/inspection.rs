@@ -0,0 +1,175 @@
+//! Decoding of tokens **without** verifying them.
+//!
+//! Use `inspect` to explain why a token was rejected, e.g. in logs or on-call tooling.
+//! Never base a decision on an `UnverifiedToken`. Use `JsonWebToken::verify_with`,
+//! `JsonWebToken::verify_with_key_set` or a `JwtVerifier` instead.
+use std::collections::HashMap;
+use chrono::{Duration, NaiveDateTime, UTC};
+use rustc_serialize::json::Json;
+use super::{RegisteredHeader, RegisteredClaim, JwtParseError, Segment, split_segments,
+            decode_segment, decode_base_64_string, parse_json_str_to_json_map};
+
+/// A token that was decoded but whose signature was NOT verified
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnverifiedToken {
+    pub header: HashMap<String, Json>,
+    /// The claims. Empty if the token is nested.
+    pub payload: HashMap<String, Json>,
+    pub signature: Vec<u8>,
+    /// The `alg` header
+    pub algorithm: Option<String>,
+    /// The `kid` header
+    pub key_id: Option<String>,
+    /// The `iat` claim
+    pub issue_date_utc: Option<NaiveDateTime>,
+    /// The `exp` claim
+    pub expiration_date_utc: Option<NaiveDateTime>,
+    /// The inner token if the payload is a JWT as signaled by `"cty":"JWT"`
+    pub nested: Option<Box<UnverifiedToken>>,
+}
+
+impl UnverifiedToken {
+    /// The time until the token expires. Negative if the token has already expired.
+    pub fn remaining_lifetime(&self) -> Option<Duration> {
+        self.remaining_lifetime_at(UTC::now().timestamp())
+    }
+
+    /// The time from the unix epoch `now` until the token expires.
+    pub fn remaining_lifetime_at(&self, now: i64) -> Option<Duration> {
+        self.expiration_date_utc.map(|exp| Duration::seconds(exp.timestamp() - now))
+    }
+
+    /// The innermost token, i.e. the one carrying the claims.
+    pub fn innermost(&self) -> &UnverifiedToken {
+        match self.nested {
+            Some(ref nested) => nested.innermost(),
+            None => self,
+        }
+    }
+}
+
+/// Decodes a token in compact serialization **without** verifying its signature.
+///
+/// A nested JWT is decoded as well. Encrypted tokens can not be inspected.
+pub fn inspect(token: &str) -> Result<UnverifiedToken, JwtParseError> {
+    let (header, payload, signature) = try!{split_segments(token)};
+    let signature = try!{decode_segment(Segment::Signature, signature)};
+    let header_str = try!{decode_base_64_string(Segment::Header, header)};
+    let header = try!{parse_json_str_to_json_map(Segment::Header, &header_str)};
+    let payload_str = try!{decode_base_64_string(Segment::Payload, payload)};
+
+    let is_nested = header.get(RegisteredHeader::ContentType.to_key())
+        .and_then(|json| json.as_string())
+        .map_or(false, |cty| cty.eq_ignore_ascii_case("JWT"));
+    let (payload, nested) = if is_nested {
+        (HashMap::new(), Some(Box::new(try!{inspect(payload_str.trim())})))
+    } else {
+        (try!{parse_json_str_to_json_map(Segment::Payload, &payload_str)}, None)
+    };
+
+    Ok(UnverifiedToken {
+        algorithm: get_string(&header, RegisteredHeader::Algorithm.to_key()),
+        key_id: get_string(&header, RegisteredHeader::KeyId.to_key()),
+        issue_date_utc: get_date(&payload, RegisteredClaim::IssuedAt.to_key()),
+        expiration_date_utc: get_date(&payload, RegisteredClaim::ExpirationTime.to_key()),
+        header: header,
+        payload: payload,
+        signature: signature,
+        nested: nested,
+    })
+}
+
+fn get_string(map: &HashMap<String, Json>, key: &str) -> Option<String> {
+    map.get(key).and_then(|json| json.as_string()).map(|s| s.to_owned())
+}
+
+/// Fractional seconds are floored as done by `Validation`.
+fn get_date(map: &HashMap<String, Json>, key: &str) -> Option<NaiveDateTime> {
+    map.get(key)
+        .and_then(|json| json.as_f64())
+        .and_then(|timestamp| NaiveDateTime::from_timestamp_opt(timestamp.floor() as i64, 0))
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+    use rustc_serialize::base64::{ToBase64, URL_SAFE};
+    use rustc_serialize::json::Json;
+    use jwt::{JsonWebToken, JwtParseError, Header, RegisteredHeader, Claim, RegisteredClaim};
+    use jwt::signature::SigningKey;
+    use es256_fixture::generate_key;
+    use super::inspect;
+
+    const SAMPLE_TOKEN: &'static str = "eyJraWQiOiJ0ZXN0a2V5LWVzMjU2IiwiYWxnIjoiRVMyNTYifQ.\
+                                        eyJzdWIiOiJ0ZXN0MiIsInNjb3BlIjpbImNuIl0sImlzcyI6IkIiLCJyZWFsbSI6Ii9zZXJ2aWNlcyIsImV4cCI6MTQ1NzMxOTgxNCwiaWF0IjoxNDU3MjkxMDE0fQ.\
+                                        KmDsVB09RAOYwT0Y6E9tdQpg0rAPd8SExYhcZ9tXEO6y9AWX4wBylnmNHVoetWu7MwoexWkaKdpKk09IodMVug";
+
+    #[test]
+    fn the_sample_token_must_be_inspected() {
+        let inspected = inspect(SAMPLE_TOKEN).unwrap();
+
+        assert_eq!(Some(String::from("ES256")), inspected.algorithm);
+        assert_eq!(Some(String::from("testkey-es256")), inspected.key_id);
+        assert_eq!(Some(NaiveDateTime::new(NaiveDate::from_ymd(2016, 3, 6),
+                                           NaiveTime::from_hms(19, 3, 34))),
+                   inspected.issue_date_utc);
+        assert_eq!(Some(NaiveDateTime::new(NaiveDate::from_ymd(2016, 3, 7),
+                                           NaiveTime::from_hms(3, 3, 34))),
+                   inspected.expiration_date_utc);
+        assert_eq!(Some(Duration::seconds(-14)),
+                   inspected.remaining_lifetime_at(1457319828));
+        assert_eq!(64, inspected.signature.len());
+        assert_eq!(Some(&Json::String(String::from("test2"))), inspected.payload.get("sub"));
+        assert_eq!(None, inspected.nested);
+    }
+
+    #[test]
+    fn a_nested_token_must_be_inspected_recursively() {
+        let key = SigningKey::EcP256(generate_key());
+        let inner = JsonWebToken::new()
+            .add_header(&Header::Registered(RegisteredHeader::KeyId),
+                        Json::String(String::from("inner")))
+            .add_payload(&Claim::Registered(RegisteredClaim::ExpirationTime),
+                         Json::U64(1457319814))
+            .encode(&key)
+            .unwrap();
+        let outer = format!("{}.{}.",
+                            b"{\"alg\":\"none\",\"cty\":\"JWT\"}".to_base64(URL_SAFE),
+                            inner.as_bytes().to_base64(URL_SAFE));
+
+        let inspected = inspect(&outer).unwrap();
+
+        assert_eq!(Some(String::from("none")), inspected.algorithm);
+        assert!(inspected.payload.is_empty());
+        assert_eq!(None, inspected.remaining_lifetime());
+        let nested = inspected.innermost();
+        assert_eq!(Some(String::from("inner")), nested.key_id);
+        assert_eq!(Some(String::from("ES256")), nested.algorithm);
+        assert_eq!(Some(Duration::seconds(0)), nested.remaining_lifetime_at(1457319814));
+    }
+
+    #[test]
+    fn fractional_numeric_dates_must_be_floored() {
+        let token = format!("{}.{}.",
+                            b"{\"alg\":\"none\"}".to_base64(URL_SAFE),
+                            b"{\"iat\":1457291014.9,\"exp\":1.5e9}".to_base64(URL_SAFE));
+
+        let inspected = inspect(&token).unwrap();
+
+        assert_eq!(Some(NaiveDateTime::new(NaiveDate::from_ymd(2016, 3, 6),
+                                           NaiveTime::from_hms(19, 3, 34))),
+                   inspected.issue_date_utc);
+        assert_eq!(Some(NaiveDateTime::from_timestamp(1500000000, 0)),
+                   inspected.expiration_date_utc);
+    }
+
+    #[test]
+    fn an_unparsable_token_must_be_explained() {
+        let header = b"{\"alg\":\"ES256\"}".to_base64(URL_SAFE);
+
+        assert_eq!(Err(JwtParseError::WrongNumberOfSegments(2)),
+                   inspect(&format!("{}.e30", header)));
+        assert_eq!(Err(JwtParseError::Encrypted), inspect("a.b.c.d.e"));
+        assert!(inspect(&format!("{}.e30=.", header)).is_err());
+    }
+}
@@ -0,0 +1,692 @@
+//! Decryption of [JWE tokens](https://tools.ietf.org/html/rfc7516)
+//!
+//! Only the compact serialization with its five segments is supported.
+//! The content encryption key is either transported with `RSA-OAEP`/`RSA-OAEP-256`
+//! or agreed upon with `ECDH-ES` on the P-256 curve. The content is encrypted
+//! with `A128GCM` or `A256GCM`.
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use openssl::derive::Deriver;
+use openssl::ec::EcKey;
+use openssl::encrypt::Decrypter;
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Padding;
+use openssl::sha::sha256;
+use openssl::symm::{self, Cipher};
+use rustc_serialize::json::Json;
+use super::{JsonWebToken, RegisteredHeader, VerificationError, JwtParseError, Segment,
+            decode_base_64_bytes, decode_base_64_string, decode_segment,
+            parse_json_str_to_json_map};
+use super::jwk::{JsonWebKey, KeyType};
+use super::signature::{VerificationKey, MIN_RSA_KEY_BITS};
+
+/// The IV length AES-GCM is used with, see
+/// [RFC 7518](https://tools.ietf.org/html/rfc7518#section-5.3).
+const GCM_IV_BYTES: usize = 12;
+
+/// The authentication tag length. Shorter tags are rejected since they are easier to forge.
+const GCM_TAG_BYTES: usize = 16;
+
+/// The algorithms used to determine the content encryption key.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum KeyManagementAlgorithm {
+    /// RSAES OAEP using SHA-1 and MGF1 with SHA-1
+    RsaOaep,
+    /// RSAES OAEP using SHA-256 and MGF1 with SHA-256
+    RsaOaep256,
+    /// ECDH-ES using Concat KDF, the agreed key is the content encryption key
+    EcdhEs,
+}
+
+impl KeyManagementAlgorithm {
+    /// The value of the `alg` header for this algorithm
+    pub fn to_key(&self) -> &str {
+        match *self {
+            KeyManagementAlgorithm::RsaOaep => "RSA-OAEP",
+            KeyManagementAlgorithm::RsaOaep256 => "RSA-OAEP-256",
+            KeyManagementAlgorithm::EcdhEs => "ECDH-ES",
+        }
+    }
+}
+
+impl FromStr for KeyManagementAlgorithm {
+    type Err = JweError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "RSA-OAEP" => Ok(KeyManagementAlgorithm::RsaOaep),
+            "RSA-OAEP-256" => Ok(KeyManagementAlgorithm::RsaOaep256),
+            "ECDH-ES" => Ok(KeyManagementAlgorithm::EcdhEs),
+            unknown => Err(JweError::UnsupportedAlgorithm(unknown.to_owned())),
+        }
+    }
+}
+
+/// The algorithms used to encrypt the content.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum ContentEncryptionAlgorithm {
+    /// AES GCM using a 128 bit key
+    A128GCM,
+    /// AES GCM using a 256 bit key
+    A256GCM,
+}
+
+impl ContentEncryptionAlgorithm {
+    /// The value of the `enc` header for this algorithm
+    pub fn to_key(&self) -> &str {
+        match *self {
+            ContentEncryptionAlgorithm::A128GCM => "A128GCM",
+            ContentEncryptionAlgorithm::A256GCM => "A256GCM",
+        }
+    }
+
+    fn cipher(&self) -> Cipher {
+        match *self {
+            ContentEncryptionAlgorithm::A128GCM => Cipher::aes_128_gcm(),
+            ContentEncryptionAlgorithm::A256GCM => Cipher::aes_256_gcm(),
+        }
+    }
+
+    fn key_len(&self) -> usize {
+        match *self {
+            ContentEncryptionAlgorithm::A128GCM => 16,
+            ContentEncryptionAlgorithm::A256GCM => 32,
+        }
+    }
+}
+
+impl FromStr for ContentEncryptionAlgorithm {
+    type Err = JweError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "A128GCM" => Ok(ContentEncryptionAlgorithm::A128GCM),
+            "A256GCM" => Ok(ContentEncryptionAlgorithm::A256GCM),
+            unknown => Err(JweError::UnsupportedAlgorithm(unknown.to_owned())),
+        }
+    }
+}
+
+/// A private key that can be used to decrypt a JWE.
+pub enum DecryptionKey {
+    /// An RSA private key. Used with `RSA-OAEP` and `RSA-OAEP-256`.
+    Rsa(PKey<Private>),
+    /// An ECDH private key on the P-256 curve. Used with `ECDH-ES`.
+    EcP256(EcKey<Private>),
+}
+
+impl DecryptionKey {
+    /// Creates an RSA key from a PEM encoded private key.
+    pub fn rsa_from_pem(pem: &[u8]) -> Result<DecryptionKey, JweError> {
+        let key = try!{PKey::private_key_from_pem(pem)};
+        match key.rsa() {
+            Err(_) => Err(JweError::InvalidKey(String::from("The key is not an RSA key."))),
+            Ok(ref rsa) if rsa.size() * 8 < MIN_RSA_KEY_BITS => {
+                Err(JweError::InvalidKey(format!("RSA keys must have at least {} bits.",
+                                                 MIN_RSA_KEY_BITS)))
+            }
+            Ok(_) => Ok(DecryptionKey::Rsa(key)),
+        }
+    }
+
+    /// Creates a P-256 key from a PEM encoded private key.
+    pub fn ec_p256_from_pem(pem: &[u8]) -> Result<DecryptionKey, JweError> {
+        let key = try!{EcKey::private_key_from_pem(pem)};
+        if key.group().curve_name() != Some(Nid::X9_62_PRIME256V1) {
+            return Err(JweError::InvalidKey(String::from("The key is not on the P-256 curve.")));
+        }
+        Ok(DecryptionKey::EcP256(key))
+    }
+}
+
+/// A [JWE](https://tools.ietf.org/html/rfc7516) in compact serialization
+#[derive(PartialEq, Debug)]
+pub struct JsonWebEncryption {
+    /// The protected header fields
+    pub header: HashMap<String, Json>,
+    /// The encrypted content encryption key. Empty for `ECDH-ES`.
+    pub encrypted_key: Vec<u8>,
+    /// The initialization vector
+    pub iv: Vec<u8>,
+    /// The encrypted content
+    pub ciphertext: Vec<u8>,
+    /// The authentication tag
+    pub tag: Vec<u8>,
+    /// The encoded protected header, the additional authenticated data
+    encoded_header: String,
+}
+
+impl JsonWebEncryption {
+    /// The key management algorithm given by the `alg` header.
+    pub fn key_management_algorithm(&self) -> Result<KeyManagementAlgorithm, JweError> {
+        KeyManagementAlgorithm::from_str(try!{self.get_header_string("alg")})
+    }
+
+    /// The content encryption algorithm given by the `enc` header.
+    pub fn content_encryption_algorithm(&self) -> Result<ContentEncryptionAlgorithm, JweError> {
+        ContentEncryptionAlgorithm::from_str(try!{self.get_header_string("enc")})
+    }
+
+    /// Whether the content is a nested JWT as signaled by `"cty":"JWT"`.
+    pub fn is_nested(&self) -> bool {
+        self.header
+            .get(RegisteredHeader::ContentType.to_key())
+            .and_then(|json| json.as_string())
+            .map_or(false, |cty| cty.eq_ignore_ascii_case("JWT"))
+    }
+
+    /// Decrypts the content.
+    pub fn decrypt(&self, key: &DecryptionKey) -> Result<Vec<u8>, JweError> {
+        if self.header.contains_key(RegisteredHeader::Critical.to_key()) {
+            return Err(JweError::Malformed(String::from("Critical headers are not supported.")));
+        }
+        if self.iv.len() != GCM_IV_BYTES || self.tag.len() != GCM_TAG_BYTES {
+            return Err(JweError::Malformed(String::from("The IV must have 96 bits and the \
+                                                         authentication tag 128 bits.")));
+        }
+        let enc = try!{self.content_encryption_algorithm()};
+        let cek = match (try!{self.key_management_algorithm()}, key) {
+            (KeyManagementAlgorithm::RsaOaep, &DecryptionKey::Rsa(ref pkey)) => {
+                try!{decrypt_rsa_oaep(pkey, MessageDigest::sha1(), &self.encrypted_key)}
+            }
+            (KeyManagementAlgorithm::RsaOaep256, &DecryptionKey::Rsa(ref pkey)) => {
+                try!{decrypt_rsa_oaep(pkey, MessageDigest::sha256(), &self.encrypted_key)}
+            }
+            (KeyManagementAlgorithm::EcdhEs, &DecryptionKey::EcP256(ref ec_key)) => {
+                if !self.encrypted_key.is_empty() {
+                    return Err(JweError::Malformed(String::from("The encrypted key must be \
+                                                                 empty for ECDH-ES.")));
+                }
+                try!{self.agree_on_key(ec_key, enc)}
+            }
+            (alg, _) => return Err(JweError::IncompatibleKey(alg)),
+        };
+        if cek.len() != enc.key_len() {
+            return Err(JweError::DecryptionFailed);
+        }
+        symm::decrypt_aead(enc.cipher(),
+                           &cek,
+                           Some(&self.iv),
+                           self.encoded_header.as_bytes(),
+                           &self.ciphertext,
+                           &self.tag)
+            .map_err(|_| JweError::DecryptionFailed)
+    }
+
+    fn agree_on_key(&self,
+                    key: &EcKey<Private>,
+                    enc: ContentEncryptionAlgorithm)
+                    -> Result<Vec<u8>, JweError> {
+        let epk = try!{self.header
+            .get("epk")
+            .ok_or_else(|| JweError::Malformed(String::from("Field 'epk' is missing.")))};
+        let epk = try!{JsonWebKey::from_json(epk).map_err(|err| JweError::Malformed(format!("{}", err)))};
+        let peer = match epk.key_type {
+            KeyType::Ec { ref curve, ref x, ref y } if curve == "P-256" => {
+                match VerificationKey::ec_p256_from_coordinates(x, y) {
+                    Ok(VerificationKey::EcP256(ec_key)) => try!{PKey::from_ec_key(ec_key)},
+                    _ => return Err(JweError::Malformed(String::from("Field 'epk' is not a valid P-256 key."))),
+                }
+            }
+            _ => return Err(JweError::Malformed(String::from("Field 'epk' is not a P-256 key."))),
+        };
+        let private_key = try!{PKey::from_ec_key(key.clone())};
+        let mut deriver = try!{Deriver::new(&private_key)};
+        try!{deriver.set_peer(&peer)};
+        let shared_secret = try!{deriver.derive_to_vec()};
+        let apu = try!{self.get_optional_bytes("apu")};
+        let apv = try!{self.get_optional_bytes("apv")};
+        Ok(concat_kdf(&shared_secret, enc.to_key(), &apu, &apv, enc.key_len()))
+    }
+
+    fn get_header_string(&self, field: &str) -> Result<&str, JweError> {
+        self.header
+            .get(field)
+            .and_then(|json| json.as_string())
+            .ok_or_else(|| JweError::Malformed(format!("Field '{}' is missing or not a String.", field)))
+    }
+
+    fn get_optional_bytes(&self, field: &str) -> Result<Vec<u8>, JweError> {
+        match self.header.get(field) {
+            None => Ok(Vec::new()),
+            Some(&Json::String(ref encoded)) => {
+                decode_base_64_bytes(encoded)
+                    .map_err(|err| JweError::Malformed(format!("Field '{}': {}", field, err)))
+            }
+            Some(_) => Err(JweError::Malformed(format!("Field '{}' is not a String.", field))),
+        }
+    }
+}
+
+impl FromStr for JsonWebEncryption {
+    type Err = JweError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split('.').collect();
+        if parts.len() != 5 {
+            return Err(JweError::Parse(JwtParseError::WrongNumberOfSegments(parts.len())));
+        }
+        let header_str = try!{decode_base_64_string(Segment::Header, parts[0])};
+        let header = try!{parse_json_str_to_json_map(Segment::Header, &header_str)};
+        Ok(JsonWebEncryption {
+            header: header,
+            encrypted_key: try!{decode_segment(Segment::EncryptedKey, parts[1])},
+            iv: try!{decode_segment(Segment::InitializationVector, parts[2])},
+            ciphertext: try!{decode_segment(Segment::Ciphertext, parts[3])},
+            tag: try!{decode_segment(Segment::AuthenticationTag, parts[4])},
+            encoded_header: parts[0].to_owned(),
+        })
+    }
+}
+
+impl JsonWebToken {
+    /// Decrypts a JWE and parses the content as a token.
+    ///
+    /// The content of a nested JWT (`"cty":"JWT"`) is parsed as a token without verifying
+    /// its signature. Use `decrypt_and_verify_with` if the inner token is signed.
+    /// Otherwise the content are the claims and the header is the one of the JWE.
+    pub fn decrypt_with(token: &str, key: &DecryptionKey) -> Result<JsonWebToken, JweError> {
+        let jwe = try!{JsonWebEncryption::from_str(token)};
+        let plaintext = try!{decrypt_to_string(&jwe, key)};
+        if jwe.is_nested() {
+            JsonWebToken::from_str(&plaintext).map_err(JweError::Parse)
+        } else {
+            Ok(JsonWebToken {
+                header: jwe.header,
+                payload: try!{parse_json_str_to_json_map(Segment::Payload, &plaintext)},
+            })
+        }
+    }
+
+    /// Decrypts a JWE containing a nested JWT and verifies the signature of the inner token.
+    pub fn decrypt_and_verify_with(token: &str,
+                                   decryption_key: &DecryptionKey,
+                                   verification_key: &VerificationKey)
+                                   -> Result<JsonWebToken, JweError> {
+        let jwe = try!{JsonWebEncryption::from_str(token)};
+        if !jwe.is_nested() {
+            return Err(JweError::Malformed(String::from("The token does not contain a nested \
+                                                         JWT.")));
+        }
+        let plaintext = try!{decrypt_to_string(&jwe, decryption_key)};
+        JsonWebToken::verify_with(&plaintext, verification_key).map_err(JweError::Verification)
+    }
+}
+
+fn decrypt_to_string(jwe: &JsonWebEncryption, key: &DecryptionKey) -> Result<String, JweError> {
+    let plaintext = try!{jwe.decrypt(key)};
+    String::from_utf8(plaintext)
+        .map_err(|err| JweError::Malformed(format!("The content is not a valid UTF-8 String: {}", err)))
+}
+
+fn decrypt_rsa_oaep(key: &PKey<Private>,
+                    digest: MessageDigest,
+                    encrypted_key: &[u8])
+                    -> Result<Vec<u8>, JweError> {
+    let mut decrypter = try!{Decrypter::new(key)};
+    try!{decrypter.set_rsa_padding(Padding::PKCS1_OAEP)};
+    try!{decrypter.set_rsa_oaep_md(digest)};
+    try!{decrypter.set_rsa_mgf1_md(digest)};
+    let mut cek = vec![0u8; try!{decrypter.decrypt_len(encrypted_key)}];
+    let len = try!{decrypter.decrypt(encrypted_key, &mut cek).map_err(|_| JweError::DecryptionFailed)};
+    cek.truncate(len);
+    Ok(cek)
+}
+
+/// The Concat KDF of [NIST SP 800-56A](https://tools.ietf.org/html/rfc7518#section-4.6.2)
+/// with SHA-256. `algorithm_id` is the `enc` value when the key is used directly.
+fn concat_kdf(shared_secret: &[u8],
+              algorithm_id: &str,
+              apu: &[u8],
+              apv: &[u8],
+              key_len: usize)
+              -> Vec<u8> {
+    let mut other_info = Vec::new();
+    for field in &[algorithm_id.as_bytes(), apu, apv] {
+        other_info.extend_from_slice(&be_u32(field.len()));
+        other_info.extend_from_slice(field);
+    }
+    other_info.extend_from_slice(&be_u32(key_len * 8));
+
+    let mut key = Vec::new();
+    let mut counter = 1;
+    while key.len() < key_len {
+        let mut round_input = be_u32(counter).to_vec();
+        round_input.extend_from_slice(shared_secret);
+        round_input.extend_from_slice(&other_info);
+        key.extend_from_slice(&sha256(&round_input));
+        counter += 1;
+    }
+    key.truncate(key_len);
+    key
+}
+
+fn be_u32(n: usize) -> [u8; 4] {
+    [(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]
+}
+
+/// Errors that can occur when decrypting a JWE.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JweError {
+    /// The token could not be parsed
+    Parse(JwtParseError),
+    /// A header is missing or invalid
+    Malformed(String),
+    /// The `alg` or `enc` is not supported
+    UnsupportedAlgorithm(String),
+    /// The key can not be used with the key management algorithm
+    IncompatibleKey(KeyManagementAlgorithm),
+    /// The key could not be used
+    InvalidKey(String),
+    /// The content could not be decrypted or was tampered with
+    DecryptionFailed,
+    /// The nested token could not be verified
+    Verification(VerificationError),
+    /// The crypto library failed
+    CryptoError(String),
+}
+
+impl fmt::Display for JweError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JweError::Parse(ref err) => write!(f, "Unparsable JWE: {}", err),
+            JweError::Malformed(ref message) => write!(f, "Malformed JWE: {}", message),
+            JweError::UnsupportedAlgorithm(ref algorithm) => {
+                write!(f, "Unsupported algorithm: {}", algorithm)
+            }
+            JweError::IncompatibleKey(ref algorithm) => {
+                write!(f, "The key can not be used with algorithm {}", algorithm.to_key())
+            }
+            JweError::InvalidKey(ref message) => write!(f, "Invalid key: {}", message),
+            JweError::DecryptionFailed => write!(f, "Decryption failed"),
+            JweError::Verification(ref err) => write!(f, "Nested token: {}", err),
+            JweError::CryptoError(ref message) => write!(f, "Crypto error: {}", message),
+        }
+    }
+}
+
+impl Error for JweError {
+    fn description(&self) -> &str {
+        match *self {
+            JweError::Malformed(ref message) |
+            JweError::InvalidKey(ref message) |
+            JweError::CryptoError(ref message) => message.as_ref(),
+            JweError::UnsupportedAlgorithm(_) => "Unsupported algorithm",
+            JweError::IncompatibleKey(_) => "The key can not be used with the algorithm",
+            JweError::DecryptionFailed => "Decryption failed",
+            JweError::Parse(ref err) => err.description(),
+            JweError::Verification(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            JweError::Parse(ref err) => Some(err),
+            JweError::Verification(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<JwtParseError> for JweError {
+    fn from(err: JwtParseError) -> Self {
+        JweError::Parse(err)
+    }
+}
+
+impl From<ErrorStack> for JweError {
+    fn from(err: ErrorStack) -> Self {
+        JweError::CryptoError(format!("{}", err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use openssl::bn::{BigNum, BigNumContext};
+    use openssl::derive::Deriver;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::encrypt::Encrypter;
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::rand::rand_bytes;
+    use openssl::rsa::{Rsa, Padding};
+    use openssl::symm::{self, Cipher};
+    use rustc_serialize::base64::{ToBase64, URL_SAFE};
+    use jwt::{JsonWebToken, Header, RegisteredHeader, Claim, RegisteredClaim, VerificationError};
+    use jwt::signature::{SigningKey, SignatureError};
+    use rustc_serialize::json::Json;
+    use es256_fixture::{generate_key, generate_key_pair, verification_key};
+    use super::{JsonWebEncryption, DecryptionKey, JweError, KeyManagementAlgorithm, concat_kdf};
+
+    const CLAIMS: &'static str = "{\"sub\":\"test2\",\"iss\":\"B\"}";
+
+    fn encrypt(header: &str, encrypted_key: &[u8], cek: &[u8], plaintext: &[u8]) -> String {
+        let cipher = if cek.len() == 16 {
+            Cipher::aes_128_gcm()
+        } else {
+            Cipher::aes_256_gcm()
+        };
+        let encoded_header = header.as_bytes().to_base64(URL_SAFE);
+        let mut iv = [0u8; 12];
+        rand_bytes(&mut iv).unwrap();
+        let mut tag = [0u8; 16];
+        let ciphertext = symm::encrypt_aead(cipher,
+                                            cek,
+                                            Some(&iv),
+                                            encoded_header.as_bytes(),
+                                            plaintext,
+                                            &mut tag)
+            .unwrap();
+        format!("{}.{}.{}.{}.{}",
+                encoded_header,
+                encrypted_key.to_base64(URL_SAFE),
+                iv.to_base64(URL_SAFE),
+                ciphertext.to_base64(URL_SAFE),
+                tag.to_base64(URL_SAFE))
+    }
+
+    fn encrypt_rsa_oaep(key: &PKey<Private>, header: &str, cek: &[u8], plaintext: &[u8]) -> String {
+        let mut encrypter = Encrypter::new(key).unwrap();
+        encrypter.set_rsa_padding(Padding::PKCS1_OAEP).unwrap();
+        encrypter.set_rsa_oaep_md(MessageDigest::sha256()).unwrap();
+        encrypter.set_rsa_mgf1_md(MessageDigest::sha256()).unwrap();
+        let mut encrypted_key = vec![0u8; encrypter.encrypt_len(cek).unwrap()];
+        let len = encrypter.encrypt(cek, &mut encrypted_key).unwrap();
+        encrypted_key.truncate(len);
+        encrypt(header, &encrypted_key, cek, plaintext)
+    }
+
+    fn encrypt_ecdh_es(key: &EcKey<Private>, plaintext: &[u8]) -> String {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let ephemeral_key = EcKey::generate(&group).unwrap();
+        let mut ctx = BigNumContext::new().unwrap();
+        let mut x = BigNum::new().unwrap();
+        let mut y = BigNum::new().unwrap();
+        ephemeral_key.public_key()
+            .affine_coordinates_gfp(&group, &mut x, &mut y, &mut ctx)
+            .unwrap();
+        let ephemeral_pkey = PKey::from_ec_key(ephemeral_key).unwrap();
+        let recipient = PKey::from_ec_key(EcKey::from_public_key(&group, key.public_key())
+                .unwrap())
+            .unwrap();
+        let mut deriver = Deriver::new(&ephemeral_pkey).unwrap();
+        deriver.set_peer(&recipient).unwrap();
+        let cek = concat_kdf(&deriver.derive_to_vec().unwrap(), "A128GCM", b"Alice", b"Bob", 16);
+        let header = format!("{{\"alg\":\"ECDH-ES\",\"enc\":\"A128GCM\",\"apu\":\"{}\",\
+                              \"apv\":\"{}\",\"epk\":{{\"kty\":\"EC\",\"crv\":\"P-256\",\
+                              \"x\":\"{}\",\"y\":\"{}\"}}}}",
+                             b"Alice".to_base64(URL_SAFE),
+                             b"Bob".to_base64(URL_SAFE),
+                             x.to_vec().to_base64(URL_SAFE),
+                             y.to_vec().to_base64(URL_SAFE));
+        encrypt(&header, &[], &cek, plaintext)
+    }
+
+    fn rsa_key() -> PKey<Private> {
+        PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn the_concat_kdf_must_match_the_rfc_7518_example() {
+        // Appendix C of RFC 7518
+        let z = [158, 86, 217, 29, 129, 113, 53, 211, 114, 131, 66, 131, 191, 132, 38, 156, 251,
+                 49, 110, 163, 218, 128, 106, 72, 246, 218, 167, 121, 140, 254, 144, 196];
+
+        let key = concat_kdf(&z, "A128GCM", b"Alice", b"Bob", 16);
+
+        assert_eq!("VqqN6vgjbSBcIijNcacQGg", key.to_base64(URL_SAFE));
+    }
+
+    #[test]
+    fn an_rsa_oaep_256_token_must_be_decrypted() {
+        let key = rsa_key();
+        let token = encrypt_rsa_oaep(&key,
+                                     "{\"alg\":\"RSA-OAEP-256\",\"enc\":\"A256GCM\"}",
+                                     &[3u8; 32],
+                                     CLAIMS.as_bytes());
+
+        let result = JsonWebToken::decrypt_with(&token, &DecryptionKey::Rsa(key)).unwrap();
+
+        assert_eq!(Some(&Json::String(String::from("test2"))),
+                   result.get_registered_payload(RegisteredClaim::Subject));
+        assert_eq!(Some(&Json::String(String::from("A256GCM"))), result.header.get("enc"));
+    }
+
+    #[test]
+    fn an_ecdh_es_token_must_be_decrypted() {
+        let key = generate_key();
+        let token = encrypt_ecdh_es(&key, CLAIMS.as_bytes());
+
+        let result = JsonWebToken::decrypt_with(&token, &DecryptionKey::EcP256(key)).unwrap();
+
+        assert_eq!(Some(&Json::String(String::from("B"))),
+                   result.get_registered_payload(RegisteredClaim::Issuer));
+    }
+
+    #[test]
+    fn a_nested_token_must_be_decrypted_and_verified() {
+        let key = rsa_key();
+        let (signing_key, verification_key) = generate_key_pair();
+        let inner = JsonWebToken::new()
+            .add_header(&Header::Registered(RegisteredHeader::KeyId),
+                        Json::String(String::from("testkey-es256")))
+            .add_payload(&Claim::Registered(RegisteredClaim::Subject),
+                         Json::String(String::from("test2")));
+        let signed = inner.encode(&SigningKey::EcP256(signing_key)).unwrap();
+        let token = encrypt_rsa_oaep(&key,
+                                     "{\"alg\":\"RSA-OAEP-256\",\"enc\":\"A128GCM\",\
+                                      \"cty\":\"JWT\"}",
+                                     &[5u8; 16],
+                                     signed.as_bytes());
+        let decryption_key = DecryptionKey::Rsa(key);
+
+        let decrypted = JsonWebToken::decrypt_with(&token, &decryption_key).unwrap();
+        let verified = JsonWebToken::decrypt_and_verify_with(&token,
+                                                             &decryption_key,
+                                                             &verification_key)
+            .unwrap();
+
+        assert_eq!(Some(&Json::String(String::from("ES256"))),
+                   decrypted.get_registered_header(RegisteredHeader::Algorithm));
+        assert_eq!(decrypted, verified);
+    }
+
+    #[test]
+    fn a_nested_token_with_a_bad_signature_must_be_rejected() {
+        let key = rsa_key();
+        let signed = JsonWebToken::new().encode(&SigningKey::EcP256(generate_key())).unwrap();
+        let token = encrypt_rsa_oaep(&key,
+                                     "{\"alg\":\"RSA-OAEP-256\",\"enc\":\"A128GCM\",\
+                                      \"cty\":\"JWT\"}",
+                                     &[5u8; 16],
+                                     signed.as_bytes());
+
+        let result = JsonWebToken::decrypt_and_verify_with(&token,
+                                                           &DecryptionKey::Rsa(key),
+                                                           &verification_key(&generate_key()));
+
+        assert_eq!(Err(JweError::Verification(VerificationError::Signature(SignatureError::InvalidSignature))),
+                   result);
+    }
+
+    #[test]
+    fn a_tampered_ciphertext_must_be_rejected() {
+        let key = rsa_key();
+        let token = encrypt_rsa_oaep(&key,
+                                     "{\"alg\":\"RSA-OAEP-256\",\"enc\":\"A128GCM\"}",
+                                     &[3u8; 16],
+                                     CLAIMS.as_bytes());
+        let mut jwe = JsonWebEncryption::from_str(&token).unwrap();
+        jwe.ciphertext[0] ^= 1;
+
+        assert_eq!(Err(JweError::DecryptionFailed),
+                   jwe.decrypt(&DecryptionKey::Rsa(key)));
+    }
+
+    #[test]
+    fn a_truncated_tag_must_be_rejected() {
+        let key = rsa_key();
+        let token = encrypt_rsa_oaep(&key,
+                                     "{\"alg\":\"RSA-OAEP-256\",\"enc\":\"A128GCM\"}",
+                                     &[3u8; 16],
+                                     CLAIMS.as_bytes());
+        let mut jwe = JsonWebEncryption::from_str(&token).unwrap();
+        jwe.tag.truncate(1);
+
+        match jwe.decrypt(&DecryptionKey::Rsa(key)) {
+            Err(JweError::Malformed(_)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn an_iv_of_the_wrong_length_must_be_rejected() {
+        let key = rsa_key();
+        let token = encrypt_rsa_oaep(&key,
+                                     "{\"alg\":\"RSA-OAEP-256\",\"enc\":\"A128GCM\"}",
+                                     &[3u8; 16],
+                                     CLAIMS.as_bytes());
+        let mut jwe = JsonWebEncryption::from_str(&token).unwrap();
+        jwe.iv.push(0);
+
+        match jwe.decrypt(&DecryptionKey::Rsa(key)) {
+            Err(JweError::Malformed(_)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn a_key_of_the_wrong_type_must_be_rejected() {
+        let token = encrypt_rsa_oaep(&rsa_key(),
+                                     "{\"alg\":\"RSA-OAEP\",\"enc\":\"A128GCM\"}",
+                                     &[3u8; 16],
+                                     CLAIMS.as_bytes());
+
+        let result = JsonWebToken::decrypt_with(&token, &DecryptionKey::EcP256(generate_key()));
+
+        assert_eq!(Err(JweError::IncompatibleKey(KeyManagementAlgorithm::RsaOaep)),
+                   result);
+    }
+
+    #[test]
+    fn rsa_keys_below_2048_bits_must_be_rejected() {
+        let pem = Rsa::generate(1024).unwrap().private_key_to_pem().unwrap();
+
+        match DecryptionKey::rsa_from_pem(&pem) {
+            Err(JweError::InvalidKey(_)) => (),
+            Err(err) => panic!("Unexpected error {:?}", err),
+            Ok(_) => panic!("A 1024 bit key was accepted"),
+        }
+        let pem = rsa_key().private_key_to_pem_pkcs8().unwrap();
+        assert!(DecryptionKey::rsa_from_pem(&pem).is_ok());
+    }
+
+    #[test]
+    fn a_jws_must_not_be_parsed_as_a_jwe() {
+        assert!(JsonWebEncryption::from_str("eyJhbGciOiJub25lIn0.e30.").is_err());
+    }
+}
@@ -0,0 +1,469 @@
+//! [JSON Web Keys](https://tools.ietf.org/html/rfc7517)
+//!
+//! A `JwkSet` is what a provider like [Plan B](https://github.com/zalando/planb-provider)
+//! publishes to let resource servers verify the signatures of its tokens.
+//! The key to use for a token is looked up by the `kid` header of the token.
+//!
+//! A key can be identified independently of its `kid` by its
+//! [thumbprint](https://tools.ietf.org/html/rfc7638).
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use openssl::sha::sha256;
+use std::collections::BTreeMap;
+use rustc_serialize::base64::{FromBase64, ToBase64, STANDARD, URL_SAFE};
+use rustc_serialize::json::{Json, Object};
+use super::decode_base_64_bytes;
+use super::signature::{VerificationKey, SignatureError};
+
+/// The key material of a `JsonWebKey` as given by its `kty` parameter.
+#[derive(PartialEq, Debug, Clone)]
+pub enum KeyType {
+    /// An elliptic curve public key (`"kty":"EC"`)
+    Ec {
+        /// The curve, e.g. `P-256`
+        curve: String,
+        /// The x coordinate of the public point
+        x: Vec<u8>,
+        /// The y coordinate of the public point
+        y: Vec<u8>,
+    },
+    /// An RSA public key (`"kty":"RSA"`)
+    Rsa {
+        /// The modulus
+        n: Vec<u8>,
+        /// The public exponent
+        e: Vec<u8>,
+    },
+    /// A symmetric key (`"kty":"oct"`)
+    Oct {
+        /// The key value
+        k: Vec<u8>,
+    },
+}
+
+impl KeyType {
+    /// The value of the `kty` parameter for this key type
+    pub fn to_key(&self) -> &str {
+        match *self {
+            KeyType::Ec { .. } => "EC",
+            KeyType::Rsa { .. } => "RSA",
+            KeyType::Oct { .. } => "oct",
+        }
+    }
+}
+
+/// A [JSON Web Key](https://tools.ietf.org/html/rfc7517#section-4)
+#[derive(PartialEq, Debug, Clone)]
+pub struct JsonWebKey {
+    /// The `kid` parameter used to match the key with the `kid` header of a token
+    pub key_id: Option<String>,
+    /// The `use` parameter, e.g. `sig`
+    pub key_use: Option<String>,
+    /// The `alg` parameter. The algorithm the key is intended to be used with.
+    pub algorithm: Option<String>,
+    /// The `x5t` parameter. The SHA-1 thumbprint of the X.509 certificate of the key.
+    pub x509_sha1_thumbprint: Option<Vec<u8>>,
+    /// The `x5c` parameter. The DER encoded X.509 certificate chain of the key,
+    /// starting with the certificate of the key.
+    pub x509_chain: Vec<Vec<u8>>,
+    /// The key material
+    pub key_type: KeyType,
+}
+
+impl JsonWebKey {
+    /// Creates a new key without any optional parameters.
+    pub fn new(key_type: KeyType) -> JsonWebKey {
+        JsonWebKey {
+            key_id: None,
+            key_use: None,
+            algorithm: None,
+            x509_sha1_thumbprint: None,
+            x509_chain: Vec::new(),
+            key_type: key_type,
+        }
+    }
+
+    /// Builder method. Set the `kid`.
+    pub fn with_key_id<T: Into<String>>(self, key_id: T) -> Self {
+        let mut x = self;
+        x.key_id = Some(key_id.into());
+        x
+    }
+
+    /// Parses a key from a JSON object.
+    pub fn from_json(json: &Json) -> Result<JsonWebKey, JwkError> {
+        let obj = try!{json.as_object()
+            .ok_or_else(|| JwkError::Malformed(String::from("A JWK must be a JSON object.")))};
+        let kty = try!{get_string(obj, "kty")};
+        let key_type = match kty {
+            "EC" => {
+                KeyType::Ec {
+                    curve: try!{get_string(obj, "crv")}.to_owned(),
+                    x: try!{get_bytes(obj, "x")},
+                    y: try!{get_bytes(obj, "y")},
+                }
+            }
+            "RSA" => {
+                KeyType::Rsa {
+                    n: try!{get_bytes(obj, "n")},
+                    e: try!{get_bytes(obj, "e")},
+                }
+            }
+            "oct" => KeyType::Oct { k: try!{get_bytes(obj, "k")} },
+            unknown => return Err(JwkError::UnsupportedKeyType(unknown.to_owned())),
+        };
+        Ok(JsonWebKey {
+            key_id: try!{get_optional_string(obj, "kid")},
+            key_use: try!{get_optional_string(obj, "use")},
+            algorithm: try!{get_optional_string(obj, "alg")},
+            x509_sha1_thumbprint: match obj.get("x5t") {
+                None => None,
+                Some(_) => Some(try!{get_bytes(obj, "x5t")}),
+            },
+            x509_chain: try!{get_certificate_chain(obj)},
+            key_type: key_type,
+        })
+    }
+
+    /// The key as a JSON object, e.g. to publish it in a `JwkSet`.
+    pub fn to_json(&self) -> Json {
+        let mut obj = BTreeMap::new();
+        let mut insert = |field: &str, value: String| obj.insert(String::from(field), Json::String(value));
+        insert("kty", String::from(self.key_type.to_key()));
+        match self.key_type {
+            KeyType::Ec { ref curve, ref x, ref y } => {
+                insert("crv", curve.clone());
+                insert("x", x.to_base64(URL_SAFE));
+                insert("y", y.to_base64(URL_SAFE));
+            }
+            KeyType::Rsa { ref n, ref e } => {
+                insert("n", n.to_base64(URL_SAFE));
+                insert("e", e.to_base64(URL_SAFE));
+            }
+            KeyType::Oct { ref k } => {
+                insert("k", k.to_base64(URL_SAFE));
+            }
+        }
+        if let Some(ref key_id) = self.key_id {
+            insert("kid", key_id.clone());
+        }
+        if let Some(ref key_use) = self.key_use {
+            insert("use", key_use.clone());
+        }
+        if let Some(ref algorithm) = self.algorithm {
+            insert("alg", algorithm.clone());
+        }
+        if let Some(ref thumbprint) = self.x509_sha1_thumbprint {
+            insert("x5t", thumbprint.to_base64(URL_SAFE));
+        }
+        if !self.x509_chain.is_empty() {
+            let chain = self.x509_chain
+                .iter()
+                .map(|certificate| Json::String(certificate.to_base64(STANDARD)))
+                .collect();
+            obj.insert(String::from("x5c"), Json::Array(chain));
+        }
+        Json::Object(obj)
+    }
+
+    /// The SHA-256 [thumbprint](https://tools.ietf.org/html/rfc7638) of the key.
+    ///
+    /// Only the required members of the key type are hashed, so the thumbprint
+    /// does not change with the `kid` or any other optional parameter.
+    pub fn thumbprint_sha256(&self) -> Vec<u8> {
+        let canonical = match self.key_type {
+            KeyType::Ec { ref curve, ref x, ref y } => {
+                format!("{{\"crv\":\"{}\",\"kty\":\"EC\",\"x\":\"{}\",\"y\":\"{}\"}}",
+                        curve,
+                        x.to_base64(URL_SAFE),
+                        y.to_base64(URL_SAFE))
+            }
+            KeyType::Rsa { ref n, ref e } => {
+                format!("{{\"e\":\"{}\",\"kty\":\"RSA\",\"n\":\"{}\"}}",
+                        e.to_base64(URL_SAFE),
+                        n.to_base64(URL_SAFE))
+            }
+            KeyType::Oct { ref k } => {
+                format!("{{\"k\":\"{}\",\"kty\":\"oct\"}}", k.to_base64(URL_SAFE))
+            }
+        };
+        sha256(canonical.as_bytes()).to_vec()
+    }
+
+    /// The base64url encoded SHA-256 thumbprint. Suitable to pin or log a key.
+    pub fn thumbprint(&self) -> String {
+        self.thumbprint_sha256().to_base64(URL_SAFE)
+    }
+
+    /// Creates a `VerificationKey` that can be used to verify the signature of a token.
+    pub fn to_verification_key(&self) -> Result<VerificationKey, SignatureError> {
+        match self.key_type {
+            KeyType::Ec { ref curve, ref x, ref y } if curve == "P-256" => {
+                VerificationKey::ec_p256_from_coordinates(x, y)
+            }
+            KeyType::Ec { ref curve, .. } => {
+                Err(SignatureError::InvalidKey(format!("Unsupported curve {}.", curve)))
+            }
+            KeyType::Rsa { ref n, ref e } => VerificationKey::rsa_from_components(n, e),
+            KeyType::Oct { ref k } => VerificationKey::hmac_from_secret(k),
+        }
+    }
+}
+
+impl FromStr for JsonWebKey {
+    type Err = JwkError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let json = try!{Json::from_str(s).map_err(|err| JwkError::Malformed(err.to_string()))};
+        JsonWebKey::from_json(&json)
+    }
+}
+
+/// A [JWK Set](https://tools.ietf.org/html/rfc7517#section-5)
+#[derive(PartialEq, Debug, Clone)]
+pub struct JwkSet {
+    pub keys: Vec<JsonWebKey>,
+}
+
+impl JwkSet {
+    /// Creates a new instance
+    pub fn new(keys: Vec<JsonWebKey>) -> JwkSet {
+        JwkSet { keys: keys }
+    }
+
+    /// Looks up the key with the given `kid`.
+    pub fn find_key(&self, key_id: &str) -> Option<&JsonWebKey> {
+        self.keys.iter().find(|key| key.key_id.as_ref().map_or(false, |kid| kid == key_id))
+    }
+
+    /// The set as a JSON object with a `keys` array.
+    pub fn to_json(&self) -> Json {
+        let mut obj = BTreeMap::new();
+        obj.insert(String::from("keys"),
+                   Json::Array(self.keys.iter().map(|key| key.to_json()).collect()));
+        Json::Object(obj)
+    }
+
+    /// Parses a set from a JSON object.
+    ///
+    /// Keys of an unsupported type are skipped as required by the RFC.
+    pub fn from_json(json: &Json) -> Result<JwkSet, JwkError> {
+        let keys_json = try!{json.find("keys")
+            .and_then(|keys| keys.as_array())
+            .ok_or_else(|| JwkError::Malformed(String::from("Field 'keys' is missing or not an array.")))};
+        let mut keys = Vec::new();
+        for key_json in keys_json {
+            match JsonWebKey::from_json(key_json) {
+                Ok(key) => keys.push(key),
+                Err(JwkError::UnsupportedKeyType(kty)) => {
+                    warn!("Skipping key of unsupported type '{}'.", kty)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(JwkSet { keys: keys })
+    }
+}
+
+impl FromStr for JwkSet {
+    type Err = JwkError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let json = try!{Json::from_str(s).map_err(|err| JwkError::Malformed(err.to_string()))};
+        JwkSet::from_json(&json)
+    }
+}
+
+fn get_string<'a>(obj: &'a Object, field: &str) -> Result<&'a str, JwkError> {
+    obj.get(field)
+        .and_then(|json| json.as_string())
+        .ok_or_else(|| JwkError::Malformed(format!("Field '{}' is missing or not a String.", field)))
+}
+
+fn get_optional_string(obj: &Object, field: &str) -> Result<Option<String>, JwkError> {
+    match obj.get(field) {
+        None => Ok(None),
+        Some(&Json::String(ref value)) => Ok(Some(value.clone())),
+        Some(_) => Err(JwkError::Malformed(format!("Field '{}' is not a String.", field))),
+    }
+}
+
+/// The certificates of `x5c` are encoded with standard base64, not base64url.
+fn get_certificate_chain(obj: &Object) -> Result<Vec<Vec<u8>>, JwkError> {
+    let encoded_chain = match obj.get("x5c") {
+        None => return Ok(Vec::new()),
+        Some(&Json::Array(ref encoded_chain)) => encoded_chain,
+        Some(_) => return Err(JwkError::Malformed(String::from("Field 'x5c' is not an array."))),
+    };
+    let mut chain = Vec::new();
+    for encoded in encoded_chain {
+        let certificate = try!{encoded.as_string()
+            .ok_or_else(|| JwkError::Malformed(String::from("Element in 'x5c' not a String.")))
+            .and_then(|x| x.from_base64()
+                .map_err(|err| JwkError::Malformed(format!("Field 'x5c': {}", err))))};
+        chain.push(certificate);
+    }
+    Ok(chain)
+}
+
+fn get_bytes(obj: &Object, field: &str) -> Result<Vec<u8>, JwkError> {
+    let encoded = try!{get_string(obj, field)};
+    decode_base_64_bytes(encoded)
+        .map_err(|err| JwkError::Malformed(format!("Field '{}': {}", field, err)))
+}
+
+/// Errors that can occur when parsing a `JsonWebKey` or a `JwkSet`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JwkError {
+    /// The JSON is not a valid key
+    Malformed(String),
+    /// The `kty` of the key is not supported
+    UnsupportedKeyType(String),
+}
+
+impl fmt::Display for JwkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JwkError::Malformed(ref message) => write!(f, "Malformed JWK: {}", message),
+            JwkError::UnsupportedKeyType(ref kty) => write!(f, "Unsupported key type: {}", kty),
+        }
+    }
+}
+
+impl Error for JwkError {
+    fn description(&self) -> &str {
+        match *self {
+            JwkError::Malformed(ref message) => message.as_ref(),
+            JwkError::UnsupportedKeyType(_) => "Unsupported key type",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::{JsonWebKey, JwkSet, KeyType, JwkError};
+
+    const EC_JWK: &'static str = "{\"kty\":\"EC\",\"crv\":\"P-256\",\"kid\":\"testkey-es256\",\
+                                  \"use\":\"sig\",\"alg\":\"ES256\",\
+                                  \"x\":\"AQID\",\"y\":\"BAUG\"}";
+    const RSA_JWK: &'static str = "{\"kty\":\"RSA\",\"kid\":\"testkey-rs256\",\
+                                   \"n\":\"AQID\",\"e\":\"AQAB\"}";
+    const OCT_JWK: &'static str = "{\"kty\":\"oct\",\"k\":\"c2VjcmV0\"}";
+
+    #[test]
+    fn an_ec_key_must_be_parsed() {
+        let expected = JsonWebKey {
+            key_id: Some(String::from("testkey-es256")),
+            key_use: Some(String::from("sig")),
+            algorithm: Some(String::from("ES256")),
+            x509_sha1_thumbprint: None,
+            x509_chain: Vec::new(),
+            key_type: KeyType::Ec {
+                curve: String::from("P-256"),
+                x: vec![1, 2, 3],
+                y: vec![4, 5, 6],
+            },
+        };
+
+        assert_eq!(Ok(expected), JsonWebKey::from_str(EC_JWK));
+    }
+
+    #[test]
+    fn an_rsa_key_must_be_parsed() {
+        let expected = JsonWebKey::new(KeyType::Rsa {
+                n: vec![1, 2, 3],
+                e: vec![1, 0, 1],
+            })
+            .with_key_id("testkey-rs256");
+
+        assert_eq!(Ok(expected), JsonWebKey::from_str(RSA_JWK));
+    }
+
+    #[test]
+    fn a_symmetric_key_must_be_parsed() {
+        let expected = JsonWebKey::new(KeyType::Oct { k: b"secret".to_vec() });
+
+        assert_eq!(Ok(expected), JsonWebKey::from_str(OCT_JWK));
+    }
+
+    #[test]
+    fn a_key_with_a_missing_field_must_be_rejected() {
+        let result = JsonWebKey::from_str("{\"kty\":\"RSA\",\"n\":\"AQID\"}");
+
+        assert_eq!(Err(JwkError::Malformed(String::from("Field 'e' is missing or not a String."))),
+                   result);
+    }
+
+    #[test]
+    fn a_key_set_must_be_parsed_and_skip_unsupported_keys() {
+        let json = format!("{{\"keys\":[{},{{\"kty\":\"OKP\",\"crv\":\"Ed25519\"}},{}]}}",
+                           EC_JWK,
+                           RSA_JWK);
+
+        let key_set = JwkSet::from_str(&json).unwrap();
+
+        assert_eq!(2, key_set.keys.len());
+    }
+
+    #[test]
+    fn a_key_must_be_found_by_its_id() {
+        let json = format!("{{\"keys\":[{},{},{}]}}", EC_JWK, RSA_JWK, OCT_JWK);
+        let key_set = JwkSet::from_str(&json).unwrap();
+
+        assert_eq!(Some(&JsonWebKey::from_str(RSA_JWK).unwrap()),
+                   key_set.find_key("testkey-rs256"));
+        assert_eq!(None, key_set.find_key("unknown"));
+    }
+
+    #[test]
+    fn the_thumbprint_must_match_the_rfc_7638_example() {
+        let jwk = JsonWebKey::from_str("{\"kty\":\"RSA\",\"n\":\"0vx7agoebGcQSuuPiLJXZptN9nndrQmbX\
+                                        Eps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_\
+                                        BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArw\
+                                        l93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaS\
+                                        qzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLy\
+                                        rdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIq\
+                                        bw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw\",\
+                                        \"e\":\"AQAB\",\"alg\":\"RS256\",\
+                                        \"kid\":\"2011-04-29\"}")
+            .unwrap();
+
+        assert_eq!("NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs", jwk.thumbprint());
+    }
+
+    #[test]
+    fn the_thumbprint_must_not_depend_on_optional_parameters() {
+        let key = JsonWebKey::from_str(EC_JWK).unwrap();
+        let other = JsonWebKey::new(key.key_type.clone());
+
+        assert_eq!(key.thumbprint(), other.thumbprint());
+        assert!(key.thumbprint() != JsonWebKey::from_str(RSA_JWK).unwrap().thumbprint());
+    }
+
+    #[test]
+    fn x509_parameters_must_be_parsed() {
+        let json = "{\"kty\":\"oct\",\"k\":\"c2VjcmV0\",\"x5t\":\"AQID\",\
+                    \"x5c\":[\"BAUG\",\"+/8=\"]}";
+
+        let key = JsonWebKey::from_str(json).unwrap();
+
+        assert_eq!(Some(vec![1, 2, 3]), key.x509_sha1_thumbprint);
+        assert_eq!(vec![vec![4, 5, 6], vec![0xfb, 0xff]], key.x509_chain);
+    }
+
+    #[test]
+    fn a_key_set_must_survive_a_round_trip_through_json() {
+        let x509_key = JsonWebKey::from_str("{\"kty\":\"oct\",\"k\":\"c2VjcmV0\",\"x5t\":\"AQID\",\
+                                             \"x5c\":[\"BAUG\",\"+/8=\"]}")
+            .unwrap();
+        let key_set = JwkSet::new(vec![JsonWebKey::from_str(EC_JWK).unwrap(),
+                                       JsonWebKey::from_str(RSA_JWK).unwrap(),
+                                       x509_key]);
+
+        assert_eq!(Ok(key_set.clone()), JwkSet::from_json(&key_set.to_json()));
+    }
+}
@@ -0,0 +1,192 @@
+//! The [JWS JSON serialization](https://tools.ietf.org/html/rfc7515#section-7.2)
+//!
+//! Both the general syntax with a `signatures` array and the flattened syntax
+//! with a single signature are supported. Use `JwtVerifier::verify_json` to
+//! verify a token in JSON serialization.
+use std::collections::HashMap;
+use std::str::FromStr;
+use rustc_serialize::json::{Json, Object};
+use super::{JsonWebToken, JwtParseError, Segment, decode_base_64_string, decode_segment,
+            parse_json_str_to_json_map};
+
+/// One signature of a JWS in JSON serialization
+#[derive(PartialEq, Debug)]
+pub struct JwsJsonSignature {
+    /// The integrity protected header fields
+    pub protected: HashMap<String, Json>,
+    /// The unprotected header fields
+    pub header: HashMap<String, Json>,
+    /// The decoded signature
+    pub signature: Vec<u8>,
+    encoded_protected: String,
+}
+
+impl JwsJsonSignature {
+    /// The protected and the unprotected header fields
+    pub fn joint_header(&self) -> HashMap<String, Json> {
+        let mut joint_header = self.header.clone();
+        for (k, v) in &self.protected {
+            joint_header.insert(k.clone(), v.clone());
+        }
+        joint_header
+    }
+
+    fn from_json(obj: &Object) -> Result<JwsJsonSignature, JwtParseError> {
+        let (protected, encoded_protected) = match obj.get("protected") {
+            None => (HashMap::new(), String::new()),
+            Some(&Json::String(ref encoded)) => {
+                let decoded = try!{decode_base_64_string(Segment::Header, encoded)};
+                (try!{parse_json_str_to_json_map(Segment::Header, &decoded)}, encoded.clone())
+            }
+            Some(_) => return Err(invalid("Member 'protected' is not a String.")),
+        };
+        let header = match obj.get("header") {
+            None => HashMap::new(),
+            Some(&Json::Object(ref header)) => {
+                header.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+            }
+            Some(_) => return Err(invalid("Member 'header' is not an object.")),
+        };
+        if protected.keys().any(|k| header.contains_key(k)) {
+            return Err(invalid("The protected and the unprotected header must be disjoint."));
+        }
+        let signature = match obj.get("signature") {
+            Some(&Json::String(ref encoded)) => try!{decode_segment(Segment::Signature, encoded)},
+            _ => return Err(invalid("Member 'signature' is missing or not a String.")),
+        };
+        Ok(JwsJsonSignature {
+            protected: protected,
+            header: header,
+            signature: signature,
+            encoded_protected: encoded_protected,
+        })
+    }
+}
+
+/// A JWS in JSON serialization with one or more signatures over the same payload
+#[derive(PartialEq, Debug)]
+pub struct JwsJson {
+    /// The payload fields
+    pub payload: HashMap<String, Json>,
+    /// The signatures in the order they were given
+    pub signatures: Vec<JwsJsonSignature>,
+    encoded_payload: String,
+}
+
+impl JwsJson {
+    /// The token as seen by the given signature
+    pub fn to_token(&self, signature: &JwsJsonSignature) -> JsonWebToken {
+        JsonWebToken {
+            header: signature.joint_header(),
+            payload: self.payload.clone(),
+        }
+    }
+
+    /// The data that was signed by the given signature
+    pub fn signing_input(&self, signature: &JwsJsonSignature) -> String {
+        format!("{}.{}", signature.encoded_protected, self.encoded_payload)
+    }
+}
+
+impl FromStr for JwsJson {
+    type Err = JwtParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let json = try!{Json::from_str(s).map_err(|err| invalid(&err.to_string()))};
+        let obj = try!{json.as_object().ok_or_else(|| invalid("Not a JSON object."))};
+        let encoded_payload = try!{obj.get("payload")
+            .and_then(|json| json.as_string())
+            .ok_or_else(|| invalid("Member 'payload' is missing or not a String."))};
+        let payload_str = try!{decode_base_64_string(Segment::Payload, encoded_payload)};
+        let payload = try!{parse_json_str_to_json_map(Segment::Payload, &payload_str)};
+
+        let signatures = match (obj.get("signatures"), obj.contains_key("signature")) {
+            (Some(&Json::Array(ref entries)), false) if !entries.is_empty() => {
+                let mut signatures = Vec::new();
+                for entry in entries {
+                    let entry = try!{entry.as_object()
+                        .ok_or_else(|| invalid("Element in 'signatures' not an object."))};
+                    signatures.push(try!{JwsJsonSignature::from_json(entry)});
+                }
+                signatures
+            }
+            (None, true) => vec![try!{JwsJsonSignature::from_json(obj)}],
+            (None, false) => return Err(invalid("Member 'signatures' or 'signature' is missing.")),
+            _ => {
+                return Err(invalid("Either a non-empty array 'signatures' or a single \
+                                    'signature' must be given."))
+            }
+        };
+
+        Ok(JwsJson {
+            payload: payload,
+            signatures: signatures,
+            encoded_payload: encoded_payload.to_owned(),
+        })
+    }
+}
+
+fn invalid(message: &str) -> JwtParseError {
+    JwtParseError::InvalidJsonSerialization(message.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use rustc_serialize::json::Json;
+    use jwt::{JwtParseError, RegisteredClaim};
+    use super::JwsJson;
+
+    // {"alg":"ES256"}, {"kid":"testkey"}, {"sub":"test2"}
+    const GENERAL: &'static str = "{\"payload\":\"eyJzdWIiOiJ0ZXN0MiJ9\",\"signatures\":[\
+                                   {\"protected\":\"eyJhbGciOiJFUzI1NiJ9\",\
+                                   \"header\":{\"kid\":\"testkey\"},\"signature\":\"AQID\"},\
+                                   {\"header\":{\"alg\":\"RS256\",\"kid\":\"other\"},\
+                                   \"signature\":\"BAUG\"}]}";
+    const FLATTENED: &'static str = "{\"payload\":\"eyJzdWIiOiJ0ZXN0MiJ9\",\
+                                     \"protected\":\"eyJhbGciOiJFUzI1NiJ9\",\
+                                     \"header\":{\"kid\":\"testkey\"},\"signature\":\"AQID\"}";
+
+    #[test]
+    fn the_general_syntax_must_be_parsed() {
+        let jws = JwsJson::from_str(GENERAL).unwrap();
+
+        assert_eq!(2, jws.signatures.len());
+        assert_eq!(vec![1, 2, 3], jws.signatures[0].signature);
+        assert_eq!("eyJhbGciOiJFUzI1NiJ9.eyJzdWIiOiJ0ZXN0MiJ9",
+                   jws.signing_input(&jws.signatures[0]));
+        assert_eq!(".eyJzdWIiOiJ0ZXN0MiJ9", jws.signing_input(&jws.signatures[1]));
+
+        let token = jws.to_token(&jws.signatures[0]);
+        assert_eq!(Some(&Json::String(String::from("ES256"))), token.header.get("alg"));
+        assert_eq!(Some(&Json::String(String::from("testkey"))), token.header.get("kid"));
+        assert_eq!(Some(&Json::String(String::from("test2"))),
+                   token.get_registered_payload(RegisteredClaim::Subject));
+    }
+
+    #[test]
+    fn the_flattened_syntax_must_be_parsed_like_a_single_general_signature() {
+        let flattened = JwsJson::from_str(FLATTENED).unwrap();
+        let general = JwsJson::from_str(GENERAL).unwrap();
+
+        assert_eq!(1, flattened.signatures.len());
+        assert_eq!(general.signatures[0], flattened.signatures[0]);
+        assert_eq!(general.payload, flattened.payload);
+    }
+
+    #[test]
+    fn overlapping_headers_must_be_rejected() {
+        let overlapping = FLATTENED.replace("\"kid\":\"testkey\"", "\"alg\":\"none\"");
+
+        assert!(JwsJson::from_str(&overlapping).is_err());
+    }
+
+    #[test]
+    fn mixing_both_syntaxes_must_be_rejected() {
+        let mixed = GENERAL.replace("{\"payload\"", "{\"signature\":\"AQID\",\"payload\"");
+
+        match JwsJson::from_str(&mixed) {
+            Err(JwtParseError::InvalidJsonSerialization(_)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! A borrowed view of a token in compact serialization.
+//!
+//! Parsing a `JwtRef` only splits the token. Header fields and claims are decoded
+//! on demand, and only the requested value is built while the rest of the segment
+//! is merely checked to be valid JSON. Each segment is base64 decoded at most once.
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use rustc_serialize::Decodable;
+use rustc_serialize::json::{self, Json, JsonEvent, Parser, StackElement};
+use super::{Header, Claim, JsonWebToken, JwtParseError, Segment, split_segments, decode_segment,
+            decode_base_64_string, parse_json_str_to_json_map};
+
+/// The JSON of a segment once it has been decoded
+type DecodedSegment = RefCell<Option<Result<String, JwtParseError>>>;
+
+/// A token in compact serialization that borrows its segments
+#[derive(Clone)]
+pub struct JwtRef<'a> {
+    token: &'a str,
+    header: &'a str,
+    payload: &'a str,
+    signature: &'a str,
+    decoded_header: DecodedSegment,
+    decoded_payload: DecodedSegment,
+}
+
+impl<'a> JwtRef<'a> {
+    /// Splits a token into its segments without decoding them.
+    pub fn parse(token: &'a str) -> Result<JwtRef<'a>, JwtParseError> {
+        let (header, payload, signature) = try!{split_segments(token)};
+        Ok(JwtRef {
+            token: token,
+            header: header,
+            payload: payload,
+            signature: signature,
+            decoded_header: RefCell::new(None),
+            decoded_payload: RefCell::new(None),
+        })
+    }
+
+    /// The encoded header and payload, i.e. the data that was signed
+    pub fn signing_input(&self) -> &'a str {
+        &self.token[..self.header.len() + 1 + self.payload.len()]
+    }
+
+    /// The decoded signature
+    pub fn signature(&self) -> Result<Vec<u8>, JwtParseError> {
+        decode_segment(Segment::Signature, self.signature)
+    }
+
+    /// Decodes a single header field.
+    pub fn get_header(&self, header: &Header) -> Result<Option<Json>, JwtParseError> {
+        let name = match *header {
+            Header::Registered(ref header) => header.to_key(),
+            Header::Custom(name) => name,
+        };
+        self.with_decoded(Segment::Header,
+                          |json_str| find_member(Segment::Header, json_str, name))
+    }
+
+    /// Decodes a single claim.
+    pub fn get_payload(&self, for_claim: &Claim) -> Result<Option<Json>, JwtParseError> {
+        let name = match *for_claim {
+            Claim::Registered(ref claim) => claim.to_key(),
+            Claim::Custom(name) => name,
+        };
+        self.with_decoded(Segment::Payload,
+                          |json_str| find_member(Segment::Payload, json_str, name))
+    }
+
+    /// Decodes the payload into a struct, e.g. one that derives `RustcDecodable`.
+    pub fn decode_claims<T: Decodable>(&self) -> Result<T, JwtParseError> {
+        self.with_decoded(Segment::Payload, |payload_str| {
+            json::decode(payload_str)
+                .map_err(|err| JwtParseError::InvalidJson(Segment::Payload, err.to_string()))
+        })
+    }
+
+    /// Decodes all segments into an owned `JsonWebToken`.
+    pub fn to_json_web_token(&self) -> Result<JsonWebToken, JwtParseError> {
+        try!{self.signature()};
+        let header = try!{self.with_decoded(Segment::Header, |header_str| {
+            parse_json_str_to_json_map(Segment::Header, header_str)
+        })};
+        let payload = try!{self.with_decoded(Segment::Payload, |payload_str| {
+            parse_json_str_to_json_map(Segment::Payload, payload_str)
+        })};
+        Ok(JsonWebToken {
+            header: header,
+            payload: payload,
+        })
+    }
+
+    /// Calls `f` with the JSON of the header or the payload, which is decoded on first use.
+    fn with_decoded<F, T>(&self, segment: Segment, f: F) -> Result<T, JwtParseError>
+        where F: FnOnce(&str) -> Result<T, JwtParseError>
+    {
+        let (decoded, encoded) = match segment {
+            Segment::Header => (&self.decoded_header, self.header),
+            _ => (&self.decoded_payload, self.payload),
+        };
+        let mut decoded = decoded.borrow_mut();
+        match *decoded.get_or_insert_with(|| decode_base_64_string(segment, encoded)) {
+            Ok(ref json_str) => f(json_str),
+            Err(ref err) => Err(err.clone()),
+        }
+    }
+}
+
+impl<'a> PartialEq for JwtRef<'a> {
+    fn eq(&self, other: &JwtRef<'a>) -> bool {
+        self.token == other.token
+    }
+}
+
+impl<'a> fmt::Debug for JwtRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "JwtRef({})", self.token)
+    }
+}
+
+/// Looks up the top level member `name` of the JSON object in `json_str`.
+///
+/// Like `Json::from_str` the last of duplicate members wins.
+fn find_member(segment: Segment,
+               json_str: &str,
+               name: &str)
+               -> Result<Option<Json>, JwtParseError> {
+    let invalid = |message: String| JwtParseError::InvalidJson(segment, message);
+    let mut parser = Parser::new(json_str.chars());
+    match parser.next() {
+        Some(JsonEvent::ObjectStart) => (),
+        Some(JsonEvent::Error(err)) => return Err(invalid(err.to_string())),
+        _ => return Err(JwtParseError::NotAnObject(segment)),
+    }
+    let mut found = None;
+    loop {
+        let event = match parser.next() {
+            Some(JsonEvent::ObjectEnd) if parser.stack().is_empty() => break,
+            Some(JsonEvent::Error(err)) => return Err(invalid(err.to_string())),
+            Some(event) => event,
+            None => return Err(invalid(String::from("Unexpected end of the object"))),
+        };
+        if parser.stack().top() == Some(StackElement::Key(name)) {
+            found = Some(try!{build_value(event, &mut parser).map_err(&invalid)});
+        } else {
+            try!{skip_value(event, &mut parser).map_err(&invalid)};
+        }
+    }
+    match parser.next() {
+        None => Ok(found),
+        Some(JsonEvent::Error(err)) => Err(invalid(err.to_string())),
+        Some(_) => Err(invalid(String::from("Trailing characters"))),
+    }
+}
+
+/// Consumes the events of the value that starts with `event` without building it.
+fn skip_value<T: Iterator<Item = char>>(event: JsonEvent,
+                                        parser: &mut Parser<T>)
+                                        -> Result<(), String> {
+    let mut depth = 0usize;
+    let mut event = event;
+    loop {
+        match event {
+            JsonEvent::ArrayStart | JsonEvent::ObjectStart => depth += 1,
+            JsonEvent::ArrayEnd | JsonEvent::ObjectEnd => {
+                if depth == 0 {
+                    return Err(String::from("Unexpected end of a value"));
+                }
+                depth -= 1;
+            }
+            JsonEvent::Error(err) => return Err(err.to_string()),
+            _ => (),
+        }
+        if depth == 0 {
+            return Ok(());
+        }
+        event = match parser.next() {
+            Some(event) => event,
+            None => return Err(String::from("Unexpected end of a value")),
+        };
+    }
+}
+
+/// Builds the value that starts with `event` from the following events of `parser`.
+fn build_value<T: Iterator<Item = char>>(event: JsonEvent,
+                                         parser: &mut Parser<T>)
+                                         -> Result<Json, String> {
+    match event {
+        JsonEvent::NullValue => Ok(Json::Null),
+        JsonEvent::BooleanValue(b) => Ok(Json::Boolean(b)),
+        JsonEvent::I64Value(n) => Ok(Json::I64(n)),
+        JsonEvent::U64Value(n) => Ok(Json::U64(n)),
+        JsonEvent::F64Value(n) => Ok(Json::F64(n)),
+        JsonEvent::StringValue(s) => Ok(Json::String(s)),
+        JsonEvent::ArrayStart => {
+            let mut array = Vec::new();
+            loop {
+                match parser.next() {
+                    Some(JsonEvent::ArrayEnd) => return Ok(Json::Array(array)),
+                    Some(event) => array.push(try!{build_value(event, parser)}),
+                    None => return Err(String::from("Unexpected end of an array")),
+                }
+            }
+        }
+        JsonEvent::ObjectStart => {
+            let mut object = BTreeMap::new();
+            loop {
+                match parser.next() {
+                    Some(JsonEvent::ObjectEnd) => return Ok(Json::Object(object)),
+                    Some(event) => {
+                        let key = match parser.stack().top() {
+                            Some(StackElement::Key(key)) => key.to_owned(),
+                            _ => return Err(String::from("Member without a key")),
+                        };
+                        object.insert(key, try!{build_value(event, parser)});
+                    }
+                    None => return Err(String::from("Unexpected end of an object")),
+                }
+            }
+        }
+        JsonEvent::Error(err) => Err(err.to_string()),
+        JsonEvent::ArrayEnd | JsonEvent::ObjectEnd => Err(String::from("Unexpected end of a value")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use rustc_serialize::base64::{ToBase64, URL_SAFE};
+    use rustc_serialize::json::Json;
+    use jwt::{JsonWebToken, JwtParseError, Header, RegisteredHeader, Claim, RegisteredClaim, Segment};
+    use super::JwtRef;
+
+    const SAMPLE_TOKEN: &'static str = "eyJraWQiOiJ0ZXN0a2V5LWVzMjU2IiwiYWxnIjoiRVMyNTYifQ.\
+                                        eyJzdWIiOiJ0ZXN0MiIsInNjb3BlIjpbImNuIl0sImlzcyI6IkIiLCJyZWFsbSI6Ii9zZXJ2aWNlcyIsImV4cCI6MTQ1NzMxOTgxNCwiaWF0IjoxNDU3MjkxMDE0fQ.\
+                                        KmDsVB09RAOYwT0Y6E9tdQpg0rAPd8SExYhcZ9tXEO6y9AWX4wBylnmNHVoetWu7MwoexWkaKdpKk09IodMVug";
+
+    fn token(header: &str, payload: &str) -> String {
+        format!("{}.{}.AQID",
+                header.as_bytes().to_base64(URL_SAFE),
+                payload.as_bytes().to_base64(URL_SAFE))
+    }
+
+    fn assert_same_as_owned(token: &str) {
+        let owned = JsonWebToken::from_str(token);
+        let borrowed = JwtRef::parse(token).and_then(|jwt| jwt.to_json_web_token());
+        assert_eq!(owned, borrowed);
+        if let Ok(owned) = owned {
+            let jwt = JwtRef::parse(token).unwrap();
+            for (name, value) in &owned.header {
+                assert_eq!(Ok(Some(value.clone())), jwt.get_header(&Header::Custom(name)));
+            }
+            for (name, value) in &owned.payload {
+                assert_eq!(Ok(Some(value.clone())), jwt.get_payload(&Claim::Custom(name)));
+            }
+        }
+    }
+
+    #[test]
+    fn single_claims_must_match_the_owned_parser() {
+        assert_same_as_owned(SAMPLE_TOKEN);
+        assert_same_as_owned(&token("{\"alg\":\"ES256\",\"nested\":{\"a\":[1,-2,3.5,null,true]}}",
+                                    "{\"sub\":\"test2\",\"aud\":[\"a\",\"b\"],\"x\":{\"y\":{}}}"));
+        assert_same_as_owned(&token("{}", "{\"dup\":1,\"dup\":2}"));
+        assert_same_as_owned(&token("{\"alg\":\"ES256\"}", "[]"));
+        assert_same_as_owned(&token("{\"alg\":\"ES256\"}", "{\"sub\":}"));
+        assert_same_as_owned(&format!("{}!", SAMPLE_TOKEN));
+        assert_same_as_owned("a.b");
+    }
+
+    #[test]
+    fn the_requested_claims_must_be_decoded() {
+        let jwt = JwtRef::parse(SAMPLE_TOKEN).unwrap();
+
+        assert_eq!(Ok(Some(Json::String(String::from("testkey-es256")))),
+                   jwt.get_header(&Header::Registered(RegisteredHeader::KeyId)));
+        assert_eq!(Ok(Some(Json::U64(1457319814))),
+                   jwt.get_payload(&Claim::Registered(RegisteredClaim::ExpirationTime)));
+        assert_eq!(Ok(None), jwt.get_payload(&Claim::Custom("realm/")));
+        assert_eq!(Ok(None),
+                   jwt.get_payload(&Claim::Registered(RegisteredClaim::Audience)));
+    }
+
+    #[test]
+    fn nested_members_must_not_be_found_as_top_level_members() {
+        let token = token("{}", "{\"x\":{\"sub\":\"nested\"},\"sub\":\"top\"}");
+        let jwt = JwtRef::parse(&token).unwrap();
+
+        assert_eq!(Ok(Some(Json::String(String::from("top")))),
+                   jwt.get_payload(&Claim::Registered(RegisteredClaim::Subject)));
+    }
+
+    #[test]
+    fn members_that_are_not_requested_must_not_be_built() {
+        // Building this member would overflow the stack of the test thread.
+        let deep = format!("{}{}", "[".repeat(100000), "]".repeat(100000));
+        let token = token("{}", &format!("{{\"deep\":{},\"sub\":\"test2\"}}", deep));
+        let jwt = JwtRef::parse(&token).unwrap();
+
+        assert_eq!(Ok(Some(Json::String(String::from("test2")))),
+                   jwt.get_payload(&Claim::Registered(RegisteredClaim::Subject)));
+    }
+
+    #[test]
+    fn segments_must_be_decoded_only_once() {
+        let jwt = JwtRef::parse(SAMPLE_TOKEN).unwrap();
+
+        assert!(jwt.get_payload(&Claim::Registered(RegisteredClaim::Subject)).is_ok());
+        assert!(jwt.decoded_header.borrow().is_none());
+        *jwt.decoded_payload.borrow_mut() = Some(Ok(String::from("{\"sub\":\"cached\"}")));
+
+        assert_eq!(Ok(Some(Json::String(String::from("cached")))),
+                   jwt.get_payload(&Claim::Registered(RegisteredClaim::Subject)));
+    }
+
+    #[test]
+    fn invalid_json_after_the_requested_claim_must_be_rejected() {
+        let token = token("{}", "{\"sub\":\"test2\",\"x\":[}");
+        let jwt = JwtRef::parse(&token).unwrap();
+
+        match jwt.get_payload(&Claim::Registered(RegisteredClaim::Subject)) {
+            Err(JwtParseError::InvalidJson(Segment::Payload, _)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_signing_input_must_be_borrowed_from_the_token() {
+        let jwt = JwtRef::parse(SAMPLE_TOKEN).unwrap();
+
+        assert_eq!(SAMPLE_TOKEN.rsplitn(2, '.').last(), Some(jwt.signing_input()));
+        assert_eq!(64, jwt.signature().unwrap().len());
+    }
+}
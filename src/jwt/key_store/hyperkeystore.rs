@@ -0,0 +1,113 @@
+//! A `KeyStore` that uses `hyper` to fetch the keys remotely.
+use std::thread::JoinHandle;
+use std::io::Read;
+use std::env;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use hyper;
+use hyper::client::response::Response;
+use hyper::status::StatusCode;
+use InitializationError;
+use jwt::jwk::JwkSet;
+use super::*;
+
+pub struct HyperKeyStore;
+
+/// A `KeyStore` that uses `hyper` to fetch the `JwkSet` remotely.
+impl HyperKeyStore {
+    /// Creates a new instance from scratch
+    #[must_use]
+    pub fn new(config: SelfUpdatingKeyStoreConfig,
+               http_client: hyper::Client,
+               url: &str)
+               -> Result<(SelfUpdatingKeyStore, JoinHandle<()>), InitializationError> {
+        if url.is_empty() {
+            return Err(InitializationError::new("The key set URL may not be empty."));
+        }
+        info!("Key set URL is {}.", url);
+        let jwk_set_provider = HyperJwkSetProvider::new(http_client, url);
+        SelfUpdatingKeyStore::new(config, jwk_set_provider)
+    }
+
+    /// Creates a new instance from environment variables.
+    ///
+    /// Used vars:
+    ///
+    /// * `RUSTY_TOKENS_KEY_SET_URL`(mandatory): The URL of the `JwkSet` of the token provider.
+    /// * `RUSTY_TOKENS_KEY_STORE_REFRESH_INTERVAL_SECONDS`(mandatory): The interval in seconds in which the key set is fetched.
+    /// * `RUSTY_TOKENS_KEY_STORE_MIN_REFETCH_INTERVAL_SECONDS`(optional): The minimum number of seconds between two fetches
+    /// caused by an unknown `kid`. Defaults to 10.
+    #[must_use]
+    pub fn new_from_env(http_client: hyper::Client)
+                        -> Result<(SelfUpdatingKeyStore, JoinHandle<()>), InitializationError> {
+        let config = try!{SelfUpdatingKeyStoreConfig::new_from_env()};
+        let url = try!{env::var("RUSTY_TOKENS_KEY_SET_URL")};
+        HyperKeyStore::new(config, http_client, &url)
+    }
+}
+
+struct HyperJwkSetProvider {
+    client: hyper::Client,
+    url: String,
+}
+
+impl HyperJwkSetProvider {
+    pub fn new<T: Into<String>>(client: hyper::Client, url: T) -> HyperJwkSetProvider {
+        HyperJwkSetProvider {
+            client: client,
+            url: url.into(),
+        }
+    }
+
+    fn execute_http_request_with_multiple_attempts(&self,
+                                                   attempts: u16,
+                                                   last_error: Option<KeyStoreError>)
+                                                   -> Result<Response, KeyStoreError> {
+        if attempts == 0 {
+            match last_error {
+                Some(err) => Err(err),
+                None => {
+                    Err(KeyStoreError::InternalError(String::from("No attempts were made.")))
+                }
+            }
+        } else {
+            match self.client.get(&self.url).send() {
+                Ok(res) => Ok(res),
+                Err(err) => {
+                    warn!("Failed to fetch keys(connection error): {}", err);
+                    thread::sleep(Duration::from_millis(30));
+                    self.execute_http_request_with_multiple_attempts(attempts - 1,
+                                                                     Some(KeyStoreError::FetchError(format!("{}", err))))
+                }
+            }
+        }
+    }
+}
+
+impl JwkSetProvider for HyperJwkSetProvider {
+    fn get_jwk_set(&self) -> Result<JwkSet, KeyStoreError> {
+        let mut response = try!{self.execute_http_request_with_multiple_attempts(3, None)};
+        evaluate_response(&mut response)
+    }
+}
+
+fn evaluate_response(response: &mut Response) -> Result<JwkSet, KeyStoreError> {
+    let mut buf = String::new();
+    let _ = try!{response.read_to_string(&mut buf)
+        .map_err(|err| KeyStoreError::FetchError(format!("{}", err)))};
+    match response.status {
+        StatusCode::Ok => {
+            JwkSet::from_str(&buf).map_err(|err| {
+                KeyStoreError::FetchError(format!("Failed to parse response as a key set: {}",
+                                                  err))
+            })
+        }
+        status => {
+            Err(KeyStoreError::FetchError(format!("The key set request failed with status \
+                                                   code {}: {}",
+                                                  status,
+                                                  buf)))
+        }
+    }
+}
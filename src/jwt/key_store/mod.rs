@@ -0,0 +1,466 @@
+//! A store for the public keys of a token provider.
+//!
+//! Resource servers use the keys to verify the signatures of tokens locally.
+//! A `SelfUpdatingKeyStore` keeps a `JwkSet` up to date so that rotated keys are picked up
+//! without a restart. If a token references a `kid` that is not known yet the key set is fetched
+//! again right away.
+use std::fmt;
+use std::error::Error;
+use std::thread;
+use std::thread::JoinHandle;
+use std::sync::{Arc, RwLock};
+use std::time::{Instant as TInstant, Duration as TDuration};
+use std::cmp::min;
+use std::str::FromStr;
+use std::env;
+use InitializationError;
+use super::jwk::{JsonWebKey, JwkSet};
+
+#[cfg(feature = "hyper")]
+pub mod hyperkeystore;
+
+#[cfg(feature = "hyper")]
+pub use self::hyperkeystore::HyperKeyStore;
+
+/// The result returned by a `KeyStore` for queried keys.
+pub type KeyResult = Result<JsonWebKey, KeyStoreError>;
+
+/// Looks up the keys to verify the signatures of tokens.
+pub trait KeyStore {
+    /// Lookup a key by its `kid`.
+    fn get_key(&self, key_id: &str) -> KeyResult;
+}
+
+impl KeyStore for JwkSet {
+    fn get_key(&self, key_id: &str) -> KeyResult {
+        self.find_key(key_id).cloned().ok_or_else(|| KeyStoreError::UnknownKey(key_id.to_owned()))
+    }
+}
+
+/// Fetches `JwkSet`s
+pub trait JwkSetProvider {
+    fn get_jwk_set(&self) -> Result<JwkSet, KeyStoreError>;
+}
+
+/// Values needed to configure a `SelfUpdatingKeyStore`
+pub struct SelfUpdatingKeyStoreConfig {
+    /// The interval in which the key set is fetched
+    pub refresh_interval: TDuration,
+    /// The minimum time between two fetches caused by an unknown `kid`. Also the time
+    /// after which a failed fetch is retried.
+    pub min_refetch_interval: TDuration,
+}
+
+impl SelfUpdatingKeyStoreConfig {
+    /// Create a new instance from scratch
+    pub fn new(refresh_interval: TDuration,
+               min_refetch_interval: TDuration)
+               -> SelfUpdatingKeyStoreConfig {
+        SelfUpdatingKeyStoreConfig {
+            refresh_interval: refresh_interval,
+            min_refetch_interval: min_refetch_interval,
+        }
+    }
+
+    /// Creates a new instance with some environment variables
+    ///
+    /// Environment vars used:
+    ///
+    /// * `RUSTY_TOKENS_KEY_STORE_REFRESH_INTERVAL_SECONDS`(mandatory): The interval in seconds
+    /// in which the key set is fetched.
+    /// * `RUSTY_TOKENS_KEY_STORE_MIN_REFETCH_INTERVAL_SECONDS`(optional): The minimum number of
+    /// seconds between two fetches caused by an unknown `kid`. Defaults to 10.
+    pub fn new_from_env() -> Result<SelfUpdatingKeyStoreConfig, InitializationError> {
+        let refresh_interval_str =
+            try!{ env::var("RUSTY_TOKENS_KEY_STORE_REFRESH_INTERVAL_SECONDS") };
+        let refresh_interval = try!{ u64::from_str(&refresh_interval_str) };
+
+        let min_refetch_interval =
+            match env::var("RUSTY_TOKENS_KEY_STORE_MIN_REFETCH_INTERVAL_SECONDS") {
+                Ok(value) => try!{ u64::from_str(&value) },
+                Err(env::VarError::NotPresent) => 10,
+                Err(err) => return Err(InitializationError::from(err)),
+            };
+        Ok(SelfUpdatingKeyStoreConfig {
+            refresh_interval: TDuration::from_secs(refresh_interval),
+            min_refetch_interval: TDuration::from_secs(min_refetch_interval),
+        })
+    }
+}
+
+struct KeyStoreState {
+    key_set: Option<JwkSet>,
+    fetched_at: Option<TInstant>,
+    fetch_failed: bool,
+}
+
+/// A `KeyStore` that autonomously updates its keys
+///
+/// Internally updates its state by using a seperate thread.
+#[derive(Clone)]
+pub struct SelfUpdatingKeyStore {
+    state: Arc<RwLock<KeyStoreState>>,
+    provider: Arc<JwkSetProvider + Send + Sync>,
+    min_refetch_interval: TDuration,
+    stop_requested: Arc<RwLock<bool>>,
+}
+
+impl SelfUpdatingKeyStore {
+    /// Create a new instance from scratch
+    #[must_use]
+    pub fn new<T>(conf: SelfUpdatingKeyStoreConfig,
+                  jwk_set_provider: T)
+                  -> Result<(SelfUpdatingKeyStore, JoinHandle<()>), InitializationError>
+        where T: JwkSetProvider + Send + Sync + 'static
+    {
+        let store = SelfUpdatingKeyStore {
+            state: Arc::new(RwLock::new(KeyStoreState {
+                key_set: None,
+                fetched_at: None,
+                fetch_failed: false,
+            })),
+            provider: Arc::new(jwk_set_provider),
+            min_refetch_interval: conf.min_refetch_interval,
+            stop_requested: Arc::new(RwLock::new(false)),
+        };
+        let loop_store = store.clone();
+        let refresh_interval = conf.refresh_interval;
+        let retry_interval = min(conf.refresh_interval, conf.min_refetch_interval);
+        let join_handle =
+            thread::spawn(move || key_store_loop(loop_store, refresh_interval, retry_interval));
+        Ok((store, join_handle))
+    }
+
+    /// Stops the background thread.
+    pub fn stop(&self) {
+        info!("Stop requested.");
+        let mut stop = self.stop_requested.write().unwrap();
+        *stop = true;
+    }
+
+    fn find_key(&self, key_id: &str) -> Result<Option<JsonWebKey>, KeyStoreError> {
+        let state = try!{self.state.read()
+            .map_err(|err| KeyStoreError::InternalError(err.to_string()))};
+        Ok(state.key_set.as_ref().and_then(|key_set| key_set.find_key(key_id).cloned()))
+    }
+
+    /// Marks the key set as fetched if a fetch is due. Returns `true` if a fetch has to be made.
+    ///
+    /// Marking the fetch up front keeps concurrent requests from fetching at the same time.
+    fn begin_fetch(&self, due_after: TDuration) -> Result<bool, KeyStoreError> {
+        let mut state = try!{self.state.write()
+            .map_err(|err| KeyStoreError::InternalError(err.to_string()))};
+        let now = TInstant::now();
+        let due = match state.fetched_at {
+            Some(fetched_at) => now.duration_since(fetched_at) >= due_after,
+            None => true,
+        };
+        if due {
+            state.fetched_at = Some(now);
+        }
+        Ok(due)
+    }
+
+    fn fetch(&self) -> Result<(), KeyStoreError> {
+        let result = self.provider.get_jwk_set();
+        let mut state = try!{self.state.write()
+            .map_err(|err| KeyStoreError::InternalError(err.to_string()))};
+        state.fetch_failed = result.is_err();
+        let key_set = try!{result};
+        info!("Fetched {} keys.", key_set.keys.len());
+        state.key_set = Some(key_set);
+        Ok(())
+    }
+
+    fn fetched_at(&self) -> Option<TInstant> {
+        self.state.read().ok().and_then(|state| state.fetched_at)
+    }
+
+    fn fetch_failed(&self) -> bool {
+        self.state.read().map(|state| state.fetch_failed).unwrap_or(true)
+    }
+
+    fn is_stop_requested(&self) -> bool {
+        match self.stop_requested.read() {
+            Ok(stop) => *stop,
+            Err(err) => {
+                error!("Could not aquire read lock. Stopping. Error was: {}", err);
+                true
+            }
+        }
+    }
+}
+
+impl KeyStore for SelfUpdatingKeyStore {
+    fn get_key(&self, key_id: &str) -> KeyResult {
+        if let Some(key) = try!{self.find_key(key_id)} {
+            return Ok(key);
+        }
+        if try!{self.begin_fetch(self.min_refetch_interval)} {
+            info!("Unknown key '{}'. Fetching keys.", key_id);
+            try!{self.fetch()};
+            if let Some(key) = try!{self.find_key(key_id)} {
+                return Ok(key);
+            }
+        }
+        Err(KeyStoreError::UnknownKey(key_id.to_owned()))
+    }
+}
+
+/// Fetches the keys every `refresh_interval` and every `retry_interval` after a failure.
+fn key_store_loop(store: SelfUpdatingKeyStore,
+                  refresh_interval: TDuration,
+                  retry_interval: TDuration) {
+    info!("Key store loop started.");
+    let fetch_interval = |store: &SelfUpdatingKeyStore| if store.fetch_failed() {
+        retry_interval
+    } else {
+        refresh_interval
+    };
+
+    loop {
+        match store.begin_fetch(fetch_interval(&store)) {
+            Ok(true) => {
+                if let Err(err) = store.fetch() {
+                    warn!("Could not fetch keys: {}", err);
+                }
+            }
+            Ok(false) => (),
+            Err(err) => error!("Could not check whether keys have to be fetched: {}", err),
+        }
+
+        if store.is_stop_requested() {
+            break;
+        }
+
+        let sleep_dur = match store.fetched_at() {
+            Some(fetched_at) => {
+                calc_sleep_duration(fetched_at.elapsed(),
+                                    fetch_interval(&store),
+                                    TDuration::from_secs(5))
+            }
+            None => TDuration::from_millis(100),
+        };
+        debug!("Checking keys again in {:?}.", sleep_dur);
+        thread::sleep(sleep_dur);
+    }
+
+    info!("Key store loop stopped.");
+}
+
+fn calc_sleep_duration(since_fetch: TDuration,
+                       refresh_interval: TDuration,
+                       max_sleep_duration: TDuration)
+                       -> TDuration {
+    if refresh_interval > since_fetch {
+        min(max_sleep_duration, refresh_interval - since_fetch)
+    } else {
+        TDuration::from_millis(100)
+    }
+}
+
+/// Errors that can occur when looking up a key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyStoreError {
+    /// There is no key with the given `kid`
+    UnknownKey(String),
+    /// The key set could not be fetched
+    FetchError(String),
+    /// Something that can not be further specified happended
+    InternalError(String),
+}
+
+impl fmt::Display for KeyStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            KeyStoreError::UnknownKey(ref key_id) => write!(f, "Unknown key: {}", key_id),
+            KeyStoreError::FetchError(ref message) => write!(f, "FetchError: {}", message),
+            KeyStoreError::InternalError(ref message) => write!(f, "InternalError: {}", message),
+        }
+    }
+}
+
+impl Error for KeyStoreError {
+    fn description(&self) -> &str {
+        match *self {
+            KeyStoreError::UnknownKey(_) => "Unknown key",
+            KeyStoreError::FetchError(ref message) |
+            KeyStoreError::InternalError(ref message) => message.as_ref(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use std::sync::Mutex;
+    use std::time::Duration as TDuration;
+    use jwt::jwk::{JsonWebKey, JwkSet, KeyType};
+    use super::{KeyStore, KeyStoreError, JwkSetProvider, SelfUpdatingKeyStore,
+                SelfUpdatingKeyStoreConfig, calc_sleep_duration};
+
+    struct MultipleJwkSetsProviderMock {
+        results: Vec<JwkSet>,
+        failures: usize,
+        counter: Mutex<usize>,
+    }
+
+    impl MultipleJwkSetsProviderMock {
+        fn new(results: Vec<JwkSet>) -> MultipleJwkSetsProviderMock {
+            MultipleJwkSetsProviderMock::failing_first(0, results)
+        }
+
+        fn failing_first(failures: usize, results: Vec<JwkSet>) -> MultipleJwkSetsProviderMock {
+            MultipleJwkSetsProviderMock {
+                results: results,
+                failures: failures,
+                counter: Mutex::new(0),
+            }
+        }
+    }
+
+    impl JwkSetProvider for MultipleJwkSetsProviderMock {
+        fn get_jwk_set(&self) -> Result<JwkSet, KeyStoreError> {
+            let mut counter = self.counter.lock().unwrap();
+            let next = *counter;
+            *counter += 1;
+            if next >= self.failures && next - self.failures < self.results.len() {
+                Ok(self.results[next - self.failures].clone())
+            } else {
+                Err(KeyStoreError::FetchError(format!("error_{}", next)))
+            }
+        }
+    }
+
+    fn key(key_id: &str) -> JsonWebKey {
+        JsonWebKey::new(KeyType::Oct { k: key_id.as_bytes().to_vec() }).with_key_id(key_id)
+    }
+
+    fn start_store(min_refetch_interval: TDuration,
+                   results: Vec<JwkSet>)
+                   -> (SelfUpdatingKeyStore, thread::JoinHandle<()>) {
+        let config = SelfUpdatingKeyStoreConfig::new(TDuration::from_secs(3600),
+                                                     min_refetch_interval);
+        let store = SelfUpdatingKeyStore::new(config, MultipleJwkSetsProviderMock::new(results))
+            .unwrap();
+        thread::sleep(TDuration::from_millis(200));
+        store
+    }
+
+    #[test]
+    fn a_key_set_must_be_usable_as_a_key_store() {
+        let key_set = JwkSet::new(vec![key("a"), key("b")]);
+
+        assert_eq!(Ok(key("b")), key_set.get_key("b"));
+        assert_eq!(Err(KeyStoreError::UnknownKey(String::from("c"))),
+                   key_set.get_key("c"));
+    }
+
+    #[test]
+    fn the_store_must_fetch_the_keys_initially() {
+        let (store, join_handle) = start_store(TDuration::from_secs(3600),
+                                               vec![JwkSet::new(vec![key("a")])]);
+
+        let result = store.get_key("a");
+
+        store.stop();
+        join_handle.join().unwrap();
+
+        assert_eq!(Ok(key("a")), result);
+    }
+
+    #[test]
+    fn an_unknown_key_must_cause_a_refetch() {
+        let (store, join_handle) = start_store(TDuration::from_secs(0),
+                                               vec![JwkSet::new(vec![key("a")]),
+                                                    JwkSet::new(vec![key("a"), key("b")])]);
+
+        let result = store.get_key("b");
+
+        store.stop();
+        join_handle.join().unwrap();
+
+        assert_eq!(Ok(key("b")), result);
+    }
+
+    #[test]
+    fn refetches_must_be_rate_limited() {
+        let (store, join_handle) = start_store(TDuration::from_secs(3600),
+                                               vec![JwkSet::new(vec![key("a")]),
+                                                    JwkSet::new(vec![key("a"), key("b")])]);
+
+        let result = store.get_key("b");
+
+        store.stop();
+        join_handle.join().unwrap();
+
+        assert_eq!(Err(KeyStoreError::UnknownKey(String::from("b"))), result);
+    }
+
+    #[test]
+    fn a_failed_refetch_must_keep_the_known_keys() {
+        let (store, join_handle) = start_store(TDuration::from_secs(0),
+                                               vec![JwkSet::new(vec![key("a")])]);
+
+        let refetch_result = store.get_key("b");
+        let result = store.get_key("a");
+
+        store.stop();
+        join_handle.join().unwrap();
+
+        assert_eq!(Err(KeyStoreError::FetchError(String::from("error_1"))),
+                   refetch_result);
+        assert_eq!(Ok(key("a")), result);
+    }
+
+    #[test]
+    fn a_failed_initial_fetch_must_be_retried_before_the_refresh_interval() {
+        let config = SelfUpdatingKeyStoreConfig::new(TDuration::from_secs(3600),
+                                                     TDuration::from_millis(50));
+        let key_sets = vec![JwkSet::new(vec![key("a")])];
+        let provider = MultipleJwkSetsProviderMock::failing_first(1, key_sets);
+        let (store, join_handle) = SelfUpdatingKeyStore::new(config, provider).unwrap();
+        thread::sleep(TDuration::from_millis(500));
+
+        let result = store.find_key("a");
+
+        store.stop();
+        join_handle.join().unwrap();
+
+        assert_eq!(Ok(Some(key("a"))), result);
+    }
+
+    #[test]
+    fn calc_sleep_duration_when_refresh_is_overdue() {
+        let expected = TDuration::from_millis(100);
+        let result = calc_sleep_duration(TDuration::from_secs(20),
+                                         TDuration::from_secs(10),
+                                         TDuration::from_secs(5));
+
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn calc_sleep_duration_when_refresh_is_soon() {
+        let expected = TDuration::from_secs(2);
+        let result = calc_sleep_duration(TDuration::from_secs(8),
+                                         TDuration::from_secs(10),
+                                         TDuration::from_secs(5));
+
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn calc_sleep_duration_must_not_exceed_the_max_sleep_duration() {
+        let expected = TDuration::from_secs(5);
+        let result = calc_sleep_duration(TDuration::from_secs(0),
+                                         TDuration::from_secs(10),
+                                         TDuration::from_secs(5));
+
+        assert_eq!(expected, result);
+    }
+}
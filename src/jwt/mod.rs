@@ -1,10 +1,31 @@
 //! Handling of [JWT Tokens](https://tools.ietf.org/html/rfc7519)
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt;
 use std::str::FromStr;
-use rustc_serialize::base64::FromBase64;
-use rustc_serialize::json::Json;
+use rustc_serialize::base64::{ToBase64, URL_SAFE};
+use openssl::sha::sha1;
+use rustc_serialize::Decodable;
+use rustc_serialize::json::{self, Json};
 
 pub mod planb;
+pub mod signature;
+pub mod jwk;
+pub mod key_store;
+pub mod validation;
+pub mod verifier;
+pub mod jwe;
+pub mod base64url;
+pub mod jws_json;
+pub mod x509;
+pub mod jwt_ref;
+pub mod inspection;
+
+use self::signature::{Algorithm, VerificationKey, SigningKey, SignatureError};
+use self::jwk::{JsonWebKey, JwkSet};
+use self::base64url::Base64Error;
+use self::x509::X509Error;
+pub use self::inspection::{inspect, UnverifiedToken};
 
 pub enum Header<'a> {
     Registered(RegisteredHeader),
@@ -46,7 +67,7 @@ pub enum Claim<'a> {
     Custom(&'a str),
 }
 
-#[derive(PartialEq, Eq, Debug, Hash)]
+#[derive(PartialEq, Eq, Debug, Hash, Clone)]
 pub enum RegisteredClaim {
     Subject,
     Audience,
@@ -134,14 +155,362 @@ impl JsonWebToken {
             Claim::Custom(key) => self.payload.get(key),
         }
     }
+
+    /// Decodes the payload into a struct, e.g. one that derives `RustcDecodable`.
+    ///
+    /// Use an `Option` for claims that may be missing.
+    pub fn decode_claims<T: Decodable>(&self) -> Result<T, json::DecoderError> {
+        let mut decoder = json::Decoder::new(to_json_object(&self.payload));
+        T::decode(&mut decoder)
+    }
+
+    /// Decodes the header into a struct, e.g. one that derives `RustcDecodable`.
+    pub fn decode_header<T: Decodable>(&self) -> Result<T, json::DecoderError> {
+        let mut decoder = json::Decoder::new(to_json_object(&self.header));
+        T::decode(&mut decoder)
+    }
+
+    /// The `Algorithm` given by the `alg` header.
+    pub fn algorithm(&self) -> Result<Algorithm, VerificationError> {
+        let alg = try!{self.get_registered_header(RegisteredHeader::Algorithm)
+            .and_then(|json| json.as_string())
+            .ok_or_else(|| VerificationError::Malformed(String::from("Field 'alg' is missing or not a String.")))};
+        Algorithm::from_str(alg).map_err(VerificationError::Signature)
+    }
+
+    /// Creates the compact serialization of this token signed with `key`.
+    ///
+    /// The `alg` header is set to the `Algorithm` of the key.
+    pub fn encode(&self, key: &SigningKey) -> Result<String, SignatureError> {
+        let mut header = self.header.clone();
+        header.insert(String::from(RegisteredHeader::Algorithm.to_key()),
+                      Json::String(String::from(key.algorithm().to_key())));
+        let signing_input = format!("{}.{}",
+                                    encode_json_map(&header),
+                                    encode_json_map(&self.payload));
+        let signature = try!{signature::sign(key, signing_input.as_bytes())};
+        Ok(format!("{}.{}", signing_input, signature.to_base64(URL_SAFE)))
+    }
+
+    /// Parses a token and verifies its signature with the given key.
+    ///
+    /// The algorithm is taken from the `alg` header of the token.
+    /// Use a `JwtVerifier` to restrict the accepted algorithms.
+    pub fn verify_with(token: &str, key: &VerificationKey) -> Result<JsonWebToken, VerificationError> {
+        let jwt_token = try!{JsonWebToken::from_str(token)};
+        try!{jwt_token.check_critical_headers(&[])};
+        try!{jwt_token.check_signature(token, key)};
+        Ok(jwt_token)
+    }
+
+    /// Parses a token and verifies its signature with the key of `key_set`
+    /// that matches the `kid` header of the token.
+    ///
+    /// If the key has an `alg` parameter the token must use that algorithm.
+    pub fn verify_with_key_set(token: &str,
+                               key_set: &JwkSet)
+                               -> Result<JsonWebToken, VerificationError> {
+        let jwt_token = try!{JsonWebToken::from_str(token)};
+        {
+            let key_id = try!{jwt_token.key_id()};
+            let jwk = try!{key_set.find_key(key_id)
+                .ok_or_else(|| VerificationError::UnknownKey(key_id.to_owned()))};
+            try!{jwt_token.check_with_jwk(token, jwk)};
+        }
+        Ok(jwt_token)
+    }
+
+    /// The `kid` header, which names the key the token was signed with.
+    pub fn key_id(&self) -> Result<&str, VerificationError> {
+        self.get_registered_header(RegisteredHeader::KeyId)
+            .and_then(|json| json.as_string())
+            .ok_or_else(|| VerificationError::Malformed(String::from("Field 'kid' is missing or not a String.")))
+    }
+
+    /// Verifies the signature of `token`, from which this instance was parsed, with `jwk`.
+    ///
+    /// If the key has an `alg` parameter the token must use that algorithm. The `x5t` header
+    /// must match the certificate of the key and no header may be listed in `crit`.
+    pub fn check_with_jwk(&self, token: &str, jwk: &JsonWebKey) -> Result<(), VerificationError> {
+        if let Some(ref key_algorithm) = jwk.algorithm {
+            let algorithm = try!{self.algorithm()};
+            if key_algorithm != algorithm.to_key() {
+                return Err(VerificationError::AlgorithmNotAllowed(algorithm.to_key().to_owned()));
+            }
+        }
+        try!{self.check_x509_thumbprint(jwk)};
+        let key = try!{jwk.to_verification_key()};
+        try!{self.check_critical_headers(&[])};
+        self.check_signature(token, &key)
+    }
+
+    /// Compares the `x5t` header with the SHA-1 thumbprint of the certificate of `jwk`
+    /// given by its `x5t` parameter or the first certificate of its `x5c` parameter.
+    ///
+    /// Passes if the token has no `x5t` header or the key has no certificate.
+    pub fn check_x509_thumbprint(&self, jwk: &JsonWebKey) -> Result<(), VerificationError> {
+        let x5t = match self.get_registered_header(RegisteredHeader::X509CertificateSha1Thumbprint) {
+            None => return Ok(()),
+            Some(json) => {
+                try!{json.as_string()
+                    .ok_or_else(|| VerificationError::Malformed(String::from("Field 'x5t' is not a String.")))}
+            }
+        };
+        let expected = match jwk.x509_sha1_thumbprint {
+            Some(ref thumbprint) => thumbprint.clone(),
+            None => {
+                match jwk.x509_chain.first() {
+                    Some(certificate) => sha1(certificate).to_vec(),
+                    None => return Ok(()),
+                }
+            }
+        };
+        let actual = try!{decode_base_64_bytes(x5t)
+            .map_err(|err| VerificationError::Malformed(format!("Field 'x5t': {}", err)))};
+        if actual == expected {
+            Ok(())
+        } else {
+            Err(VerificationError::X509ThumbprintMismatch)
+        }
+    }
+
+    /// Every header listed in `crit` must be present and understood as required by
+    /// [RFC 7515](https://tools.ietf.org/html/rfc7515#section-4.1.11).
+    fn check_critical_headers(&self, understood: &[String]) -> Result<(), VerificationError> {
+        let critical = match self.get_registered_header(RegisteredHeader::Critical) {
+            None => return Ok(()),
+            Some(&Json::Array(ref critical)) if !critical.is_empty() => critical,
+            Some(_) => {
+                return Err(VerificationError::Malformed(String::from("Field 'crit' is not a \
+                                                                      non-empty array.")))
+            }
+        };
+        let mut seen = HashSet::new();
+        for entry in critical {
+            let name = try!{entry.as_string()
+                .ok_or_else(|| VerificationError::Malformed(String::from("Element in 'crit' not a String.")))};
+            if !seen.insert(name) {
+                return Err(VerificationError::Malformed(format!("Header '{}' is listed twice in \
+                                                                 'crit'.",
+                                                                name)));
+            }
+            if !understood.iter().any(|x| x == name) || !self.header.contains_key(name) {
+                return Err(VerificationError::UnsupportedCriticalHeader(name.to_owned()));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_signature(&self, token: &str, key: &VerificationKey) -> Result<(), VerificationError> {
+        let (header, payload, signature) = try!{split_segments(token)};
+        let signature_bytes = try!{decode_segment(Segment::Signature, signature)};
+        let signing_input = format!("{}.{}", header, payload);
+        self.check_signature_over(&signing_input, &signature_bytes, key)
+    }
+
+    fn check_signature_over(&self,
+                            signing_input: &str,
+                            signature_bytes: &[u8],
+                            key: &VerificationKey)
+                            -> Result<(), VerificationError> {
+        let algorithm = try!{self.algorithm()};
+        try!{signature::verify_signature(algorithm,
+                                         key,
+                                         signing_input.as_bytes(),
+                                         signature_bytes)};
+        Ok(())
+    }
+}
+
+/// Errors that can occur when verifying a token.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerificationError {
+    /// The token could not be parsed
+    Parse(JwtParseError),
+    /// A header is missing or invalid
+    Malformed(String),
+    /// The signature could not be verified
+    Signature(SignatureError),
+    /// There is no key for the `kid` of the token
+    UnknownKey(String),
+    /// The algorithm of the token is not allowed for the key
+    AlgorithmNotAllowed(String),
+    /// A header listed in `crit` is not understood or missing
+    UnsupportedCriticalHeader(String),
+    /// The `x5t` header does not match the certificate of the key
+    X509ThumbprintMismatch,
+    /// The certificate chain of the `x5c` header could not be verified
+    Certificate(X509Error),
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VerificationError::Parse(ref err) => write!(f, "Unparsable token: {}", err),
+            VerificationError::Malformed(ref message) => write!(f, "Malformed token: {}", message),
+            VerificationError::Signature(ref err) => write!(f, "Signature: {}", err),
+            VerificationError::UnknownKey(ref key_id) => write!(f, "Unknown key id: {}", key_id),
+            VerificationError::AlgorithmNotAllowed(ref algorithm) => {
+                write!(f, "Algorithm not allowed: {}", algorithm)
+            }
+            VerificationError::UnsupportedCriticalHeader(ref name) => {
+                write!(f, "Unsupported critical header: {}", name)
+            }
+            VerificationError::X509ThumbprintMismatch => {
+                write!(f, "The x5t header does not match the certificate of the key")
+            }
+            VerificationError::Certificate(ref err) => write!(f, "Certificate: {}", err),
+        }
+    }
+}
+
+impl Error for VerificationError {
+    fn description(&self) -> &str {
+        match *self {
+            VerificationError::Parse(ref err) => err.description(),
+            VerificationError::Malformed(ref message) => message.as_ref(),
+            VerificationError::Signature(ref err) => err.description(),
+            VerificationError::UnknownKey(_) => "Unknown key id",
+            VerificationError::AlgorithmNotAllowed(_) => "Algorithm not allowed",
+            VerificationError::UnsupportedCriticalHeader(_) => "Unsupported critical header",
+            VerificationError::X509ThumbprintMismatch => "The x5t header does not match the key",
+            VerificationError::Certificate(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            VerificationError::Malformed(_) |
+            VerificationError::UnknownKey(_) |
+            VerificationError::AlgorithmNotAllowed(_) |
+            VerificationError::UnsupportedCriticalHeader(_) |
+            VerificationError::X509ThumbprintMismatch => None,
+            VerificationError::Parse(ref err) => Some(err),
+            VerificationError::Signature(ref err) => Some(err),
+            VerificationError::Certificate(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<SignatureError> for VerificationError {
+    fn from(err: SignatureError) -> Self {
+        VerificationError::Signature(err)
+    }
+}
+
+impl From<JwtParseError> for VerificationError {
+    fn from(err: JwtParseError) -> Self {
+        VerificationError::Parse(err)
+    }
+}
+
+/// The segments of a token in compact serialization
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Segment {
+    Header,
+    Payload,
+    Signature,
+    /// The encrypted key of a JWE
+    EncryptedKey,
+    /// The initialization vector of a JWE
+    InitializationVector,
+    /// The ciphertext of a JWE
+    Ciphertext,
+    /// The authentication tag of a JWE
+    AuthenticationTag,
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Segment::Header => "header",
+            Segment::Payload => "payload",
+            Segment::Signature => "signature",
+            Segment::EncryptedKey => "encrypted key",
+            Segment::InitializationVector => "initialization vector",
+            Segment::Ciphertext => "ciphertext",
+            Segment::AuthenticationTag => "authentication tag",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Errors that can occur when parsing a token.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JwtParseError {
+    /// The token does not split into the expected number of segments
+    WrongNumberOfSegments(usize),
+    /// A token with five segments was given where a signed token was expected
+    Encrypted,
+    /// The segment is not strictly base64url encoded
+    InvalidBase64(Segment, Base64Error),
+    /// The decoded segment is not UTF-8
+    InvalidUtf8(Segment),
+    /// The decoded segment is not JSON
+    InvalidJson(Segment, String),
+    /// The decoded segment is JSON but not an object
+    NotAnObject(Segment),
+    /// The JSON serialization of a JWS is not valid
+    InvalidJsonSerialization(String),
+}
+
+impl fmt::Display for JwtParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JwtParseError::WrongNumberOfSegments(count) => {
+                write!(f, "Wrong number of segments: {}", count)
+            }
+            JwtParseError::Encrypted => {
+                write!(f,
+                       "The token is encrypted and must be decrypted with \
+                        `JsonWebToken::decrypt_with`")
+            }
+            JwtParseError::InvalidBase64(ref segment, ref err) => {
+                write!(f, "The {} is not base64url encoded: {}", segment, err)
+            }
+            JwtParseError::InvalidUtf8(ref segment) => write!(f, "The {} is not UTF-8", segment),
+            JwtParseError::InvalidJson(ref segment, ref message) => {
+                write!(f, "The {} is not JSON: {}", segment, message)
+            }
+            JwtParseError::NotAnObject(ref segment) => {
+                write!(f, "The {} is not a JSON object", segment)
+            }
+            JwtParseError::InvalidJsonSerialization(ref message) => {
+                write!(f, "Invalid JSON serialization: {}", message)
+            }
+        }
+    }
+}
+
+impl Error for JwtParseError {
+    fn description(&self) -> &str {
+        match *self {
+            JwtParseError::WrongNumberOfSegments(_) => "Wrong number of segments",
+            JwtParseError::Encrypted => "The token is encrypted",
+            JwtParseError::InvalidBase64(_, _) => "A segment is not base64url encoded",
+            JwtParseError::InvalidUtf8(_) => "A segment is not UTF-8",
+            JwtParseError::InvalidJson(_, _) => "A segment is not JSON",
+            JwtParseError::NotAnObject(_) => "A segment is not a JSON object",
+            JwtParseError::InvalidJsonSerialization(ref message) => message.as_ref(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            JwtParseError::InvalidBase64(_, ref err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
 impl FromStr for JsonWebToken {
-    type Err = String;
+    type Err = JwtParseError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (_, _, signature) = try!{split_segments(s)};
+        try!{decode_segment(Segment::Signature, signature)};
         let (header_str, payload_str) = try!{decode_data_segments(s)};
-        let header = try!{parse_json_str_to_json_map(&header_str)};
-        let payload = try!{parse_json_str_to_json_map(&payload_str)};
+        let header = try!{parse_json_str_to_json_map(Segment::Header, &header_str)};
+        let payload = try!{parse_json_str_to_json_map(Segment::Payload, &payload_str)};
         Ok(JsonWebToken {
             header: header,
             payload: payload,
@@ -149,8 +518,11 @@ impl FromStr for JsonWebToken {
     }
 }
 
-fn parse_json_str_to_json_map(json_str: &str) -> Result<HashMap<String, Json>, String> {
-    let json_val = try!{Json::from_str(json_str).map_err(|x| x.to_string())};
+fn parse_json_str_to_json_map(segment: Segment,
+                              json_str: &str)
+                              -> Result<HashMap<String, Json>, JwtParseError> {
+    let json_val = try!{Json::from_str(json_str)
+        .map_err(|x| JwtParseError::InvalidJson(segment, x.to_string()))};
     match json_val {
         Json::Object(obj) => {
             let mut the_map = HashMap::new();
@@ -159,37 +531,51 @@ fn parse_json_str_to_json_map(json_str: &str) -> Result<HashMap<String, Json>, S
             }
             Ok(the_map)
         }
-        _ => Err(String::from("Not a JSON object.")),
+        _ => Err(JwtParseError::NotAnObject(segment)),
     }
 }
 
-fn decode_data_segments(complete: &str) -> Result<(String, String), String> {
-    let (header, payload) = try!{extract_data_segments(complete).map_err(|x| x.to_owned())};
-    let decoded_header = try!{decode_base_64_string(header)};
-    let decoded_payload = try!{decode_base_64_string(payload)};
+fn to_json_object(map: &HashMap<String, Json>) -> Json {
+    let obj: BTreeMap<String, Json> = map.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    Json::Object(obj)
+}
+
+fn encode_json_map(map: &HashMap<String, Json>) -> String {
+    to_json_object(map).to_string().as_bytes().to_base64(URL_SAFE)
+}
+
+fn decode_data_segments(complete: &str) -> Result<(String, String), JwtParseError> {
+    let (header, payload) = try!{extract_data_segments(complete)};
+    let decoded_header = try!{decode_base_64_string(Segment::Header, header)};
+    let decoded_payload = try!{decode_base_64_string(Segment::Payload, payload)};
     Ok((decoded_header, decoded_payload))
 }
 
-fn extract_data_segments(complete: &str) -> Result<(&str, &str), &'static str> {
+fn extract_data_segments(complete: &str) -> Result<(&str, &str), JwtParseError> {
+    let (header, payload, _) = try!{split_segments(complete)};
+    Ok((header, payload))
+}
+
+fn split_segments(complete: &str) -> Result<(&str, &str, &str), JwtParseError> {
     let parts: Vec<&str> = complete.split('.').collect();
-    if parts.len() == 3 {
-        Ok((parts[0], parts[1]))
-    } else {
-        Err("The given String must split to 3 parts segmented by a dot each.")
+    match parts.len() {
+        3 => Ok((parts[0], parts[1], parts[2])),
+        5 => Err(JwtParseError::Encrypted),
+        count => Err(JwtParseError::WrongNumberOfSegments(count)),
     }
 }
 
-fn decode_base_64_bytes(what: &str) -> Result<Vec<u8>, String> {
-    let bytes: Vec<u8> =
-        try!{what.from_base64().map_err(|err| format!("No base64 encoded bytes: {}", err))};
-    Ok(bytes)
+fn decode_base_64_bytes(what: &str) -> Result<Vec<u8>, Base64Error> {
+    base64url::decode(what)
 }
 
-fn decode_base_64_string(what: &str) -> Result<String, String> {
-    let bytes: Vec<u8> = try!{decode_base_64_bytes(what)};
-    let string =
-        try!{String::from_utf8(bytes).map_err(|err| format!("basde64 bytes are not a valid UTF-8 String: {}", err))};
-    Ok(string)
+fn decode_segment(segment: Segment, what: &str) -> Result<Vec<u8>, JwtParseError> {
+    decode_base_64_bytes(what).map_err(|err| JwtParseError::InvalidBase64(segment, err))
+}
+
+fn decode_base_64_string(segment: Segment, what: &str) -> Result<String, JwtParseError> {
+    let bytes: Vec<u8> = try!{decode_segment(segment, what)};
+    String::from_utf8(bytes).map_err(|_| JwtParseError::InvalidUtf8(segment))
 }
 
 #[cfg(test)]
@@ -197,7 +583,19 @@ mod test {
     use std::str::FromStr;
     use rustc_serialize::json::Json;
     use jwt;
-    use jwt::{RegisteredHeader, Header, Claim, RegisteredClaim};
+    use jwt::{RegisteredHeader, Header, Claim, RegisteredClaim, VerificationError, JwtParseError,
+              Segment};
+    use jwt::base64url::Base64Error;
+    use jwt::signature::{VerificationKey, SigningKey, SignatureError};
+    use jwt::planb::PlanbToken;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::sha::sha1;
+    use openssl::sign::Signer;
+    use jwt::jwk::{JsonWebKey, KeyType};
+    use openssl::hash::MessageDigest;
+    use rustc_serialize::base64::{ToBase64, URL_SAFE};
+    use es256_fixture::{generate_key_pair, sign_es256};
 
     const SAMPLE_TOKEN: &'static str = "eyJraWQiOiJ0ZXN0a2V5LWVzMjU2IiwiYWxnIjoiRVMyNTYifQ.\
                                         eyJzdWIiOiJ0ZXN0MiIsInNjb3BlIjpbImNuIl0sImlzcyI6IkIiLCJyZWFsbSI6Ii9zZXJ2aWNlcyIsImV4cCI6MTQ1NzMxOTgxNCwiaWF0IjoxNDU3MjkxMDE0fQ.\
@@ -208,17 +606,20 @@ mod test {
                                                \"B\",\"realm\":\"/services\",\"exp\":1457319814,\
                                                \"iat\":1457291014}";
 
+    const SAMPLE_SIGNING_INPUT: &'static str = "eyJraWQiOiJ0ZXN0a2V5LWVzMjU2IiwiYWxnIjoiRVMyNTYifQ.\
+                                                eyJzdWIiOiJ0ZXN0MiIsInNjb3BlIjpbImNuIl0sImlzcyI6IkIiLCJyZWFsbSI6Ii9zZXJ2aWNlcyIsImV4cCI6MTQ1NzMxOTgxNCwiaWF0IjoxNDU3MjkxMDE0fQ";
+
     #[test]
     fn must_decode_base_64_header_to_a_string() {
         let sample = "eyJraWQiOiJ0ZXN0a2V5LWVzMjU2IiwiYWxnIjoiRVMyNTYifQ";
-        let result = jwt::decode_base_64_string(sample).unwrap();
+        let result = jwt::decode_base_64_string(Segment::Header, sample).unwrap();
         assert_eq!(SAMPLE_HEADER_JSON, result);
     }
 
     #[test]
     fn must_decode_base_64_payload_to_a_string() {
         let sample = "eyJzdWIiOiJ0ZXN0MiIsInNjb3BlIjpbImNuIl0sImlzcyI6IkIiLCJyZWFsbSI6Ii9zZXJ2aWNlcyIsImV4cCI6MTQ1NzMxOTgxNCwiaWF0IjoxNDU3MjkxMDE0fQ";
-        let result = jwt::decode_base_64_string(sample).unwrap();
+        let result = jwt::decode_base_64_string(Segment::Payload, sample).unwrap();
         assert_eq!(SAMPLE_PAYLOAD_JSON, result);
     }
 
@@ -268,4 +669,225 @@ mod test {
 
         assert_eq!(expected, result);
     }
+
+    #[test]
+    fn verify_with_must_accept_a_valid_es256_signature() {
+        let (private_key, public_key) = generate_key_pair();
+        let token = sign_es256(SAMPLE_SIGNING_INPUT, &private_key);
+
+        let result = jwt::JsonWebToken::verify_with(&token, &public_key).unwrap();
+
+        assert_eq!(jwt::JsonWebToken::from_str(SAMPLE_TOKEN).unwrap(), result);
+    }
+
+    #[test]
+    fn verify_with_must_reject_a_tampered_payload() {
+        let (private_key, public_key) = generate_key_pair();
+        let token = sign_es256(SAMPLE_SIGNING_INPUT, &private_key);
+        // {"sub":"admin","scope":["cn"],"iss":"B","realm":"/services","exp":1457319814,"iat":1457291014}
+        let tampered = token.replace("eyJzdWIiOiJ0ZXN0MiIs", "eyJzdWIiOiJhZG1pbiIs");
+
+        let result = jwt::JsonWebToken::verify_with(&tampered, &public_key);
+
+        assert_eq!(Err(VerificationError::Signature(SignatureError::InvalidSignature)),
+                   result);
+    }
+
+    #[test]
+    fn verify_with_must_reject_an_unsupported_algorithm() {
+        let (_, public_key) = generate_key_pair();
+        // {"alg":"none"}
+        let unsigned = "eyJhbGciOiJub25lIn0.\
+                        eyJzdWIiOiJ0ZXN0MiIsInNjb3BlIjpbImNuIl0sImlzcyI6IkIiLCJyZWFsbSI6Ii9zZXJ2aWNlcyIsImV4cCI6MTQ1NzMxOTgxNCwiaWF0IjoxNDU3MjkxMDE0fQ.";
+
+        let result = jwt::JsonWebToken::verify_with(unsigned, &public_key);
+
+        assert_eq!(Err(VerificationError::Signature(SignatureError::UnsupportedAlgorithm(String::from("none")))),
+                   result);
+    }
+
+    #[test]
+    fn an_encoded_es256_token_must_be_verifiable() {
+        let (private_key, public_key) = generate_key_pair();
+        let token = jwt::JsonWebToken::from_str(SAMPLE_TOKEN).unwrap();
+
+        let encoded = token.encode(&SigningKey::EcP256(private_key)).unwrap();
+
+        assert_eq!(Ok(token), jwt::JsonWebToken::verify_with(&encoded, &public_key));
+    }
+
+    #[test]
+    fn an_encoded_rs256_token_must_be_verifiable() {
+        let rsa = Rsa::generate(2048).unwrap();
+        let public_key = VerificationKey::rsa_from_pem(&rsa.public_key_to_pem().unwrap()).unwrap();
+        let token = jwt::JsonWebToken::from_str(SAMPLE_TOKEN).unwrap();
+
+        let encoded = token.encode(&SigningKey::Rsa(PKey::from_rsa(rsa).unwrap())).unwrap();
+        let result = jwt::JsonWebToken::verify_with(&encoded, &public_key).unwrap();
+
+        assert_eq!(Some(&Json::String(String::from("RS256"))),
+                   result.get_registered_header(RegisteredHeader::Algorithm));
+        assert_eq!(token.payload, result.payload);
+    }
+
+    #[test]
+    fn an_encoded_hs256_token_must_carry_the_hmac_of_the_signing_input() {
+        let secret = [42u8; 32];
+        let token = jwt::JsonWebToken::from_str(SAMPLE_TOKEN).unwrap();
+        let key = SigningKey::hmac_from_secret(jwt::signature::Algorithm::HS256, &secret).unwrap();
+
+        let encoded = token.encode(&key).unwrap();
+
+        let (header, payload, signature) = jwt::split_segments(&encoded).unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &PKey::hmac(&secret).unwrap())
+            .unwrap();
+        signer.update(format!("{}.{}", header, payload).as_bytes()).unwrap();
+        assert_eq!(signer.sign_to_vec().unwrap().to_base64(URL_SAFE), signature);
+        assert_eq!(Ok(jwt::signature::Algorithm::HS256),
+                   jwt::JsonWebToken::from_str(&encoded).unwrap().algorithm());
+    }
+
+    #[test]
+    fn an_encoded_token_must_be_parsable_as_a_planb_token() {
+        let (private_key, _) = generate_key_pair();
+        let token = jwt::JsonWebToken::from_str(SAMPLE_TOKEN).unwrap();
+
+        let encoded = token.encode(&SigningKey::EcP256(private_key)).unwrap();
+
+        assert_eq!(PlanbToken::from_str(SAMPLE_TOKEN),
+                   PlanbToken::from_str(&encoded));
+    }
+
+    #[test]
+    fn an_encoded_hs512_token_must_be_verifiable_with_the_shared_secret() {
+        let secret = [42u8; 64];
+        let token = jwt::JsonWebToken::from_str(SAMPLE_TOKEN).unwrap();
+        let key = SigningKey::hmac_from_secret(jwt::signature::Algorithm::HS512, &secret).unwrap();
+
+        let encoded = token.encode(&key).unwrap();
+        let result = jwt::JsonWebToken::verify_with(&encoded,
+                                                    &VerificationKey::hmac_from_secret(&secret)
+                                                        .unwrap());
+
+        assert_eq!(token.payload, result.unwrap().payload);
+    }
+
+    #[test]
+    fn verify_with_must_reject_an_unknown_critical_header() {
+        let (private_key, public_key) = generate_key_pair();
+        let token = jwt::JsonWebToken::from_str(SAMPLE_TOKEN)
+            .unwrap()
+            .add_header(&Header::Registered(RegisteredHeader::Critical),
+                        Json::Array(vec![Json::String(String::from("exp"))]))
+            .add_header(&Header::Custom("exp"), Json::U64(1457319814));
+
+        let encoded = token.encode(&SigningKey::EcP256(private_key)).unwrap();
+
+        assert_eq!(Err(VerificationError::UnsupportedCriticalHeader(String::from("exp"))),
+                   jwt::JsonWebToken::verify_with(&encoded, &public_key));
+    }
+
+    #[derive(RustcDecodable, PartialEq, Debug)]
+    struct SampleClaims {
+        sub: String,
+        scope: Vec<String>,
+        realm: String,
+        exp: i64,
+        azp: Option<String>,
+    }
+
+    #[derive(RustcDecodable, PartialEq, Debug)]
+    struct SampleHeader {
+        kid: String,
+        alg: String,
+    }
+
+    #[test]
+    fn claims_must_be_decoded_into_a_struct() {
+        let token = jwt::JsonWebToken::from_str(SAMPLE_TOKEN).unwrap();
+        let expected = SampleClaims {
+            sub: String::from("test2"),
+            scope: vec![String::from("cn")],
+            realm: String::from("/services"),
+            exp: 1457319814,
+            azp: None,
+        };
+
+        assert_eq!(Ok(expected), token.decode_claims::<SampleClaims>());
+    }
+
+    #[test]
+    fn the_header_must_be_decoded_into_a_struct() {
+        let token = jwt::JsonWebToken::from_str(SAMPLE_TOKEN).unwrap();
+        let expected = SampleHeader {
+            kid: String::from("testkey-es256"),
+            alg: String::from("ES256"),
+        };
+
+        assert_eq!(Ok(expected), token.decode_header::<SampleHeader>());
+    }
+
+    #[test]
+    fn decoding_claims_must_fail_for_a_missing_claim() {
+        let token = jwt::JsonWebToken::new()
+            .add_payload(&Claim::Registered(RegisteredClaim::Subject),
+                         Json::String(String::from("test2")));
+
+        assert!(token.decode_claims::<SampleClaims>().is_err());
+    }
+
+    #[test]
+    fn parse_errors_must_name_the_segment_and_the_reason() {
+        let (header, payload) = jwt::extract_data_segments(SAMPLE_TOKEN).unwrap();
+
+        assert_eq!(Err(JwtParseError::WrongNumberOfSegments(2)),
+                   jwt::JsonWebToken::from_str(&format!("{}.{}", header, payload)));
+        assert_eq!(Err(JwtParseError::Encrypted),
+                   jwt::JsonWebToken::from_str("a.b.c.d.e"));
+        assert_eq!("The token is encrypted and must be decrypted with \
+                    `JsonWebToken::decrypt_with`",
+                   JwtParseError::Encrypted.to_string());
+        assert_eq!(Err(JwtParseError::InvalidBase64(Segment::Payload,
+                                                    Base64Error::InvalidCharacter {
+                                                        character: '=',
+                                                        position: payload.len(),
+                                                    })),
+                   jwt::JsonWebToken::from_str(&format!("{}.{}=.", header, payload)));
+        assert_eq!(Err(JwtParseError::InvalidBase64(Segment::Signature,
+                                                    Base64Error::InvalidCharacter {
+                                                        character: '+',
+                                                        position: 1,
+                                                    })),
+                   jwt::JsonWebToken::from_str(&format!("{}.{}.a+c", header, payload)));
+        // "[]" and "{" encoded
+        assert_eq!(Err(JwtParseError::NotAnObject(Segment::Header)),
+                   jwt::JsonWebToken::from_str(&format!("W10.{}.", payload)));
+        match jwt::JsonWebToken::from_str(&format!("ew.{}.", payload)) {
+            Err(JwtParseError::InvalidJson(Segment::Header, _)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        // 0xff encoded
+        assert_eq!(Err(JwtParseError::InvalidUtf8(Segment::Payload)),
+                   jwt::JsonWebToken::from_str(&format!("{}._w.", header)));
+    }
+
+    #[test]
+    fn the_x5t_header_must_match_the_certificate_of_the_key() {
+        let certificate = b"DER encoded certificate".to_vec();
+        let mut jwk = JsonWebKey::new(KeyType::Oct { k: vec![1; 32] });
+        jwk.x509_chain = vec![certificate.clone()];
+        let with_x5t = |x5t: &[u8]| {
+            jwt::JsonWebToken::new()
+                .add_header(&Header::Registered(RegisteredHeader::X509CertificateSha1Thumbprint),
+                            Json::String(x5t.to_base64(URL_SAFE)))
+        };
+
+        assert_eq!(Ok(()), jwt::JsonWebToken::new().check_x509_thumbprint(&jwk));
+        assert_eq!(Ok(()), with_x5t(&sha1(&certificate)).check_x509_thumbprint(&jwk));
+        assert_eq!(Err(VerificationError::X509ThumbprintMismatch),
+                   with_x5t(&sha1(b"other")).check_x509_thumbprint(&jwk));
+
+        jwk.x509_sha1_thumbprint = Some(sha1(b"other").to_vec());
+        assert_eq!(Ok(()), with_x5t(&sha1(b"other")).check_x509_thumbprint(&jwk));
+    }
 }
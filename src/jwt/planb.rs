@@ -2,9 +2,10 @@
 //!
 //! A JWT Token according to [Plan B](https://github.com/zalando/planb-provider)
 use std::str::FromStr;
-use rustc_serialize::json::Json;
 use chrono::*;
 use super::*;
+use super::signature::VerificationKey;
+use super::jwk::JwkSet;
 
 /// The header of JWT token as returned by Plan B
 #[derive(PartialEq, Debug)]
@@ -24,6 +25,24 @@ pub struct PlanbPayload {
     pub issue_date_utc: NaiveDateTime,
 }
 
+/// The header fields of a Plan B token as named in the JSON
+#[derive(RustcDecodable)]
+struct PlanbHeaderFields {
+    kid: String,
+    alg: String,
+}
+
+/// The claims of a Plan B token as named in the JSON
+#[derive(RustcDecodable)]
+struct PlanbClaims {
+    sub: String,
+    realm: String,
+    scope: Vec<String>,
+    iss: String,
+    exp: i64,
+    iat: i64,
+}
+
 /// A JWT token as returned by Plan B
 #[derive(PartialEq, Debug)]
 pub struct PlanbToken {
@@ -43,72 +62,55 @@ impl PlanbToken {
     /// Takes a JWT token and makes a Plan B token from it.
     /// May fail if the required fields for a Plan B JWT token are not supplied with the JWT token.
     pub fn from_jwt_token(jwt_token: &JsonWebToken) -> Result<PlanbToken, &'static str> {
-        let kid: &str = try!{
-            jwt_token.get_registered_header(RegisteredHeader::KeyId).and_then(|json|
-                json.as_string()).ok_or("Custom field 'kid' is missing or not a String.") };
-        let algorithm: &str = try!{
-            jwt_token.get_header(&Header::Registered(RegisteredHeader::Algorithm)).and_then(|json|
-                json.as_string()).ok_or("Field 'Algorithm' is missing or not a String.") };
-
-        let header = PlanbHeader {
-            key_id: String::from(kid),
-            algorithm: String::from(algorithm),
-        };
-
-        let subject: &str = try!{
-            jwt_token.get_registered_payload(RegisteredClaim::Subject).and_then(|json|
-                json.as_string()).ok_or("Field 'Subject' is missing or not a String.") };
-        let realm: &str = try!{
-            jwt_token.get_payload(&Claim::Custom("realm")).and_then(|json|
-                json.as_string()).ok_or("Custom field 'realm' is missing or not a String.") };
-        let scopes_json: &Vec<Json> = try!{
-            jwt_token.get_payload(&Claim::Custom("scope"))
-                .and_then(|json| json.as_array())
-                .ok_or("Custom field 'realm' is missing or not an array.") };
-        let scopes_res: Result<Vec<_>, _> = scopes_json.into_iter()
-            .map(|elem| {
-                elem.as_string()
-                    .map(|x| String::from(x))
-                    .ok_or("Element in scopes not a String")
-            })
-            .collect();
-
-        let scopes = try!{scopes_res};
-
-        let issuer: &str = try!{
-            jwt_token.get_registered_payload(RegisteredClaim::Issuer).and_then(|json|
-                json.as_string()).ok_or("Field 'Issuer' is missing or not a String.") };
-        let expiration_date_unix_seconds: i64 = try!{
-            jwt_token.get_registered_payload(RegisteredClaim::ExpirationTime).and_then(|json|
-                json.as_i64()).ok_or("Field 'ExpirationTime' is missing or not a i64.") };
-        let expiration_date =
-            try!{NaiveDateTime::from_timestamp_opt(expiration_date_unix_seconds, 0).ok_or("Field 'ExpirationTime' is not a unix epoch.")};
-        let issue_date_unix_seconds: i64 = try!{
-            jwt_token.get_registered_payload(RegisteredClaim::IssuedAt).and_then(|json|
-                json.as_i64()).ok_or("Field 'IssuedAt' is missing or not a i64.") };
-        let issue_date =
-            try!{NaiveDateTime::from_timestamp_opt(issue_date_unix_seconds, 0).ok_or("Field 'IssuedAt' is not a unix epoch.")};
-
-        let payload = PlanbPayload {
-            subject: String::from(subject),
-            realm: String::from(realm),
-            scopes: scopes,
-            issuer: String::from(issuer),
-            expiration_date_utc: expiration_date,
-            issue_date_utc: issue_date,
-        };
+        let header = try!{jwt_token.decode_header::<PlanbHeaderFields>().map_err(|err| {
+            debug!("Not a Plan B header: {}", err);
+            "Field 'kid' or 'alg' is missing or not a String."
+        })};
+        let claims = try!{jwt_token.decode_claims::<PlanbClaims>().map_err(|err| {
+            debug!("Not a Plan B payload: {}", err);
+            "Field 'sub', 'realm', 'scope', 'iss', 'exp' or 'iat' is missing or of the wrong type."
+        })};
+        let expiration_date = try!{NaiveDateTime::from_timestamp_opt(claims.exp, 0)
+            .ok_or("Field 'exp' is not a unix epoch.")};
+        let issue_date = try!{NaiveDateTime::from_timestamp_opt(claims.iat, 0)
+            .ok_or("Field 'iat' is not a unix epoch.")};
 
         Ok(PlanbToken {
-            header: header,
-            payload: payload,
+            header: PlanbHeader {
+                key_id: header.kid,
+                algorithm: header.alg,
+            },
+            payload: PlanbPayload {
+                subject: claims.sub,
+                realm: claims.realm,
+                scopes: claims.scope,
+                issuer: claims.iss,
+                expiration_date_utc: expiration_date,
+                issue_date_utc: issue_date,
+            },
         })
     }
+
+    /// Parses a Plan B token and verifies its signature with the given key.
+    ///
+    /// Use the `key_id` of the `PlanbHeader` to select the key.
+    pub fn verify_with(token: &str, key: &VerificationKey) -> Result<PlanbToken, VerificationError> {
+        let jwt_token = try!{JsonWebToken::verify_with(token, key)};
+        PlanbToken::from_jwt_token(&jwt_token).map_err(|x| VerificationError::Malformed(String::from(x)))
+    }
+
+    /// Parses a Plan B token and verifies its signature with the key of `key_set`
+    /// that matches the `kid` of the token.
+    pub fn verify_with_key_set(token: &str, key_set: &JwkSet) -> Result<PlanbToken, VerificationError> {
+        let jwt_token = try!{JsonWebToken::verify_with_key_set(token, key_set)};
+        PlanbToken::from_jwt_token(&jwt_token).map_err(|x| VerificationError::Malformed(String::from(x)))
+    }
 }
 
 impl FromStr for PlanbToken {
     type Err = String;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let jwt_token = try!{JsonWebToken::from_str(s)};
+        let jwt_token = try!{JsonWebToken::from_str(s).map_err(|err| err.to_string())};
         PlanbToken::from_jwt_token(&jwt_token).map_err(|x| String::from(x))
     }
 }
@@ -118,11 +120,84 @@ mod test {
     use super::{PlanbToken, PlanbHeader, PlanbPayload};
     use std::str::FromStr;
     use chrono::{NaiveDate, NaiveTime, NaiveDateTime};
+    use jwt::VerificationError;
+    use jwt::signature::SignatureError;
+    use jwt::jwk::JwkSet;
+    use es256_fixture::{generate_key, jwk, sign_es256, verification_key};
 
     const SAMPLE_TOKEN: &'static str = "eyJraWQiOiJ0ZXN0a2V5LWVzMjU2IiwiYWxnIjoiRVMyNTYifQ.\
                                         eyJzdWIiOiJ0ZXN0MiIsInNjb3BlIjpbImNuIl0sImlzcyI6IkIiLCJyZWFsbSI6Ii9zZXJ2aWNlcyIsImV4cCI6MTQ1NzMxOTgxNCwiaWF0IjoxNDU3MjkxMDE0fQ.\
                                         KmDsVB09RAOYwT0Y6E9tdQpg0rAPd8SExYhcZ9tXEO6y9AWX4wBylnmNHVoetWu7MwoexWkaKdpKk09IodMVug";
 
+    const SAMPLE_SIGNING_INPUT: &'static str = "eyJraWQiOiJ0ZXN0a2V5LWVzMjU2IiwiYWxnIjoiRVMyNTYifQ.\
+                                                eyJzdWIiOiJ0ZXN0MiIsInNjb3BlIjpbImNuIl0sImlzcyI6IkIiLCJyZWFsbSI6Ii9zZXJ2aWNlcyIsImV4cCI6MTQ1NzMxOTgxNCwiaWF0IjoxNDU3MjkxMDE0fQ";
+
+    #[test]
+    fn verify_with_must_accept_a_token_signed_with_the_key() {
+        let key = generate_key();
+        let token = sign_es256(SAMPLE_SIGNING_INPUT, &key);
+
+        let result = PlanbToken::verify_with(&token, &verification_key(&key)).unwrap();
+
+        assert_eq!(PlanbToken::from_str(SAMPLE_TOKEN).unwrap(), result);
+    }
+
+    #[test]
+    fn verify_with_must_reject_a_token_signed_with_another_key() {
+        let key = generate_key();
+        let other_key = generate_key();
+        let token = sign_es256(SAMPLE_SIGNING_INPUT, &key);
+
+        let result = PlanbToken::verify_with(&token, &verification_key(&other_key));
+
+        assert_eq!(Err(VerificationError::Signature(SignatureError::InvalidSignature)),
+                   result);
+    }
+
+    #[test]
+    fn verify_with_key_set_must_use_the_key_matching_the_kid() {
+        let key = generate_key();
+        let other_key = generate_key();
+        let token = sign_es256(SAMPLE_SIGNING_INPUT, &key);
+        let key_set = JwkSet::new(vec![jwk(&other_key, "other-key"), jwk(&key, "testkey-es256")]);
+
+        let result = PlanbToken::verify_with_key_set(&token, &key_set).unwrap();
+
+        assert_eq!(PlanbToken::from_str(SAMPLE_TOKEN).unwrap(), result);
+    }
+
+    #[test]
+    fn verify_with_key_set_must_reject_an_unknown_kid() {
+        let key = generate_key();
+        let token = sign_es256(SAMPLE_SIGNING_INPUT, &key);
+        let key_set = JwkSet::new(vec![jwk(&key, "other-key")]);
+
+        let result = PlanbToken::verify_with_key_set(&token, &key_set);
+
+        assert_eq!(Err(VerificationError::UnknownKey(String::from("testkey-es256"))),
+                   result);
+    }
+
+    #[test]
+    fn verify_with_key_set_must_reject_an_algorithm_other_than_the_one_of_the_key() {
+        let key = generate_key();
+        let token = sign_es256(SAMPLE_SIGNING_INPUT, &key);
+        let mut restricted_key = jwk(&key, "testkey-es256");
+        restricted_key.algorithm = Some(String::from("RS256"));
+
+        let result = PlanbToken::verify_with_key_set(&token, &JwkSet::new(vec![restricted_key]));
+
+        assert_eq!(Err(VerificationError::AlgorithmNotAllowed(String::from("ES256"))),
+                   result);
+    }
+
+    #[test]
+    fn verify_with_must_reject_the_unverifiable_sample_token() {
+        let key = generate_key();
+
+        assert!(PlanbToken::verify_with(SAMPLE_TOKEN, &verification_key(&key)).is_err());
+    }
+
     #[test]
     fn parse_the_token() {
         let sample = SAMPLE_TOKEN;
@@ -0,0 +1,587 @@
+//! Creation and verification of [JWS signatures](https://tools.ietf.org/html/rfc7515)
+//!
+//! Supported for verification are `ES256` as used by
+//! [Plan B](https://github.com/zalando/planb-provider), the RSA algorithms
+//! `RS256`, `RS384`, `RS512` and `PS256` and the HMAC algorithms `HS256`, `HS384` and `HS512`.
+//! Tokens can be signed with the HMAC algorithms, `ES256` and `RS256`.
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use openssl::bn::BigNum;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::ecdsa::EcdsaSig;
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Public, Private};
+use openssl::rsa::{Rsa, Padding};
+use openssl::sha::sha256;
+use openssl::sign::{Signer, Verifier, RsaPssSaltlen};
+
+/// RSA keys smaller than this are rejected as required by
+/// [RFC 7518](https://tools.ietf.org/html/rfc7518#section-3.3).
+pub const MIN_RSA_KEY_BITS: u32 = 2048;
+
+/// The algorithms that can be used to sign a JWT.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Algorithm {
+    /// HMAC using SHA-256
+    HS256,
+    /// HMAC using SHA-384
+    HS384,
+    /// HMAC using SHA-512
+    HS512,
+    /// ECDSA using P-256 and SHA-256
+    ES256,
+    /// RSASSA-PKCS1-v1_5 using SHA-256
+    RS256,
+    /// RSASSA-PKCS1-v1_5 using SHA-384
+    RS384,
+    /// RSASSA-PKCS1-v1_5 using SHA-512
+    RS512,
+    /// RSASSA-PSS using SHA-256 and MGF1 with SHA-256
+    PS256,
+}
+
+impl Algorithm {
+    /// The value of the `alg` header for this algorithm
+    pub fn to_key(&self) -> &str {
+        match *self {
+            Algorithm::HS256 => "HS256",
+            Algorithm::HS384 => "HS384",
+            Algorithm::HS512 => "HS512",
+            Algorithm::ES256 => "ES256",
+            Algorithm::RS256 => "RS256",
+            Algorithm::RS384 => "RS384",
+            Algorithm::RS512 => "RS512",
+            Algorithm::PS256 => "PS256",
+        }
+    }
+
+    /// The digest of an HMAC algorithm or `None` for the other algorithms
+    fn hmac_digest(&self) -> Option<MessageDigest> {
+        match *self {
+            Algorithm::HS256 => Some(MessageDigest::sha256()),
+            Algorithm::HS384 => Some(MessageDigest::sha384()),
+            Algorithm::HS512 => Some(MessageDigest::sha512()),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = SignatureError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HS256" => Ok(Algorithm::HS256),
+            "HS384" => Ok(Algorithm::HS384),
+            "HS512" => Ok(Algorithm::HS512),
+            "ES256" => Ok(Algorithm::ES256),
+            "RS256" => Ok(Algorithm::RS256),
+            "RS384" => Ok(Algorithm::RS384),
+            "RS512" => Ok(Algorithm::RS512),
+            "PS256" => Ok(Algorithm::PS256),
+            unknown => Err(SignatureError::UnsupportedAlgorithm(unknown.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_key())
+    }
+}
+
+/// A public key that can be used to verify the signature of a JWT.
+pub enum VerificationKey {
+    /// An ECDSA public key on the P-256 curve. Used with `ES256`.
+    EcP256(EcKey<Public>),
+    /// An RSA public key. Used with `RS256`, `RS384`, `RS512` and `PS256`.
+    Rsa(PKey<Public>),
+    /// A shared secret. Used with `HS256`, `HS384` and `HS512`.
+    Hmac(Vec<u8>),
+}
+
+impl VerificationKey {
+    /// Creates an HMAC key from a shared secret.
+    pub fn hmac_from_secret(secret: &[u8]) -> Result<VerificationKey, SignatureError> {
+        if secret.is_empty() {
+            return Err(SignatureError::InvalidKey(String::from("The HMAC secret is empty.")));
+        }
+        Ok(VerificationKey::Hmac(secret.to_vec()))
+    }
+
+    /// Creates a P-256 key from the big endian encoded affine coordinates of the public point.
+    pub fn ec_p256_from_coordinates(x: &[u8], y: &[u8]) -> Result<VerificationKey, SignatureError> {
+        let group = try!{EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)};
+        let x = try!{BigNum::from_slice(x)};
+        let y = try!{BigNum::from_slice(y)};
+        let key = try!{EcKey::from_public_key_affine_coordinates(&group, &x, &y)};
+        try!{key.check_key()};
+        Ok(VerificationKey::EcP256(key))
+    }
+
+    /// Creates a P-256 key from a PEM encoded public key.
+    pub fn ec_p256_from_pem(pem: &[u8]) -> Result<VerificationKey, SignatureError> {
+        let key = try!{EcKey::public_key_from_pem(pem)};
+        if key.group().curve_name() != Some(Nid::X9_62_PRIME256V1) {
+            return Err(SignatureError::InvalidKey(String::from("The key is not on the P-256 \
+                                                                curve.")));
+        }
+        Ok(VerificationKey::EcP256(key))
+    }
+
+    /// Creates an RSA key from the big endian encoded modulus and public exponent.
+    pub fn rsa_from_components(n: &[u8], e: &[u8]) -> Result<VerificationKey, SignatureError> {
+        let n = try!{BigNum::from_slice(n)};
+        let e = try!{BigNum::from_slice(e)};
+        let rsa = try!{Rsa::from_public_components(n, e)};
+        VerificationKey::from_rsa(rsa)
+    }
+
+    /// Creates an RSA key from a PEM encoded public key.
+    pub fn rsa_from_pem(pem: &[u8]) -> Result<VerificationKey, SignatureError> {
+        let rsa = try!{Rsa::public_key_from_pem(pem)};
+        VerificationKey::from_rsa(rsa)
+    }
+
+    /// Creates a P-256 or RSA key from a public key, e.g. the one of a certificate.
+    pub fn from_public_key(key: &PKey<Public>) -> Result<VerificationKey, SignatureError> {
+        match key.id() {
+            Id::EC => {
+                let key = try!{key.ec_key()};
+                if key.group().curve_name() != Some(Nid::X9_62_PRIME256V1) {
+                    return Err(SignatureError::InvalidKey(String::from("The key is not on the \
+                                                                        P-256 curve.")));
+                }
+                Ok(VerificationKey::EcP256(key))
+            }
+            Id::RSA => VerificationKey::from_rsa(try!{key.rsa()}),
+            _ => {
+                Err(SignatureError::InvalidKey(String::from("Only P-256 and RSA keys are \
+                                                             supported.")))
+            }
+        }
+    }
+
+    /// Whether the key can be used with `algorithm`.
+    ///
+    /// An HMAC algorithm is never supported by a public key, so that a public key can
+    /// not be abused as a shared secret.
+    pub fn supports(&self, algorithm: Algorithm) -> bool {
+        match (self, algorithm) {
+            (&VerificationKey::EcP256(_), Algorithm::ES256) |
+            (&VerificationKey::Rsa(_), Algorithm::RS256) |
+            (&VerificationKey::Rsa(_), Algorithm::RS384) |
+            (&VerificationKey::Rsa(_), Algorithm::RS512) |
+            (&VerificationKey::Rsa(_), Algorithm::PS256) |
+            (&VerificationKey::Hmac(_), Algorithm::HS256) |
+            (&VerificationKey::Hmac(_), Algorithm::HS384) |
+            (&VerificationKey::Hmac(_), Algorithm::HS512) => true,
+            _ => false,
+        }
+    }
+
+    fn from_rsa(rsa: Rsa<Public>) -> Result<VerificationKey, SignatureError> {
+        if rsa.size() * 8 < MIN_RSA_KEY_BITS {
+            return Err(SignatureError::InvalidKey(format!("RSA keys must have at least {} bits.",
+                                                          MIN_RSA_KEY_BITS)));
+        }
+        Ok(VerificationKey::Rsa(try!{PKey::from_rsa(rsa)}))
+    }
+}
+
+/// A private key or a shared secret that can be used to sign a JWT.
+///
+/// Each kind of key signs with exactly one `Algorithm`.
+pub enum SigningKey {
+    /// A shared secret. Used with `HS256`, `HS384` or `HS512`.
+    Hmac(Algorithm, Vec<u8>),
+    /// An ECDSA private key on the P-256 curve. Used with `ES256`.
+    EcP256(EcKey<Private>),
+    /// An RSA private key. Used with `RS256`.
+    Rsa(PKey<Private>),
+}
+
+impl SigningKey {
+    /// Creates a key for one of the HMAC algorithms.
+    ///
+    /// The secret must be at least as long as the output of the hash function as required by
+    /// [RFC 7518](https://tools.ietf.org/html/rfc7518#section-3.2).
+    pub fn hmac_from_secret(algorithm: Algorithm,
+                            secret: &[u8])
+                            -> Result<SigningKey, SignatureError> {
+        let digest = try!{algorithm.hmac_digest()
+            .ok_or_else(|| SignatureError::IncompatibleKey(algorithm))};
+        if secret.len() < digest.size() {
+            return Err(SignatureError::InvalidKey(format!("{} secrets must have at least {} \
+                                                           bytes.",
+                                                          algorithm,
+                                                          digest.size())));
+        }
+        Ok(SigningKey::Hmac(algorithm, secret.to_vec()))
+    }
+
+    /// Creates a P-256 key from a PEM encoded private key.
+    pub fn ec_p256_from_pem(pem: &[u8]) -> Result<SigningKey, SignatureError> {
+        let key = try!{EcKey::private_key_from_pem(pem)};
+        if key.group().curve_name() != Some(Nid::X9_62_PRIME256V1) {
+            return Err(SignatureError::InvalidKey(String::from("The key is not on the P-256 \
+                                                                curve.")));
+        }
+        Ok(SigningKey::EcP256(key))
+    }
+
+    /// Creates an RSA key from a PEM encoded private key.
+    pub fn rsa_from_pem(pem: &[u8]) -> Result<SigningKey, SignatureError> {
+        let rsa = try!{Rsa::private_key_from_pem(pem)};
+        if rsa.size() * 8 < MIN_RSA_KEY_BITS {
+            return Err(SignatureError::InvalidKey(format!("RSA keys must have at least {} bits.",
+                                                          MIN_RSA_KEY_BITS)));
+        }
+        Ok(SigningKey::Rsa(try!{PKey::from_rsa(rsa)}))
+    }
+
+    /// The `Algorithm` this key signs with
+    pub fn algorithm(&self) -> Algorithm {
+        match *self {
+            SigningKey::Hmac(algorithm, _) => algorithm,
+            SigningKey::EcP256(_) => Algorithm::ES256,
+            SigningKey::Rsa(_) => Algorithm::RS256,
+        }
+    }
+}
+
+/// Signs `signing_input` with `key` using the `Algorithm` of the key.
+///
+/// The result is the JWS signature, i.e. for `ES256` the concatenation of `r` and `s`.
+pub fn sign(key: &SigningKey, signing_input: &[u8]) -> Result<Vec<u8>, SignatureError> {
+    match *key {
+        SigningKey::Hmac(algorithm, ref secret) => {
+            let digest = try!{algorithm.hmac_digest()
+                .ok_or_else(|| SignatureError::IncompatibleKey(algorithm))};
+            hmac(digest, secret, signing_input)
+        }
+        SigningKey::EcP256(ref ec_key) => {
+            let ecdsa_sig = try!{EcdsaSig::sign(&sha256(signing_input), ec_key)};
+            let mut signature = vec![0u8; 64];
+            let r = ecdsa_sig.r().to_vec();
+            let s = ecdsa_sig.s().to_vec();
+            signature[32 - r.len()..32].copy_from_slice(&r);
+            signature[64 - s.len()..].copy_from_slice(&s);
+            Ok(signature)
+        }
+        SigningKey::Rsa(ref pkey) => {
+            let mut signer = try!{Signer::new(MessageDigest::sha256(), pkey)};
+            try!{signer.update(signing_input)};
+            Ok(try!{signer.sign_to_vec()})
+        }
+    }
+}
+
+/// Verifies the `signature` over `signing_input` with `key` using the JWS `algorithm`.
+///
+/// The `signing_input` is the ASCII representation of `BASE64URL(header).BASE64URL(payload)`.
+pub fn verify_signature(algorithm: Algorithm,
+                        key: &VerificationKey,
+                        signing_input: &[u8],
+                        signature: &[u8])
+                        -> Result<(), SignatureError> {
+    match (algorithm, key) {
+        (Algorithm::ES256, &VerificationKey::EcP256(ref ec_key)) => {
+            verify_ecdsa(ec_key, 32, &sha256(signing_input), signature)
+        }
+        (Algorithm::RS256, &VerificationKey::Rsa(ref pkey)) => {
+            verify_rsa(pkey, MessageDigest::sha256(), false, signing_input, signature)
+        }
+        (Algorithm::RS384, &VerificationKey::Rsa(ref pkey)) => {
+            verify_rsa(pkey, MessageDigest::sha384(), false, signing_input, signature)
+        }
+        (Algorithm::RS512, &VerificationKey::Rsa(ref pkey)) => {
+            verify_rsa(pkey, MessageDigest::sha512(), false, signing_input, signature)
+        }
+        (Algorithm::PS256, &VerificationKey::Rsa(ref pkey)) => {
+            verify_rsa(pkey, MessageDigest::sha256(), true, signing_input, signature)
+        }
+        (Algorithm::HS256, &VerificationKey::Hmac(ref secret)) => {
+            verify_hmac(MessageDigest::sha256(), secret, signing_input, signature)
+        }
+        (Algorithm::HS384, &VerificationKey::Hmac(ref secret)) => {
+            verify_hmac(MessageDigest::sha384(), secret, signing_input, signature)
+        }
+        (Algorithm::HS512, &VerificationKey::Hmac(ref secret)) => {
+            verify_hmac(MessageDigest::sha512(), secret, signing_input, signature)
+        }
+        (algorithm, _) => Err(SignatureError::IncompatibleKey(algorithm)),
+    }
+}
+
+fn hmac(digest: MessageDigest,
+        secret: &[u8],
+        signing_input: &[u8])
+        -> Result<Vec<u8>, SignatureError> {
+    let pkey = try!{PKey::hmac(secret)};
+    let mut signer = try!{Signer::new(digest, &pkey)};
+    try!{signer.update(signing_input)};
+    Ok(try!{signer.sign_to_vec()})
+}
+
+/// The signatures are compared in constant time to not leak how many bytes matched.
+fn verify_hmac(digest: MessageDigest,
+               secret: &[u8],
+               signing_input: &[u8],
+               signature: &[u8])
+               -> Result<(), SignatureError> {
+    let expected = try!{hmac(digest, secret, signing_input)};
+    if expected.len() == signature.len() && memcmp::eq(&expected, signature) {
+        Ok(())
+    } else {
+        Err(SignatureError::InvalidSignature)
+    }
+}
+
+fn verify_rsa(pkey: &PKey<Public>,
+              digest: MessageDigest,
+              pss: bool,
+              signing_input: &[u8],
+              signature: &[u8])
+              -> Result<(), SignatureError> {
+    let mut verifier = try!{Verifier::new(digest, pkey)};
+    if pss {
+        try!{verifier.set_rsa_padding(Padding::PKCS1_PSS)};
+        try!{verifier.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)};
+        try!{verifier.set_rsa_mgf1_md(digest)};
+    }
+    try!{verifier.update(signing_input)};
+    match verifier.verify(signature) {
+        Ok(true) => Ok(()),
+        Ok(false) | Err(_) => Err(SignatureError::InvalidSignature),
+    }
+}
+
+/// JWS encodes ECDSA signatures as the concatenation of `r` and `s`, each
+/// left padded to the size of the curve order.
+fn verify_ecdsa(key: &EcKey<Public>,
+                component_len: usize,
+                digest: &[u8],
+                signature: &[u8])
+                -> Result<(), SignatureError> {
+    if signature.len() != 2 * component_len {
+        return Err(SignatureError::InvalidSignature);
+    }
+    let r = try!{BigNum::from_slice(&signature[..component_len])};
+    let s = try!{BigNum::from_slice(&signature[component_len..])};
+    let ecdsa_sig = try!{EcdsaSig::from_private_components(r, s)};
+    if try!{ecdsa_sig.verify(digest, key)} {
+        Ok(())
+    } else {
+        Err(SignatureError::InvalidSignature)
+    }
+}
+
+/// Errors that can occur when verifying a signature.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignatureError {
+    /// The algorithm is not supported
+    UnsupportedAlgorithm(String),
+    /// The key can not be used with the algorithm
+    IncompatibleKey(Algorithm),
+    /// The key could not be used
+    InvalidKey(String),
+    /// The signature does not match the signed data
+    InvalidSignature,
+    /// The crypto library failed
+    CryptoError(String),
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SignatureError::UnsupportedAlgorithm(ref algorithm) => {
+                write!(f, "Unsupported algorithm: {}", algorithm)
+            }
+            SignatureError::IncompatibleKey(ref algorithm) => {
+                write!(f, "The key can not be used with algorithm {}", algorithm)
+            }
+            SignatureError::InvalidKey(ref message) => write!(f, "Invalid key: {}", message),
+            SignatureError::InvalidSignature => write!(f, "Invalid signature"),
+            SignatureError::CryptoError(ref message) => write!(f, "Crypto error: {}", message),
+        }
+    }
+}
+
+impl Error for SignatureError {
+    fn description(&self) -> &str {
+        match *self {
+            SignatureError::UnsupportedAlgorithm(_) => "Unsupported algorithm",
+            SignatureError::IncompatibleKey(_) => "The key can not be used with the algorithm",
+            SignatureError::InvalidKey(ref message) |
+            SignatureError::CryptoError(ref message) => message.as_ref(),
+            SignatureError::InvalidSignature => "Invalid signature",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        None
+    }
+}
+
+impl From<ErrorStack> for SignatureError {
+    fn from(err: ErrorStack) -> Self {
+        SignatureError::CryptoError(format!("{}", err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::{PKey, Private};
+    use openssl::rsa::{Rsa, Padding};
+    use openssl::sign::{Signer, RsaPssSaltlen};
+    use super::{Algorithm, VerificationKey, SigningKey, SignatureError, sign, verify_signature};
+
+    const SIGNING_INPUT: &'static [u8] = b"eyJhbGciOiJSUzI1NiJ9.eyJzdWIiOiJ0ZXN0MiJ9";
+
+    fn generate_rsa_key(bits: u32) -> (PKey<Private>, VerificationKey) {
+        let rsa = Rsa::generate(bits).unwrap();
+        let public_key = VerificationKey::rsa_from_pem(&rsa.public_key_to_pem().unwrap());
+        (PKey::from_rsa(rsa).unwrap(), public_key.unwrap())
+    }
+
+    fn sign_rsa(key: &PKey<Private>, digest: MessageDigest, pss: bool) -> Vec<u8> {
+        let mut signer = Signer::new(digest, key).unwrap();
+        if pss {
+            signer.set_rsa_padding(Padding::PKCS1_PSS).unwrap();
+            signer.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH).unwrap();
+            signer.set_rsa_mgf1_md(digest).unwrap();
+        }
+        signer.update(SIGNING_INPUT).unwrap();
+        signer.sign_to_vec().unwrap()
+    }
+
+    #[test]
+    fn algorithm_must_be_parsed_from_the_alg_header_value() {
+        assert_eq!(Ok(Algorithm::HS256), Algorithm::from_str("HS256"));
+        assert_eq!(Ok(Algorithm::HS384), Algorithm::from_str("HS384"));
+        assert_eq!(Ok(Algorithm::HS512), Algorithm::from_str("HS512"));
+        assert_eq!(Ok(Algorithm::ES256), Algorithm::from_str("ES256"));
+        assert_eq!(Ok(Algorithm::RS256), Algorithm::from_str("RS256"));
+        assert_eq!(Ok(Algorithm::RS384), Algorithm::from_str("RS384"));
+        assert_eq!(Ok(Algorithm::RS512), Algorithm::from_str("RS512"));
+        assert_eq!(Ok(Algorithm::PS256), Algorithm::from_str("PS256"));
+    }
+
+    #[test]
+    fn an_unknown_algorithm_must_be_a_typed_error() {
+        assert_eq!(Err(SignatureError::UnsupportedAlgorithm(String::from("XY999"))),
+                   Algorithm::from_str("XY999"));
+    }
+
+    #[test]
+    fn rsa_signatures_must_be_verified() {
+        let (private_key, public_key) = generate_rsa_key(2048);
+        let cases = vec![(Algorithm::RS256, MessageDigest::sha256(), false),
+                         (Algorithm::RS384, MessageDigest::sha384(), false),
+                         (Algorithm::RS512, MessageDigest::sha512(), false),
+                         (Algorithm::PS256, MessageDigest::sha256(), true)];
+
+        for (algorithm, digest, pss) in cases {
+            let signature = sign_rsa(&private_key, digest, pss);
+            assert_eq!(Ok(()),
+                       verify_signature(algorithm, &public_key, SIGNING_INPUT, &signature));
+        }
+    }
+
+    #[test]
+    fn a_pss_signature_must_not_verify_as_pkcs1() {
+        let (private_key, public_key) = generate_rsa_key(2048);
+        let signature = sign_rsa(&private_key, MessageDigest::sha256(), true);
+
+        assert_eq!(Err(SignatureError::InvalidSignature),
+                   verify_signature(Algorithm::RS256, &public_key, SIGNING_INPUT, &signature));
+    }
+
+    #[test]
+    fn a_signature_with_another_digest_must_be_rejected() {
+        let (private_key, public_key) = generate_rsa_key(2048);
+        let signature = sign_rsa(&private_key, MessageDigest::sha512(), false);
+
+        assert_eq!(Err(SignatureError::InvalidSignature),
+                   verify_signature(Algorithm::RS256, &public_key, SIGNING_INPUT, &signature));
+    }
+
+    #[test]
+    fn an_rsa_key_must_not_be_used_with_es256() {
+        let (private_key, public_key) = generate_rsa_key(2048);
+        let signature = sign_rsa(&private_key, MessageDigest::sha256(), false);
+
+        assert_eq!(Err(SignatureError::IncompatibleKey(Algorithm::ES256)),
+                   verify_signature(Algorithm::ES256, &public_key, SIGNING_INPUT, &signature));
+    }
+
+    #[test]
+    fn small_rsa_keys_must_be_rejected() {
+        let rsa = Rsa::generate(1024).unwrap();
+
+        let result = VerificationKey::rsa_from_pem(&rsa.public_key_to_pem().unwrap());
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn an_rs256_signature_must_be_verifiable() {
+        let rsa = Rsa::generate(2048).unwrap();
+        let public_key = VerificationKey::rsa_from_pem(&rsa.public_key_to_pem().unwrap()).unwrap();
+        let signing_key = SigningKey::rsa_from_pem(&rsa.private_key_to_pem().unwrap()).unwrap();
+
+        let signature = sign(&signing_key, SIGNING_INPUT).unwrap();
+
+        assert_eq!(Ok(()),
+                   verify_signature(Algorithm::RS256, &public_key, SIGNING_INPUT, &signature));
+    }
+
+    #[test]
+    fn short_hmac_secrets_must_be_rejected() {
+        assert!(SigningKey::hmac_from_secret(Algorithm::HS256, b"too short").is_err());
+        assert!(SigningKey::hmac_from_secret(Algorithm::HS256, &[7u8; 32]).is_ok());
+        assert!(SigningKey::hmac_from_secret(Algorithm::HS512, &[7u8; 32]).is_err());
+    }
+
+    #[test]
+    fn hmac_signatures_must_be_verified() {
+        let secret = [7u8; 64];
+        let verification_key = VerificationKey::hmac_from_secret(&secret).unwrap();
+
+        for algorithm in vec![Algorithm::HS256, Algorithm::HS384, Algorithm::HS512] {
+            let signing_key = SigningKey::hmac_from_secret(algorithm, &secret).unwrap();
+            let signature = sign(&signing_key, SIGNING_INPUT).unwrap();
+            assert_eq!(Ok(()),
+                       verify_signature(algorithm, &verification_key, SIGNING_INPUT, &signature));
+        }
+    }
+
+    #[test]
+    fn an_hmac_signature_must_be_rejected_with_another_secret_or_digest() {
+        let signing_key = SigningKey::hmac_from_secret(Algorithm::HS256, &[7u8; 32]).unwrap();
+        let signature = sign(&signing_key, SIGNING_INPUT).unwrap();
+        let other_key = VerificationKey::hmac_from_secret(&[8u8; 32]).unwrap();
+        let same_key = VerificationKey::hmac_from_secret(&[7u8; 32]).unwrap();
+
+        assert_eq!(Err(SignatureError::InvalidSignature),
+                   verify_signature(Algorithm::HS256, &other_key, SIGNING_INPUT, &signature));
+        assert_eq!(Err(SignatureError::InvalidSignature),
+                   verify_signature(Algorithm::HS512, &same_key, SIGNING_INPUT, &signature));
+        assert_eq!(Err(SignatureError::InvalidSignature),
+                   verify_signature(Algorithm::HS256, &same_key, SIGNING_INPUT, &signature[1..]));
+    }
+
+    #[test]
+    fn an_hmac_key_must_not_be_used_with_rs256() {
+        let key = VerificationKey::hmac_from_secret(&[7u8; 32]).unwrap();
+
+        assert_eq!(Err(SignatureError::IncompatibleKey(Algorithm::RS256)),
+                   verify_signature(Algorithm::RS256, &key, SIGNING_INPUT, &[0u8; 32]));
+    }
+}
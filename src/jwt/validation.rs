@@ -0,0 +1,364 @@
+//! Validation of the [registered claims](https://tools.ietf.org/html/rfc7519#section-4.1)
+//! of a `JsonWebToken`.
+//!
+//! Validate the claims only after the signature of the token has been verified.
+use std::error::Error;
+use std::fmt;
+use chrono::UTC;
+use rustc_serialize::json::Json;
+use super::{JsonWebToken, RegisteredClaim};
+
+/// Configures which registered claims of a token are checked.
+///
+/// `exp`, `nbf` and `iat` are checked against the current time whenever they are present.
+/// `exp` is required unless configured otherwise. The issuer and the audience
+/// are only checked if expected values were added.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Validation {
+    /// The tolerated clock skew in seconds
+    pub leeway_seconds: i64,
+    /// Whether a token without `exp` is rejected
+    pub require_expiration_time: bool,
+    /// The accepted values for `iss`. Any issuer is accepted if empty.
+    pub issuers: Vec<String>,
+    /// The accepted values for `aud`. Any audience is accepted if empty.
+    pub audiences: Vec<String>,
+}
+
+impl Validation {
+    /// Create a new instance without any leeway that requires `exp`.
+    pub fn new() -> Validation {
+        Validation {
+            leeway_seconds: 0,
+            require_expiration_time: true,
+            issuers: Vec::new(),
+            audiences: Vec::new(),
+        }
+    }
+
+    /// Builder method. Set the tolerated clock skew in seconds.
+    pub fn with_leeway_seconds(self, leeway_seconds: i64) -> Self {
+        let mut x = self;
+        x.leeway_seconds = leeway_seconds;
+        x
+    }
+
+    /// Builder method. Set whether a token without `exp` is rejected.
+    pub fn with_required_expiration_time(self, required: bool) -> Self {
+        let mut x = self;
+        x.require_expiration_time = required;
+        x
+    }
+
+    /// Builder method. Add an accepted issuer.
+    pub fn with_issuer<T: Into<String>>(self, issuer: T) -> Self {
+        let mut x = self;
+        x.issuers.push(issuer.into());
+        x
+    }
+
+    /// Builder method. Add an accepted audience.
+    pub fn with_audience<T: Into<String>>(self, audience: T) -> Self {
+        let mut x = self;
+        x.audiences.push(audience.into());
+        x
+    }
+
+    /// Validate the claims of the token against the current time.
+    pub fn validate(&self, token: &JsonWebToken) -> Result<(), ValidationError> {
+        self.validate_at(token, UTC::now().timestamp())
+    }
+
+    /// Validate the claims of the token against the given time in seconds since the epoch.
+    pub fn validate_at(&self, token: &JsonWebToken, now: i64) -> Result<(), ValidationError> {
+        match try!{get_numeric_date(token, RegisteredClaim::ExpirationTime)} {
+            Some(expiration_time) if expiration_time + self.leeway_seconds <= now => {
+                return Err(ValidationError::Expired(expiration_time))
+            }
+            None if self.require_expiration_time => {
+                return Err(ValidationError::MissingClaim(RegisteredClaim::ExpirationTime))
+            }
+            _ => (),
+        }
+
+        if let Some(not_before) = try!{get_numeric_date(token, RegisteredClaim::NotBefore)} {
+            if not_before - self.leeway_seconds > now {
+                return Err(ValidationError::NotYetValid(not_before));
+            }
+        }
+
+        if let Some(issued_at) = try!{get_numeric_date(token, RegisteredClaim::IssuedAt)} {
+            if issued_at - self.leeway_seconds > now {
+                return Err(ValidationError::IssuedInTheFuture(issued_at));
+            }
+        }
+
+        if !self.issuers.is_empty() {
+            let issuer = try!{token.get_registered_payload(RegisteredClaim::Issuer)
+                .ok_or(ValidationError::MissingClaim(RegisteredClaim::Issuer))
+                .and_then(|json| json.as_string()
+                    .ok_or(ValidationError::InvalidClaim(RegisteredClaim::Issuer)))};
+            if !self.issuers.iter().any(|expected| expected == issuer) {
+                return Err(ValidationError::InvalidIssuer(issuer.to_owned()));
+            }
+        }
+
+        if !self.audiences.is_empty() {
+            let audiences = try!{get_audiences(token)};
+            if !audiences.iter().any(|audience| self.audiences.contains(audience)) {
+                return Err(ValidationError::InvalidAudience(audiences));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A NumericDate may be given with fractions of a second.
+fn get_numeric_date(token: &JsonWebToken,
+                    claim: RegisteredClaim)
+                    -> Result<Option<i64>, ValidationError> {
+    match token.get_registered_payload(claim.clone()) {
+        None => Ok(None),
+        Some(json) => {
+            json.as_f64()
+                .map(|seconds| Some(seconds.floor() as i64))
+                .ok_or(ValidationError::InvalidClaim(claim))
+        }
+    }
+}
+
+fn get_audiences(token: &JsonWebToken) -> Result<Vec<String>, ValidationError> {
+    match token.get_registered_payload(RegisteredClaim::Audience) {
+        None => Err(ValidationError::MissingClaim(RegisteredClaim::Audience)),
+        Some(aud) => {
+            parse_audiences(aud).ok_or(ValidationError::InvalidClaim(RegisteredClaim::Audience))
+        }
+    }
+}
+
+/// The audiences of an `aud` claim, which is either a single String or an array of Strings.
+///
+/// Returns `None` if the claim has another type.
+pub fn parse_audiences(aud: &Json) -> Option<Vec<String>> {
+    match *aud {
+        Json::String(ref audience) => Some(vec![audience.clone()]),
+        Json::Array(ref audiences) => {
+            audiences.iter().map(|audience| audience.as_string().map(String::from)).collect()
+        }
+        _ => None,
+    }
+}
+
+/// The reasons for a token to fail the validation of its claims.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    /// The token expired at the given time
+    Expired(i64),
+    /// The token must not be used before the given time
+    NotYetValid(i64),
+    /// The token claims to be issued at the given time which is in the future
+    IssuedInTheFuture(i64),
+    /// The issuer is not accepted
+    InvalidIssuer(String),
+    /// None of the audiences is accepted
+    InvalidAudience(Vec<String>),
+    /// A required claim is missing
+    MissingClaim(RegisteredClaim),
+    /// A claim has the wrong type
+    InvalidClaim(RegisteredClaim),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::Expired(at) => write!(f, "The token expired at {}", at),
+            ValidationError::NotYetValid(at) => write!(f, "The token is not valid before {}", at),
+            ValidationError::IssuedInTheFuture(at) => {
+                write!(f, "The token claims to be issued in the future at {}", at)
+            }
+            ValidationError::InvalidIssuer(ref issuer) => write!(f, "Invalid issuer: {}", issuer),
+            ValidationError::InvalidAudience(ref audiences) => {
+                write!(f, "Invalid audience: {}", audiences.join(", "))
+            }
+            ValidationError::MissingClaim(ref claim) => {
+                write!(f, "Claim '{}' is missing", claim.to_key())
+            }
+            ValidationError::InvalidClaim(ref claim) => {
+                write!(f, "Claim '{}' has an invalid type", claim.to_key())
+            }
+        }
+    }
+}
+
+impl Error for ValidationError {
+    fn description(&self) -> &str {
+        match *self {
+            ValidationError::Expired(_) => "The token expired",
+            ValidationError::NotYetValid(_) => "The token is not valid yet",
+            ValidationError::IssuedInTheFuture(_) => "The token claims to be issued in the future",
+            ValidationError::InvalidIssuer(_) => "Invalid issuer",
+            ValidationError::InvalidAudience(_) => "Invalid audience",
+            ValidationError::MissingClaim(_) => "A claim is missing",
+            ValidationError::InvalidClaim(_) => "A claim has an invalid type",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rustc_serialize::json::Json;
+    use jwt::{JsonWebToken, Claim, RegisteredClaim};
+    use super::{Validation, ValidationError};
+
+    const NOW: i64 = 1457291014;
+
+    fn token_with(claims: Vec<(RegisteredClaim, Json)>) -> JsonWebToken {
+        claims.into_iter().fold(JsonWebToken::new(), |token, (claim, value)| {
+            token.add_payload(&Claim::Registered(claim), value)
+        })
+    }
+
+    fn valid_token() -> JsonWebToken {
+        token_with(vec![(RegisteredClaim::ExpirationTime, Json::I64(NOW + 60)),
+                        (RegisteredClaim::IssuedAt, Json::I64(NOW - 60)),
+                        (RegisteredClaim::Issuer, Json::String(String::from("B")))])
+    }
+
+    #[test]
+    fn a_valid_token_must_pass() {
+        assert_eq!(Ok(()), Validation::new().validate_at(&valid_token(), NOW));
+    }
+
+    #[test]
+    fn an_expired_token_must_fail() {
+        let token = token_with(vec![(RegisteredClaim::ExpirationTime, Json::I64(NOW - 1))]);
+
+        assert_eq!(Err(ValidationError::Expired(NOW - 1)),
+                   Validation::new().validate_at(&token, NOW));
+    }
+
+    #[test]
+    fn an_expired_token_must_pass_within_the_leeway() {
+        let token = token_with(vec![(RegisteredClaim::ExpirationTime, Json::I64(NOW - 1))]);
+
+        assert_eq!(Ok(()),
+                   Validation::new().with_leeway_seconds(5).validate_at(&token, NOW));
+    }
+
+    #[test]
+    fn a_fractional_expiration_time_must_be_accepted() {
+        let token = token_with(vec![(RegisteredClaim::ExpirationTime,
+                                     Json::F64((NOW + 10) as f64 + 0.5))]);
+
+        assert_eq!(Ok(()), Validation::new().validate_at(&token, NOW));
+    }
+
+    #[test]
+    fn a_missing_expiration_time_must_fail_unless_optional() {
+        let token = token_with(vec![]);
+
+        assert_eq!(Err(ValidationError::MissingClaim(RegisteredClaim::ExpirationTime)),
+                   Validation::new().validate_at(&token, NOW));
+        assert_eq!(Ok(()),
+                   Validation::new()
+                       .with_required_expiration_time(false)
+                       .validate_at(&token, NOW));
+    }
+
+    #[test]
+    fn an_expiration_time_that_is_no_number_must_fail() {
+        let token = token_with(vec![(RegisteredClaim::ExpirationTime,
+                                     Json::String(String::from("tomorrow")))]);
+
+        assert_eq!(Err(ValidationError::InvalidClaim(RegisteredClaim::ExpirationTime)),
+                   Validation::new().validate_at(&token, NOW));
+    }
+
+    #[test]
+    fn a_token_used_before_not_before_must_fail() {
+        let token = valid_token().add_payload(&Claim::Registered(RegisteredClaim::NotBefore),
+                                              Json::I64(NOW + 10));
+
+        assert_eq!(Err(ValidationError::NotYetValid(NOW + 10)),
+                   Validation::new().validate_at(&token, NOW));
+        assert_eq!(Ok(()),
+                   Validation::new().with_leeway_seconds(10).validate_at(&token, NOW));
+    }
+
+    #[test]
+    fn a_token_issued_in_the_future_must_fail() {
+        let token = valid_token().add_payload(&Claim::Registered(RegisteredClaim::IssuedAt),
+                                              Json::I64(NOW + 10));
+
+        assert_eq!(Err(ValidationError::IssuedInTheFuture(NOW + 10)),
+                   Validation::new().validate_at(&token, NOW));
+    }
+
+    #[test]
+    fn the_issuer_must_be_one_of_the_expected_issuers() {
+        let validation = Validation::new().with_issuer("A").with_issuer("B");
+
+        assert_eq!(Ok(()), validation.validate_at(&valid_token(), NOW));
+    }
+
+    #[test]
+    fn an_unexpected_issuer_must_fail() {
+        let validation = Validation::new().with_issuer("A");
+
+        assert_eq!(Err(ValidationError::InvalidIssuer(String::from("B"))),
+                   validation.validate_at(&valid_token(), NOW));
+    }
+
+    #[test]
+    fn a_missing_issuer_must_fail_if_issuers_are_expected() {
+        let token = token_with(vec![(RegisteredClaim::ExpirationTime, Json::I64(NOW + 60))]);
+        let validation = Validation::new().with_issuer("B");
+
+        assert_eq!(Err(ValidationError::MissingClaim(RegisteredClaim::Issuer)),
+                   validation.validate_at(&token, NOW));
+    }
+
+    #[test]
+    fn a_single_audience_must_be_accepted() {
+        let token = valid_token().add_payload(&Claim::Registered(RegisteredClaim::Audience),
+                                              Json::String(String::from("my-service")));
+        let validation = Validation::new().with_audience("my-service");
+
+        assert_eq!(Ok(()), validation.validate_at(&token, NOW));
+    }
+
+    #[test]
+    fn an_audience_array_must_be_accepted_if_one_audience_matches() {
+        let audiences = vec![Json::String(String::from("other-service")),
+                             Json::String(String::from("my-service"))];
+        let token = valid_token().add_payload(&Claim::Registered(RegisteredClaim::Audience),
+                                              Json::Array(audiences));
+        let validation = Validation::new().with_audience("my-service");
+
+        assert_eq!(Ok(()), validation.validate_at(&token, NOW));
+    }
+
+    #[test]
+    fn an_unexpected_audience_must_fail() {
+        let audiences = vec![Json::String(String::from("other-service"))];
+        let token = valid_token().add_payload(&Claim::Registered(RegisteredClaim::Audience),
+                                              Json::Array(audiences));
+        let validation = Validation::new().with_audience("my-service");
+
+        assert_eq!(Err(ValidationError::InvalidAudience(vec![String::from("other-service")])),
+                   validation.validate_at(&token, NOW));
+    }
+
+    #[test]
+    fn a_missing_audience_must_fail_if_audiences_are_expected() {
+        let validation = Validation::new().with_audience("my-service");
+
+        assert_eq!(Err(ValidationError::MissingClaim(RegisteredClaim::Audience)),
+                   validation.validate_at(&valid_token(), NOW));
+    }
+}
@@ -0,0 +1,288 @@
+//! Verification of tokens with a fixed set of keys.
+//!
+//! The `alg` header of a token is chosen by whoever created the token. A `JwtVerifier`
+//! therefore only accepts the algorithms that were explicitly allowed for the key
+//! the token refers to by its `kid`.
+use std::collections::HashMap;
+use std::str::FromStr;
+use super::{JsonWebToken, RegisteredHeader, VerificationError, Segment, split_segments,
+            decode_segment};
+use super::jws_json::JwsJson;
+use super::signature::{Algorithm, VerificationKey};
+
+struct AllowedKey {
+    key: VerificationKey,
+    algorithms: Vec<Algorithm>,
+}
+
+/// Verifies the signatures of tokens with keys that are each restricted to
+/// an allow-list of algorithms.
+///
+/// * `alg: none` is always rejected.
+/// * An HMAC algorithm is never accepted for a public key even if allowed.
+/// * Headers listed in `crit` must have been added with `with_critical_header`.
+pub struct JwtVerifier {
+    keys: HashMap<String, AllowedKey>,
+    critical_headers: Vec<String>,
+}
+
+impl JwtVerifier {
+    /// Create a new instance without any keys
+    pub fn new() -> JwtVerifier {
+        JwtVerifier {
+            keys: HashMap::new(),
+            critical_headers: Vec::new(),
+        }
+    }
+
+    /// Builder method. Add a key for the `kid` `key_id` that may only be used
+    /// with the given algorithms.
+    pub fn with_key<T: Into<String>>(self,
+                                     key_id: T,
+                                     key: VerificationKey,
+                                     algorithms: &[Algorithm])
+                                     -> Self {
+        let mut x = self;
+        x.keys.insert(key_id.into(),
+                      AllowedKey {
+                          key: key,
+                          algorithms: algorithms.to_vec(),
+                      });
+        x
+    }
+
+    /// Builder method. Add a header that is understood when it is listed in `crit`.
+    pub fn with_critical_header<T: Into<String>>(self, name: T) -> Self {
+        let mut x = self;
+        x.critical_headers.push(name.into());
+        x
+    }
+
+    /// Parses a token and verifies its signature with the key matching its `kid`.
+    pub fn verify(&self, token: &str) -> Result<JsonWebToken, VerificationError> {
+        let jwt_token = try!{JsonWebToken::from_str(token)};
+        let (header, payload, signature) = try!{split_segments(token)};
+        let signature_bytes = try!{decode_segment(Segment::Signature, signature)};
+        try!{self.check(&jwt_token, &format!("{}.{}", header, payload), &signature_bytes)};
+        Ok(jwt_token)
+    }
+
+    /// Parses a token in JWS JSON serialization and accepts it if any of its
+    /// signatures can be verified.
+    ///
+    /// If none can be verified the error of the last signature is returned.
+    pub fn verify_json(&self, serialized: &str) -> Result<JsonWebToken, VerificationError> {
+        let jws = try!{JwsJson::from_str(serialized)};
+        let mut last_error = None;
+        for signature in &jws.signatures {
+            if signature.header.contains_key(RegisteredHeader::Critical.to_key()) {
+                last_error = Some(VerificationError::Malformed(String::from("Field 'crit' must be \
+                                                                             protected.")));
+                continue;
+            }
+            let jwt_token = jws.to_token(signature);
+            match self.check(&jwt_token, &jws.signing_input(signature), &signature.signature) {
+                Ok(()) => return Ok(jwt_token),
+                Err(err) => last_error = Some(err),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| VerificationError::Malformed(String::from("No signatures."))))
+    }
+
+    fn check(&self,
+             jwt_token: &JsonWebToken,
+             signing_input: &str,
+             signature: &[u8])
+             -> Result<(), VerificationError> {
+        let alg = try!{jwt_token.get_registered_header(RegisteredHeader::Algorithm)
+            .and_then(|json| json.as_string())
+            .ok_or_else(|| VerificationError::Malformed(String::from("Field 'alg' is missing or not a String.")))};
+        let algorithm = try!{Algorithm::from_str(alg)
+            .map_err(|_| VerificationError::AlgorithmNotAllowed(alg.to_owned()))};
+        let allowed_key = {
+            let key_id = try!{jwt_token.get_registered_header(RegisteredHeader::KeyId)
+                .and_then(|json| json.as_string())
+                .ok_or_else(|| VerificationError::Malformed(String::from("Field 'kid' is missing or not a String.")))};
+            try!{self.keys
+                .get(key_id)
+                .ok_or_else(|| VerificationError::UnknownKey(key_id.to_owned()))}
+        };
+        if !allowed_key.algorithms.contains(&algorithm) || !allowed_key.key.supports(algorithm) {
+            return Err(VerificationError::AlgorithmNotAllowed(alg.to_owned()));
+        }
+        try!{jwt_token.check_critical_headers(&self.critical_headers)};
+        jwt_token.check_signature_over(signing_input, signature, &allowed_key.key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use rustc_serialize::base64::{ToBase64, URL_SAFE};
+    use rustc_serialize::json::Json;
+    use jwt::{JsonWebToken, Header, RegisteredHeader, Claim, RegisteredClaim, VerificationError};
+    use jwt::signature::{Algorithm, VerificationKey, SigningKey, SignatureError};
+    use jwt::jws_json::JwsJson;
+    use es256_fixture::{generate_key, verification_key};
+    use super::JwtVerifier;
+
+    fn sample_token() -> JsonWebToken {
+        JsonWebToken::new()
+            .add_header(&Header::Registered(RegisteredHeader::KeyId),
+                        Json::String(String::from("testkey")))
+            .add_payload(&Claim::Registered(RegisteredClaim::Subject),
+                         Json::String(String::from("test2")))
+    }
+
+    #[test]
+    fn a_token_with_an_allowed_algorithm_must_be_verified() {
+        let key = generate_key();
+        let verifier = JwtVerifier::new().with_key("testkey", verification_key(&key), &[Algorithm::ES256]);
+        let token = sample_token().encode(&SigningKey::EcP256(key)).unwrap();
+
+        assert!(verifier.verify(&token).is_ok());
+    }
+
+    #[test]
+    fn an_algorithm_not_in_the_allow_list_must_be_rejected() {
+        let secret = [7u8; 32];
+        let verifier = JwtVerifier::new().with_key("testkey",
+                                                   VerificationKey::hmac_from_secret(&secret)
+                                                       .unwrap(),
+                                                   &[Algorithm::HS512]);
+        let key = SigningKey::hmac_from_secret(Algorithm::HS256, &secret).unwrap();
+        let token = sample_token().encode(&key).unwrap();
+
+        assert_eq!(Err(VerificationError::AlgorithmNotAllowed(String::from("HS256"))),
+                   verifier.verify(&token));
+    }
+
+    #[test]
+    fn alg_none_must_be_rejected() {
+        let key = generate_key();
+        let verifier = JwtVerifier::new().with_key("testkey", verification_key(&key), &[Algorithm::ES256]);
+        let signing_input = format!("{}.{}",
+                                    b"{\"alg\":\"none\",\"kid\":\"testkey\"}".to_base64(URL_SAFE),
+                                    b"{\"sub\":\"test2\"}".to_base64(URL_SAFE));
+
+        assert_eq!(Err(VerificationError::AlgorithmNotAllowed(String::from("none"))),
+                   verifier.verify(&format!("{}.", signing_input)));
+    }
+
+    #[test]
+    fn an_hmac_token_must_be_rejected_for_a_public_key_even_if_allowed() {
+        let key = generate_key();
+        let public_pem = key.public_key_to_pem().unwrap();
+        let verifier = JwtVerifier::new().with_key("testkey",
+                                                   verification_key(&key),
+                                                   &[Algorithm::ES256, Algorithm::HS256]);
+        let forged_key = SigningKey::hmac_from_secret(Algorithm::HS256, &public_pem).unwrap();
+        let token = sample_token().encode(&forged_key).unwrap();
+
+        assert_eq!(Err(VerificationError::AlgorithmNotAllowed(String::from("HS256"))),
+                   verifier.verify(&token));
+    }
+
+    #[test]
+    fn a_token_with_an_unknown_key_id_must_be_rejected() {
+        let key = generate_key();
+        let verifier = JwtVerifier::new().with_key("otherkey", verification_key(&key), &[Algorithm::ES256]);
+        let token = sample_token().encode(&SigningKey::EcP256(key)).unwrap();
+
+        assert_eq!(Err(VerificationError::UnknownKey(String::from("testkey"))),
+                   verifier.verify(&token));
+    }
+
+    #[test]
+    fn critical_headers_must_be_understood_and_present() {
+        let key = generate_key();
+        let verifier = JwtVerifier::new()
+            .with_key("testkey", verification_key(&key), &[Algorithm::ES256])
+            .with_critical_header("b64");
+        let critical = |names: Vec<&str>| {
+            Json::Array(names.into_iter().map(|name| Json::String(String::from(name))).collect())
+        };
+        let signing_key = SigningKey::EcP256(key);
+
+        let understood = sample_token()
+            .add_header(&Header::Registered(RegisteredHeader::Critical),
+                        critical(vec!["b64"]))
+            .add_header(&Header::Custom("b64"), Json::Boolean(true))
+            .encode(&signing_key)
+            .unwrap();
+        let unknown = sample_token()
+            .add_header(&Header::Registered(RegisteredHeader::Critical),
+                        critical(vec!["exp"]))
+            .add_header(&Header::Custom("exp"), Json::U64(1))
+            .encode(&signing_key)
+            .unwrap();
+        let missing = sample_token()
+            .add_header(&Header::Registered(RegisteredHeader::Critical),
+                        critical(vec!["b64"]))
+            .encode(&signing_key)
+            .unwrap();
+        let empty = sample_token()
+            .add_header(&Header::Registered(RegisteredHeader::Critical), critical(vec![]))
+            .encode(&signing_key)
+            .unwrap();
+
+        assert!(verifier.verify(&understood).is_ok());
+        assert_eq!(Err(VerificationError::UnsupportedCriticalHeader(String::from("exp"))),
+                   verifier.verify(&unknown));
+        assert_eq!(Err(VerificationError::UnsupportedCriticalHeader(String::from("b64"))),
+                   verifier.verify(&missing));
+        assert!(verifier.verify(&empty).is_err());
+    }
+
+    #[test]
+    fn a_tampered_token_must_still_fail_the_signature_check() {
+        let key = generate_key();
+        let other_key = generate_key();
+        let verifier = JwtVerifier::new().with_key("testkey", verification_key(&key), &[Algorithm::ES256]);
+        let token = sample_token().encode(&SigningKey::EcP256(other_key)).unwrap();
+
+        assert_eq!(Err(VerificationError::Signature(SignatureError::InvalidSignature)),
+                   verifier.verify(&token));
+    }
+
+    fn to_general_json(tokens: &[&str]) -> String {
+        let mut payload = "";
+        let signatures: Vec<String> = tokens.iter()
+            .map(|token| {
+                let parts: Vec<&str> = token.split('.').collect();
+                payload = parts[1];
+                format!("{{\"protected\":\"{}\",\"signature\":\"{}\"}}",
+                        parts[0],
+                        parts[2])
+            })
+            .collect();
+        format!("{{\"payload\":\"{}\",\"signatures\":[{}]}}",
+                payload,
+                signatures.join(","))
+    }
+
+    #[test]
+    fn a_json_serialization_must_be_accepted_if_any_signature_is_valid() {
+        let key = generate_key();
+        let untrusted_key = generate_key();
+        let verifier = JwtVerifier::new().with_key("testkey", verification_key(&key), &[Algorithm::ES256]);
+        let untrusted = sample_token().encode(&SigningKey::EcP256(untrusted_key)).unwrap();
+        let trusted = sample_token().encode(&SigningKey::EcP256(key)).unwrap();
+        let serialized = to_general_json(&[&untrusted, &trusted]);
+
+        let result = verifier.verify_json(&serialized).unwrap();
+
+        assert_eq!(JwsJson::from_str(&serialized).unwrap().payload, result.payload);
+    }
+
+    #[test]
+    fn a_json_serialization_must_be_rejected_if_no_signature_is_valid() {
+        let key = generate_key();
+        let verifier = JwtVerifier::new().with_key("testkey", verification_key(&key), &[Algorithm::ES256]);
+        let untrusted = sample_token().encode(&SigningKey::EcP256(generate_key())).unwrap();
+        let other_untrusted = sample_token().encode(&SigningKey::EcP256(generate_key())).unwrap();
+
+        assert_eq!(Err(VerificationError::Signature(SignatureError::InvalidSignature)),
+                   verifier.verify_json(&to_general_json(&[&untrusted, &other_untrusted])));
+    }
+}
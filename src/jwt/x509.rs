@@ -0,0 +1,339 @@
+//! Verification of tokens signed with the key of an X.509 certificate.
+//!
+//! The certificate chain is taken from the `x5c` header of the token and must lead to
+//! one of the configured trust anchors. The validity periods of all certificates are
+//! checked against the current time. Chains referenced by `x5u` are not fetched.
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+use openssl::error::ErrorStack;
+use openssl::stack::Stack;
+use openssl::x509::{X509, X509StoreContext};
+use openssl::x509::store::{X509Store, X509StoreBuilder};
+use rustc_serialize::base64::FromBase64;
+use super::{JsonWebToken, RegisteredHeader, VerificationError};
+use super::signature::VerificationKey;
+
+/// The certificates a chain given by `x5c` must lead to
+pub struct TrustAnchors {
+    store: X509Store,
+}
+
+impl TrustAnchors {
+    /// Creates the trust anchors from PEM encoded certificates.
+    pub fn from_pem(pem: &[u8]) -> Result<TrustAnchors, X509Error> {
+        TrustAnchors::from_certificates(try!{X509::stack_from_pem(pem)})
+    }
+
+    /// Creates the trust anchors from files that each contain one or more PEM
+    /// encoded certificates.
+    pub fn from_pem_files<P: AsRef<Path>>(paths: &[P]) -> Result<TrustAnchors, X509Error> {
+        let mut certificates = Vec::new();
+        for path in paths {
+            let mut pem = Vec::new();
+            try!{File::open(path)
+                .and_then(|mut file| file.read_to_end(&mut pem))
+                .map_err(|err| {
+                    X509Error::InvalidTrustAnchor(format!("{}: {}", path.as_ref().display(), err))
+                })};
+            certificates.extend(try!{X509::stack_from_pem(&pem)});
+        }
+        TrustAnchors::from_certificates(certificates)
+    }
+
+    fn from_certificates(certificates: Vec<X509>) -> Result<TrustAnchors, X509Error> {
+        if certificates.is_empty() {
+            return Err(X509Error::InvalidTrustAnchor(String::from("No certificates given.")));
+        }
+        let mut builder = try!{X509StoreBuilder::new()};
+        for certificate in certificates {
+            try!{builder.add_cert(certificate)};
+        }
+        Ok(TrustAnchors { store: builder.build() })
+    }
+
+    /// Verifies a chain of DER encoded certificates, starting with the one of the
+    /// signing key, and returns the key of the first certificate.
+    pub fn verify_chain(&self, chain: &[Vec<u8>]) -> Result<VerificationKey, X509Error> {
+        let (leaf, intermediates) = match chain.split_first() {
+            Some(split) => split,
+            None => return Err(X509Error::InvalidCertificate(String::from("The chain is empty."))),
+        };
+        let leaf = try!{X509::from_der(leaf)};
+        let mut untrusted = try!{Stack::new()};
+        for intermediate in intermediates {
+            try!{untrusted.push(try!{X509::from_der(intermediate)})};
+        }
+        let mut context = try!{X509StoreContext::new()};
+        let result = try!{context.init(&self.store, &leaf, &untrusted, |context| {
+            context.verify_cert().map(|valid| if valid { Ok(()) } else { Err(context.error()) })
+        })};
+        try!{result.map_err(|err| X509Error::UntrustedCertificate(err.error_string().to_owned()))};
+        let public_key = try!{leaf.public_key()};
+        VerificationKey::from_public_key(&public_key)
+            .map_err(|err| X509Error::InvalidCertificate(err.to_string()))
+    }
+}
+
+impl JsonWebToken {
+    /// Parses a token and verifies its signature with the key of the first certificate
+    /// of its `x5c` header after verifying the chain against `trust_anchors`.
+    pub fn verify_with_x5c(token: &str,
+                           trust_anchors: &TrustAnchors)
+                           -> Result<JsonWebToken, VerificationError> {
+        let jwt_token = try!{JsonWebToken::from_str(token)};
+        let chain = try!{jwt_token.certificate_chain()};
+        let key = try!{trust_anchors.verify_chain(&chain).map_err(VerificationError::Certificate)};
+        try!{jwt_token.check_critical_headers(&[])};
+        try!{jwt_token.check_signature(token, &key)};
+        Ok(jwt_token)
+    }
+
+    /// The DER encoded certificates of the `x5c` header.
+    pub fn certificate_chain(&self) -> Result<Vec<Vec<u8>>, VerificationError> {
+        let encoded_chain = try!{self.get_registered_header(RegisteredHeader::X509CertificateChain)
+            .and_then(|json| json.as_array())
+            .ok_or_else(|| VerificationError::Malformed(String::from("Field 'x5c' is missing or not an array.")))};
+        let mut chain = Vec::new();
+        for encoded in encoded_chain {
+            let certificate = try!{encoded.as_string()
+                .ok_or_else(|| VerificationError::Malformed(String::from("Element in 'x5c' not a String.")))
+                .and_then(|x| x.from_base64()
+                    .map_err(|err| VerificationError::Malformed(format!("Field 'x5c': {}", err))))};
+            chain.push(certificate);
+        }
+        Ok(chain)
+    }
+}
+
+/// Errors that can occur when verifying a certificate chain.
+#[derive(Debug, Clone, PartialEq)]
+pub enum X509Error {
+    /// A trust anchor could not be read
+    InvalidTrustAnchor(String),
+    /// A certificate could not be parsed or its key is not supported
+    InvalidCertificate(String),
+    /// The chain does not lead to a trust anchor or a certificate is not valid now
+    UntrustedCertificate(String),
+}
+
+impl fmt::Display for X509Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            X509Error::InvalidTrustAnchor(ref message) => {
+                write!(f, "Invalid trust anchor: {}", message)
+            }
+            X509Error::InvalidCertificate(ref message) => {
+                write!(f, "Invalid certificate: {}", message)
+            }
+            X509Error::UntrustedCertificate(ref message) => {
+                write!(f, "Untrusted certificate: {}", message)
+            }
+        }
+    }
+}
+
+impl Error for X509Error {
+    fn description(&self) -> &str {
+        match *self {
+            X509Error::InvalidTrustAnchor(ref message) |
+            X509Error::InvalidCertificate(ref message) |
+            X509Error::UntrustedCertificate(ref message) => message.as_ref(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        None
+    }
+}
+
+impl From<ErrorStack> for X509Error {
+    fn from(err: ErrorStack) -> Self {
+        X509Error::InvalidCertificate(format!("{}", err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process;
+    use openssl::asn1::Asn1Time;
+    use openssl::ec::EcKey;
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::rand::rand_bytes;
+    use openssl::x509::{X509, X509Builder, X509NameBuilder};
+    use openssl::x509::extension::BasicConstraints;
+    use rustc_serialize::base64::{ToBase64, STANDARD, URL_SAFE};
+    use rustc_serialize::json::Json;
+    use jwt::{JsonWebToken, Header, RegisteredHeader, Claim, RegisteredClaim, VerificationError};
+    use jwt::signature::SigningKey;
+    use es256_fixture::generate_key;
+    use super::{TrustAnchors, X509Error};
+
+    /// A file in the temporary directory that is removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        /// The name is unique per process and call, so concurrent test runs do not collide.
+        fn create(contents: &[u8]) -> TempFile {
+            let mut suffix = [0u8; 8];
+            rand_bytes(&mut suffix).unwrap();
+            let path = env::temp_dir().join(format!("x509_trust_anchor_test_{}_{}.pem",
+                                                    process::id(),
+                                                    suffix.to_base64(URL_SAFE)));
+            File::create(&path).unwrap().write_all(contents).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn certificate(subject: &str,
+                   key: &EcKey<Private>,
+                   issuer: Option<(&X509, &EcKey<Private>)>,
+                   not_after: Asn1Time)
+                   -> X509 {
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, subject).unwrap();
+        let name = name.build();
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_pubkey(&PKey::from_ec_key(key.clone()).unwrap()).unwrap();
+        builder.set_not_before(&Asn1Time::from_unix(0).unwrap()).unwrap();
+        builder.set_not_after(&not_after).unwrap();
+        let signer = match issuer {
+            Some((issuer_certificate, issuer_key)) => {
+                builder.set_issuer_name(issuer_certificate.subject_name()).unwrap();
+                issuer_key
+            }
+            None => {
+                builder.set_issuer_name(&name).unwrap();
+                builder.append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                    .unwrap();
+                key
+            }
+        };
+        builder.sign(&PKey::from_ec_key(signer.clone()).unwrap(), MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    fn valid_for_a_day() -> Asn1Time {
+        Asn1Time::days_from_now(1).unwrap()
+    }
+
+    fn token_with_chain(key: &EcKey<Private>, chain: &[&X509]) -> String {
+        let x5c = chain.iter()
+            .map(|certificate| Json::String(certificate.to_der().unwrap().to_base64(STANDARD)))
+            .collect();
+        JsonWebToken::new()
+            .add_header(&Header::Registered(RegisteredHeader::X509CertificateChain),
+                        Json::Array(x5c))
+            .add_payload(&Claim::Registered(RegisteredClaim::Subject),
+                         Json::String(String::from("test2")))
+            .encode(&SigningKey::EcP256(key.clone()))
+            .unwrap()
+    }
+
+    #[test]
+    fn a_token_signed_with_a_trusted_certificate_must_be_verified() {
+        let ca_key = generate_key();
+        let ca = certificate("CA", &ca_key, None, valid_for_a_day());
+        let key = generate_key();
+        let leaf = certificate("issuer", &key, Some((&ca, &ca_key)), valid_for_a_day());
+        let trust_anchors = TrustAnchors::from_pem(&ca.to_pem().unwrap()).unwrap();
+
+        let result = JsonWebToken::verify_with_x5c(&token_with_chain(&key, &[&leaf]),
+                                                   &trust_anchors);
+
+        assert_eq!(Some(&Json::String(String::from("test2"))),
+                   result.unwrap().get_registered_payload(RegisteredClaim::Subject));
+    }
+
+    #[test]
+    fn trust_anchors_must_be_read_from_pem_files() {
+        let ca_key = generate_key();
+        let ca = certificate("CA", &ca_key, None, valid_for_a_day());
+        let key = generate_key();
+        let leaf = certificate("issuer", &key, Some((&ca, &ca_key)), valid_for_a_day());
+        let file = TempFile::create(&ca.to_pem().unwrap());
+
+        let trust_anchors = TrustAnchors::from_pem_files(&[&file.0]).unwrap();
+
+        assert!(JsonWebToken::verify_with_x5c(&token_with_chain(&key, &[&leaf, &ca]),
+                                              &trust_anchors)
+            .is_ok());
+        assert!(TrustAnchors::from_pem_files(&[env::temp_dir().join("missing.pem")]).is_err());
+    }
+
+    #[test]
+    fn a_chain_that_does_not_lead_to_a_trust_anchor_must_be_rejected() {
+        let ca_key = generate_key();
+        let ca = certificate("CA", &ca_key, None, valid_for_a_day());
+        let other_ca = certificate("Other CA", &generate_key(), None, valid_for_a_day());
+        let key = generate_key();
+        let leaf = certificate("issuer", &key, Some((&ca, &ca_key)), valid_for_a_day());
+        let trust_anchors = TrustAnchors::from_pem(&other_ca.to_pem().unwrap()).unwrap();
+
+        match JsonWebToken::verify_with_x5c(&token_with_chain(&key, &[&leaf, &ca]),
+                                            &trust_anchors) {
+            Err(VerificationError::Certificate(X509Error::UntrustedCertificate(_))) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn an_expired_certificate_must_be_rejected() {
+        let ca_key = generate_key();
+        let ca = certificate("CA", &ca_key, None, valid_for_a_day());
+        let key = generate_key();
+        let leaf = certificate("issuer",
+                               &key,
+                               Some((&ca, &ca_key)),
+                               Asn1Time::from_unix(86400).unwrap());
+        let trust_anchors = TrustAnchors::from_pem(&ca.to_pem().unwrap()).unwrap();
+
+        match JsonWebToken::verify_with_x5c(&token_with_chain(&key, &[&leaf]), &trust_anchors) {
+            Err(VerificationError::Certificate(X509Error::UntrustedCertificate(_))) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn a_token_signed_with_another_key_than_the_certificate_must_be_rejected() {
+        let ca_key = generate_key();
+        let ca = certificate("CA", &ca_key, None, valid_for_a_day());
+        let leaf = certificate("issuer", &generate_key(), Some((&ca, &ca_key)), valid_for_a_day());
+        let trust_anchors = TrustAnchors::from_pem(&ca.to_pem().unwrap()).unwrap();
+
+        let result = JsonWebToken::verify_with_x5c(&token_with_chain(&generate_key(), &[&leaf]),
+                                                   &trust_anchors);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn a_token_without_x5c_must_be_rejected() {
+        let ca_key = generate_key();
+        let ca = certificate("CA", &ca_key, None, valid_for_a_day());
+        let trust_anchors = TrustAnchors::from_pem(&ca.to_pem().unwrap()).unwrap();
+        let token = JsonWebToken::new().encode(&SigningKey::EcP256(ca_key)).unwrap();
+
+        match JsonWebToken::verify_with_x5c(&token, &trust_anchors) {
+            Err(VerificationError::Malformed(_)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
@@ -14,16 +14,25 @@ extern crate chrono;
 
 extern crate url;
 
+extern crate openssl;
+
 use std::convert::{Into, From};
 use std::error::Error;
 use std::fmt;
-use std::num::ParseFloatError;
+use std::num::{ParseFloatError, ParseIntError};
 
 use std::env::VarError;
 
 pub mod jwt;
 pub mod client;
 pub mod resource_server;
+pub mod paseto;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(test)]
+mod es256_fixture;
+#[cfg(all(test, feature = "hyper"))]
+mod fake_provider;
 
 /// This is a Scope used for authorization once the `AuthorizationServer` authenticated the user.
 #[derive(PartialEq, Eq, Hash, Debug, Clone)]
@@ -101,3 +110,9 @@ impl From<ParseFloatError> for InitializationError {
         InitializationError { message: format!{"{}", err} }
     }
 }
+
+impl From<ParseIntError> for InitializationError {
+    fn from(err: ParseIntError) -> Self {
+        InitializationError { message: format!{"{}", err} }
+    }
+}
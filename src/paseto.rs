@@ -0,0 +1,409 @@
+//! Handling of [PASETO](https://github.com/paseto-standard/paseto-spec) tokens.
+//!
+//! Only `v4.public` tokens are supported. They are signed with Ed25519 and, unlike a JWT,
+//! carry no algorithm header, so a token can not choose how it is verified.
+//!
+//! The registered time claims `exp`, `nbf` and `iat` are RFC 3339 date times.
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use chrono::{DateTime, NaiveDateTime};
+use openssl::error::ErrorStack;
+use openssl::pkey::{Id, PKey, Private, Public};
+use openssl::sign::{Signer, Verifier};
+use rustc_serialize::base64::{ToBase64, URL_SAFE};
+use rustc_serialize::json::Json;
+use jwt::{JsonWebToken, Claim, RegisteredClaim};
+use jwt::base64url;
+
+/// The header of every `v4.public` token
+pub const V4_PUBLIC_HEADER: &'static str = "v4.public.";
+
+const SIGNATURE_LENGTH: usize = 64;
+
+/// Whether the token looks like a `v4.public` token.
+pub fn is_v4_public(token: &str) -> bool {
+    token.starts_with(V4_PUBLIC_HEADER)
+}
+
+/// An Ed25519 public key to verify `v4.public` tokens
+#[derive(Clone)]
+pub struct PasetoPublicKey(PKey<Public>);
+
+impl PasetoPublicKey {
+    /// Creates a key from its 32 raw bytes.
+    pub fn from_raw_bytes(bytes: &[u8]) -> Result<PasetoPublicKey, PasetoError> {
+        Ok(PasetoPublicKey(try!{PKey::public_key_from_raw_bytes(bytes, Id::ED25519)}))
+    }
+
+    /// Creates a key from a PEM encoded public key.
+    pub fn from_pem(pem: &[u8]) -> Result<PasetoPublicKey, PasetoError> {
+        let key = try!{PKey::public_key_from_pem(pem)};
+        if key.id() != Id::ED25519 {
+            return Err(PasetoError::InvalidKey(String::from("Not an Ed25519 key.")));
+        }
+        Ok(PasetoPublicKey(key))
+    }
+}
+
+/// An Ed25519 private key to sign `v4.public` tokens
+pub struct PasetoSecretKey(PKey<Private>);
+
+impl PasetoSecretKey {
+    /// Generates a new key.
+    pub fn generate() -> Result<PasetoSecretKey, PasetoError> {
+        Ok(PasetoSecretKey(try!{PKey::generate_ed25519()}))
+    }
+
+    /// Creates a key from its 32 byte seed.
+    pub fn from_raw_bytes(seed: &[u8]) -> Result<PasetoSecretKey, PasetoError> {
+        Ok(PasetoSecretKey(try!{PKey::private_key_from_raw_bytes(seed, Id::ED25519)}))
+    }
+
+    /// The public key to verify the tokens signed with this key
+    pub fn public_key(&self) -> Result<PasetoPublicKey, PasetoError> {
+        PasetoPublicKey::from_raw_bytes(&try!{self.0.raw_public_key()})
+    }
+}
+
+/// The claims and the footer of a `v4.public` token
+#[derive(PartialEq, Debug, Clone)]
+pub struct PasetoToken {
+    /// The claims of the JSON payload
+    pub claims: HashMap<String, Json>,
+    /// The unencrypted but authenticated footer. Empty if the token has none.
+    pub footer: Vec<u8>,
+}
+
+impl PasetoToken {
+    /// Create a new instance without claims and footer
+    pub fn new() -> PasetoToken {
+        PasetoToken {
+            claims: HashMap::new(),
+            footer: Vec::new(),
+        }
+    }
+
+    /// Add a claim. Consumes the instance.
+    pub fn add_claim(self, for_claim: &Claim, value: Json) -> Self {
+        let mut x = self;
+        let key = match *for_claim {
+            Claim::Registered(ref claim) => claim.to_key(),
+            Claim::Custom(key) => key,
+        };
+        x.claims.insert(String::from(key), value);
+        x
+    }
+
+    /// Add a date time claim like `exp` formatted as RFC 3339. Consumes the instance.
+    pub fn add_date_claim(self, for_claim: &Claim, date_utc: NaiveDateTime) -> Self {
+        let formatted = date_utc.format("%Y-%m-%dT%H:%M:%S+00:00").to_string();
+        self.add_claim(for_claim, Json::String(formatted))
+    }
+
+    /// Builder method. Set the footer.
+    pub fn with_footer<T: Into<Vec<u8>>>(self, footer: T) -> Self {
+        let mut x = self;
+        x.footer = footer.into();
+        x
+    }
+
+    pub fn get_claim(&self, for_claim: &Claim) -> Option<&Json> {
+        match *for_claim {
+            Claim::Registered(ref claim) => self.claims.get(claim.to_key()),
+            Claim::Custom(key) => self.claims.get(key),
+        }
+    }
+
+    /// Parses a date time claim like `exp`.
+    pub fn get_date_claim(&self, for_claim: &Claim) -> Result<Option<NaiveDateTime>, PasetoError> {
+        match self.get_claim(for_claim) {
+            None => Ok(None),
+            Some(&Json::String(ref date)) => {
+                DateTime::parse_from_rfc3339(date)
+                    .map(|date| Some(date.naive_utc()))
+                    .map_err(|err| {
+                        PasetoError::Malformed(format!("Invalid date '{}': {}", date, err))
+                    })
+            }
+            Some(_) => Err(PasetoError::Malformed(String::from("A date claim is not a String."))),
+        }
+    }
+
+    /// Converts the claims to a `JsonWebToken` payload with the time claims as unix epochs,
+    /// e.g. to check them with a `Validation`.
+    pub fn to_json_web_token(&self) -> Result<JsonWebToken, PasetoError> {
+        let mut jwt_token = JsonWebToken::new();
+        jwt_token.payload = self.claims.clone();
+        for claim in &[RegisteredClaim::ExpirationTime,
+                       RegisteredClaim::NotBefore,
+                       RegisteredClaim::IssuedAt] {
+            let claim = Claim::Registered(claim.clone());
+            if let Some(date) = try!{self.get_date_claim(&claim)} {
+                jwt_token = jwt_token.add_payload(&claim, Json::I64(date.timestamp()));
+            }
+        }
+        Ok(jwt_token)
+    }
+
+    /// Parses a `v4.public` token and verifies its signature.
+    pub fn verify_with(token: &str, key: &PasetoPublicKey) -> Result<PasetoToken, PasetoError> {
+        PasetoToken::verify_with_implicit_assertion(token, key, b"")
+    }
+
+    /// Parses a `v4.public` token and verifies its signature over the token
+    /// and the implicit assertion that is not part of the token.
+    pub fn verify_with_implicit_assertion(token: &str,
+                                          key: &PasetoPublicKey,
+                                          implicit_assertion: &[u8])
+                                          -> Result<PasetoToken, PasetoError> {
+        if !is_v4_public(token) {
+            let prefix: Vec<&str> = token.splitn(3, '.').take(2).collect();
+            return Err(PasetoError::UnsupportedVersion(prefix.join(".")));
+        }
+        let mut segments = token[V4_PUBLIC_HEADER.len()..].split('.');
+        let (signed, footer) = match (segments.next(), segments.next(), segments.next()) {
+            (Some(signed), footer, None) => {
+                let footer = match footer {
+                    Some(footer) => try!{decode(footer, "footer")},
+                    None => Vec::new(),
+                };
+                (try!{decode(signed, "payload")}, footer)
+            }
+            _ => return Err(PasetoError::Malformed(String::from("Too many segments."))),
+        };
+        if signed.len() <= SIGNATURE_LENGTH {
+            return Err(PasetoError::Malformed(String::from("The payload is too short.")));
+        }
+        let (message, signature) = signed.split_at(signed.len() - SIGNATURE_LENGTH);
+
+        let pre_auth = pre_auth_encode(&[V4_PUBLIC_HEADER.as_bytes(),
+                                         message,
+                                         &footer,
+                                         implicit_assertion]);
+        let mut verifier = try!{Verifier::new_without_digest(&key.0)};
+        if !try!{verifier.verify_oneshot(signature, &pre_auth)} {
+            return Err(PasetoError::InvalidSignature);
+        }
+
+        let message = try!{String::from_utf8(message.to_vec()).map_err(|err| {
+            PasetoError::Malformed(format!("The claims are not UTF-8: {}", err))
+        })};
+        let claims = match Json::from_str(&message) {
+            Ok(Json::Object(claims)) => claims.into_iter().collect(),
+            Ok(_) => {
+                return Err(PasetoError::Malformed(String::from("The claims are not an object.")))
+            }
+            Err(err) => return Err(PasetoError::Malformed(format!("Invalid claims: {}", err))),
+        };
+        Ok(PasetoToken {
+            claims: claims,
+            footer: footer,
+        })
+    }
+
+    /// Creates a `v4.public` token signed with `key`.
+    pub fn sign(&self, key: &PasetoSecretKey) -> Result<String, PasetoError> {
+        self.sign_with_implicit_assertion(key, b"")
+    }
+
+    /// Creates a `v4.public` token whose signature also covers the implicit assertion.
+    pub fn sign_with_implicit_assertion(&self,
+                                        key: &PasetoSecretKey,
+                                        implicit_assertion: &[u8])
+                                        -> Result<String, PasetoError> {
+        let claims: BTreeMap<String, Json> = self.claims.clone().into_iter().collect();
+        let message = Json::Object(claims).to_string().into_bytes();
+        let pre_auth = pre_auth_encode(&[V4_PUBLIC_HEADER.as_bytes(),
+                                         &message,
+                                         &self.footer,
+                                         implicit_assertion]);
+        let mut signer = try!{Signer::new_without_digest(&key.0)};
+        let signature = try!{signer.sign_oneshot_to_vec(&pre_auth)};
+        let mut signed = message;
+        signed.extend(signature);
+        let mut token = format!("{}{}", V4_PUBLIC_HEADER, signed.to_base64(URL_SAFE));
+        if !self.footer.is_empty() {
+            token.push('.');
+            token.push_str(&self.footer.to_base64(URL_SAFE));
+        }
+        Ok(token)
+    }
+}
+
+fn decode(segment: &str, name: &str) -> Result<Vec<u8>, PasetoError> {
+    base64url::decode(segment)
+        .map_err(|err| PasetoError::Malformed(format!("Invalid {}: {}", name, err)))
+}
+
+/// The Pre-Authentication Encoding (PAE) of the specification
+fn pre_auth_encode(pieces: &[&[u8]]) -> Vec<u8> {
+    let mut encoded = le64(pieces.len());
+    for piece in pieces {
+        encoded.extend(le64(piece.len()));
+        encoded.extend_from_slice(piece);
+    }
+    encoded
+}
+
+fn le64(n: usize) -> Vec<u8> {
+    let n = (n as u64) & 0x7fff_ffff_ffff_ffff;
+    (0..8).map(|i| (n >> (8 * i)) as u8).collect()
+}
+
+/// Errors that can occur when verifying or signing a PASETO token.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PasetoError {
+    /// The version or purpose is not `v4.public`
+    UnsupportedVersion(String),
+    /// The token could not be parsed
+    Malformed(String),
+    /// The signature does not match the token
+    InvalidSignature,
+    /// The key could not be used
+    InvalidKey(String),
+    /// The crypto library failed
+    CryptoError(String),
+}
+
+impl fmt::Display for PasetoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PasetoError::UnsupportedVersion(ref version) => {
+                write!(f, "Unsupported version and purpose: {}", version)
+            }
+            PasetoError::Malformed(ref message) => write!(f, "Malformed token: {}", message),
+            PasetoError::InvalidSignature => write!(f, "Invalid signature"),
+            PasetoError::InvalidKey(ref message) => write!(f, "Invalid key: {}", message),
+            PasetoError::CryptoError(ref message) => write!(f, "Crypto error: {}", message),
+        }
+    }
+}
+
+impl Error for PasetoError {
+    fn description(&self) -> &str {
+        match *self {
+            PasetoError::UnsupportedVersion(_) => "Unsupported version and purpose",
+            PasetoError::Malformed(ref message) |
+            PasetoError::InvalidKey(ref message) |
+            PasetoError::CryptoError(ref message) => message.as_ref(),
+            PasetoError::InvalidSignature => "Invalid signature",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        None
+    }
+}
+
+impl From<ErrorStack> for PasetoError {
+    fn from(err: ErrorStack) -> Self {
+        PasetoError::CryptoError(format!("{}", err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{NaiveDate, NaiveTime, NaiveDateTime};
+    use openssl::sign::Signer;
+    use rustc_serialize::base64::{ToBase64, URL_SAFE};
+    use rustc_serialize::hex::FromHex;
+    use rustc_serialize::json::Json;
+    use jwt::{Claim, RegisteredClaim};
+    use super::{PasetoToken, PasetoPublicKey, PasetoSecretKey, PasetoError, pre_auth_encode,
+                V4_PUBLIC_HEADER};
+
+    // Test vector 4-S-1 of the PASETO specification
+    const PUBLIC_KEY: &'static str = "1eb9dbbbbc047c03fd70604e0071f0987e16b28b757225c11f00415d0e\
+                                      20b1a2";
+    const SECRET_SEED: &'static str = "b4cbfb43df4ce210727d953e4a713307fa19bb7d9f85041438d9e11b94\
+                                       2a3774";
+    const TOKEN: &'static str = "v4.public.eyJkYXRhIjoidGhpcyBpcyBhIHNpZ25lZCBtZXNzYWdlIiwiZXhwIj\
+                                 oiMjAyMi0wMS0wMVQwMDowMDowMCswMDowMCJ9bg_XBBzds8lTZShVlwwKSgeKpL\
+                                 T3yukTw6JUz3W4h_ExsQV-P0V54zemZDcAxFaSeef1QlXEFtkqxT1ciiQEDA";
+
+    fn public_key() -> PasetoPublicKey {
+        PasetoPublicKey::from_raw_bytes(&PUBLIC_KEY.from_hex().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn pre_auth_encoding_must_match_the_specification() {
+        assert_eq!(vec![0; 8], pre_auth_encode(&[]));
+        assert_eq!(vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                   pre_auth_encode(&[b""]));
+        assert_eq!(b"\x01\x00\x00\x00\x00\x00\x00\x00\x04\x00\x00\x00\x00\x00\x00\x00test".to_vec(),
+                   pre_auth_encode(&[b"test"]));
+    }
+
+    #[test]
+    fn the_test_vector_must_be_verified() {
+        let token = PasetoToken::verify_with(TOKEN, &public_key()).unwrap();
+
+        assert_eq!(Some(&Json::String(String::from("this is a signed message"))),
+                   token.get_claim(&Claim::Custom("data")));
+        assert_eq!(Ok(Some(NaiveDateTime::new(NaiveDate::from_ymd(2022, 1, 1),
+                                              NaiveTime::from_hms(0, 0, 0)))),
+                   token.get_date_claim(&Claim::Registered(RegisteredClaim::ExpirationTime)));
+        assert!(token.footer.is_empty());
+    }
+
+    #[test]
+    fn a_tampered_token_must_be_rejected() {
+        let tampered = TOKEN.replace("eyJkYXRh", "eyJkYXRi");
+
+        assert_eq!(Err(PasetoError::InvalidSignature),
+                   PasetoToken::verify_with(&tampered, &public_key()));
+        assert_eq!(Err(PasetoError::InvalidSignature),
+                   PasetoToken::verify_with(&format!("{}.e30", TOKEN), &public_key()));
+    }
+
+    #[test]
+    fn other_versions_and_purposes_must_be_rejected() {
+        let local = TOKEN.replace("v4.public.", "v4.local.");
+        let v2 = TOKEN.replace("v4.public.", "v2.public.");
+
+        assert_eq!(Err(PasetoError::UnsupportedVersion(String::from("v4.local"))),
+                   PasetoToken::verify_with(&local, &public_key()));
+        assert_eq!(Err(PasetoError::UnsupportedVersion(String::from("v2.public"))),
+                   PasetoToken::verify_with(&v2, &public_key()));
+    }
+
+    #[test]
+    fn a_signed_token_must_round_trip_with_footer_and_implicit_assertion() {
+        let key = PasetoSecretKey::from_raw_bytes(&SECRET_SEED.from_hex().unwrap()).unwrap();
+        let token = PasetoToken::new()
+            .add_claim(&Claim::Registered(RegisteredClaim::Subject),
+                       Json::String(String::from("test2")))
+            .add_date_claim(&Claim::Registered(RegisteredClaim::ExpirationTime),
+                            NaiveDateTime::new(NaiveDate::from_ymd(2016, 3, 7),
+                                               NaiveTime::from_hms(3, 3, 34)))
+            .with_footer("{\"kid\":\"testkey\"}");
+
+        let signed = token.sign_with_implicit_assertion(&key, b"my-service").unwrap();
+
+        assert_eq!(Ok(token.clone()),
+                   PasetoToken::verify_with_implicit_assertion(&signed,
+                                                               &key.public_key().unwrap(),
+                                                               b"my-service"));
+        assert_eq!(Err(PasetoError::InvalidSignature),
+                   PasetoToken::verify_with(&signed, &public_key()));
+        assert_eq!(Json::I64(1457319814),
+                   token.to_json_web_token().unwrap().payload["exp"]);
+    }
+
+    #[test]
+    fn signed_claims_that_are_not_utf8_must_be_rejected() {
+        let key = PasetoSecretKey::from_raw_bytes(&SECRET_SEED.from_hex().unwrap()).unwrap();
+        let message = b"{\"sub\":\"\xff\"}".to_vec();
+        let pre_auth = pre_auth_encode(&[V4_PUBLIC_HEADER.as_bytes(), &message, b"", b""]);
+        let mut signer = Signer::new_without_digest(&key.0).unwrap();
+        let mut signed = message;
+        signed.extend(signer.sign_oneshot_to_vec(&pre_auth).unwrap());
+        let token = format!("{}{}", V4_PUBLIC_HEADER, signed.to_base64(URL_SAFE));
+
+        match PasetoToken::verify_with(&token, &key.public_key().unwrap()) {
+            Err(PasetoError::Malformed(_)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
@@ -20,6 +20,8 @@ pub struct AuthorizationHyperServer {
     pub fallback_token_info_url: Option<String>,
     /// The query parameter that shall contain the Token.
     pub query_parameter: String,
+    /// The accepted audiences. Any token is accepted if empty.
+    pub audiences: Vec<String>,
 }
 
 impl AuthorizationHyperServer {
@@ -56,6 +58,7 @@ impl AuthorizationHyperServer {
             token_info_url: token_info_url,
             fallback_token_info_url: None,
             query_parameter: query_parameter,
+            audiences: Vec::new(),
         })
     }
 
@@ -119,6 +122,13 @@ impl AuthorizationHyperServer {
         Ok(x)
     }
 
+    /// Builder method. Only accept tokens whose `aud` contains `audience`.
+    pub fn with_audience<T: Into<String>>(self, audience: T) -> Self {
+        let mut x = self;
+        x.audiences.push(audience.into());
+        x
+    }
+
     fn request_token_info(&self, token: &Token) -> Result<Response, AuthorizationServerError> {
         self.request_token_info_from_url_with_fallback(&self.create_url(token),
                                                        &self.create_fallback_url(token),
@@ -205,6 +215,11 @@ impl AuthorizationServer for AuthorizationHyperServer {
                 let mut buf = String::new();
                 let _ = try!{response.read_to_string(&mut buf)};
                 let user = try!{AuthenticatedUser::from_json(buf.as_ref())};
+                if !self.audiences.is_empty() && !user.has_any_audience(&self.audiences) {
+                    return Err(AuthorizationServerError::NotAuthenticated {
+                        message: format!("The token was issued for {:?}.", user.audiences),
+                    });
+                }
                 Ok(user)
             }
             StatusCode::BadRequest => {
@@ -228,3 +243,42 @@ impl From<HError> for AuthorizationServerError {
         AuthorizationServerError::Connection { message: err.description().to_owned() }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use hyper::Client;
+    use {Token, Scope};
+    use fake_provider::fake_provider;
+    use resource_server::{AuthorizationServer, AuthorizationServerError};
+    use super::AuthorizationHyperServer;
+
+    const TOKEN_INFO: &'static str = "{\"uid\":\"test2\",\"scope\":[\"uid\"],\
+                                      \"aud\":[\"https://orders.example.org/\"]}";
+
+    fn server(url: &str) -> AuthorizationHyperServer {
+        AuthorizationHyperServer::new(Client::new(), format!("{}/tokeninfo", url), "access_token")
+            .unwrap()
+    }
+
+    #[test]
+    fn a_token_for_an_accepted_audience_must_be_authenticated() {
+        let (url, _) = fake_provider(vec![(200, TOKEN_INFO)]);
+        let server = server(&url).with_audience("https://orders.example.org/");
+
+        let user = server.authenticate(&Token::new("token")).unwrap();
+
+        assert!(user.has_scope(&Scope::new("uid")));
+        assert_eq!(vec![String::from("https://orders.example.org/")], user.audiences);
+    }
+
+    #[test]
+    fn a_token_for_another_audience_must_be_rejected() {
+        let (url, _) = fake_provider(vec![(200, TOKEN_INFO)]);
+        let server = server(&url).with_audience("https://payments.example.org/");
+
+        match server.authenticate(&Token::new("token")) {
+            Err(AuthorizationServerError::NotAuthenticated { .. }) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
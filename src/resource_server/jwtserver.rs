@@ -0,0 +1,266 @@
+//! An `AuthorizationServer` that validates Plan B tokens locally.
+//!
+//! The signature of a token is verified with a key from a `KeyStore`, so no request
+//! to a token info endpoint is needed.
+use std::str::FromStr;
+use Token;
+use jwt::{JsonWebToken, RegisteredClaim, VerificationError};
+use jwt::planb::PlanbToken;
+use jwt::key_store::{KeyStore, KeyStoreError};
+use jwt::signature::SignatureError;
+use jwt::validation::{Validation, parse_audiences};
+use super::{AuthorizationServer, AuthenticatedUser, AuthorizationServerError, Uid, Scope};
+
+/// An `AuthorizationServer` that verifies the signature and the claims of Plan B JWTs itself.
+pub struct JwtAuthorizationServer<K: KeyStore> {
+    /// Provides the keys to verify the signatures
+    pub key_store: K,
+    /// The checks for the registered claims like `exp` and `iat`
+    pub validation: Validation,
+}
+
+impl<K: KeyStore> JwtAuthorizationServer<K> {
+    /// Create a new instance with the default `Validation`
+    pub fn new(key_store: K) -> JwtAuthorizationServer<K> {
+        JwtAuthorizationServer {
+            key_store: key_store,
+            validation: Validation::new(),
+        }
+    }
+
+    /// Builder method. Set the checks for the registered claims.
+    pub fn with_validation(self, validation: Validation) -> Self {
+        let mut x = self;
+        x.validation = validation;
+        x
+    }
+
+    /// Builder method. Only accept tokens whose `aud` contains `audience`.
+    ///
+    /// Shorthand for adding the audience to the `Validation`.
+    pub fn with_audience<T: Into<String>>(self, audience: T) -> Self {
+        let mut x = self;
+        x.validation = x.validation.with_audience(audience);
+        x
+    }
+
+    fn verify_token(&self, token: &Token) -> Result<JsonWebToken, AuthorizationServerError> {
+        let jwt_token = try!{JsonWebToken::from_str(&token.0).map_err(|err| {
+            AuthorizationServerError::NotAuthenticated { message: format!("Not a JWT: {}", err) }
+        })};
+        {
+            let key_id = try!{jwt_token.key_id().map_err(|err| {
+                AuthorizationServerError::NotAuthenticated { message: format!("{}", err) }
+            })};
+            let jwk = try!{self.key_store.get_key(key_id).map_err(|err| match err {
+                KeyStoreError::UnknownKey(_) => {
+                    AuthorizationServerError::NotAuthenticated { message: format!("{}", err) }
+                }
+                KeyStoreError::FetchError(_) => {
+                    AuthorizationServerError::Connection { message: format!("{}", err) }
+                }
+                KeyStoreError::InternalError(_) => {
+                    AuthorizationServerError::Unknown { message: format!("{}", err) }
+                }
+            })};
+            try!{jwt_token.check_with_jwk(&token.0, &jwk).map_err(|err| match err {
+                VerificationError::Signature(SignatureError::InvalidKey(_)) => {
+                    AuthorizationServerError::Unknown {
+                        message: format!("Key '{}' is not usable: {}", key_id, err),
+                    }
+                }
+                err => AuthorizationServerError::NotAuthenticated { message: format!("{}", err) },
+            })};
+            debug!("Verified a token signed with key '{}' (thumbprint {}).",
+                   key_id,
+                   jwk.thumbprint());
+        }
+        Ok(jwt_token)
+    }
+}
+
+impl<K: KeyStore> AuthorizationServer for JwtAuthorizationServer<K> {
+    fn authenticate(&self, token: &Token) -> Result<AuthenticatedUser, AuthorizationServerError> {
+        let jwt_token = try!{self.verify_token(token)};
+        try!{self.validation.validate(&jwt_token).map_err(|err| {
+            AuthorizationServerError::NotAuthenticated { message: format!("{}", err) }
+        })};
+        let planb_token = try!{PlanbToken::from_jwt_token(&jwt_token).map_err(|err| {
+            AuthorizationServerError::NotAuthenticated {
+                message: format!("Not a Plan B token: {}", err),
+            }
+        })};
+        let aud = jwt_token.get_registered_payload(RegisteredClaim::Audience);
+        let audiences = try!{aud.map_or(Some(Vec::new()), parse_audiences)
+            .ok_or_else(|| AuthorizationServerError::NotAuthenticated {
+                message: String::from("Field 'aud' is neither a String nor an array of Strings."),
+            })};
+        let payload = planb_token.payload;
+        Ok(AuthenticatedUser {
+            uid: Some(Uid(payload.subject)),
+            scopes: payload.scopes.into_iter().map(Scope).collect(),
+            audiences: audiences,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use chrono::UTC;
+    use openssl::ec::EcKey;
+    use openssl::pkey::Private;
+    use rustc_serialize::json::Json;
+    use {Token, Scope};
+    use jwt::{JsonWebToken, Header, RegisteredHeader, Claim, RegisteredClaim};
+    use jwt::signature::SigningKey;
+    use jwt::jwk::JwkSet;
+    use jwt::validation::Validation;
+    use resource_server::{AuthorizationServer, AuthenticatedUser, AuthorizationServerError, Uid};
+    use testing::PlanbTokenBuilder;
+    use es256_fixture::{generate_key, jwk, key_set};
+    use super::JwtAuthorizationServer;
+
+    fn sign_token(key: &EcKey<Private>, issued_at: i64, expires_at: i64) -> Token {
+        let token = JsonWebToken::new()
+            .add_header(&Header::Registered(RegisteredHeader::KeyId),
+                        Json::String(String::from("testkey-es256")))
+            .add_payload(&Claim::Registered(RegisteredClaim::Subject),
+                         Json::String(String::from("test2")))
+            .add_payload(&Claim::Custom("scope"),
+                         Json::Array(vec![Json::String(String::from("uid")),
+                                          Json::String(String::from("cn"))]))
+            .add_payload(&Claim::Registered(RegisteredClaim::Issuer),
+                         Json::String(String::from("B")))
+            .add_payload(&Claim::Custom("realm"),
+                         Json::String(String::from("/services")))
+            .add_payload(&Claim::Registered(RegisteredClaim::ExpirationTime),
+                         Json::I64(expires_at))
+            .add_payload(&Claim::Registered(RegisteredClaim::IssuedAt),
+                         Json::I64(issued_at));
+        Token(token.encode(&SigningKey::EcP256(key.clone())).unwrap())
+    }
+
+    fn not_authenticated<T>(result: Result<T, AuthorizationServerError>) -> bool {
+        match result {
+            Err(AuthorizationServerError::NotAuthenticated { .. }) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn a_valid_token_must_authenticate_the_user() {
+        let key = generate_key();
+        let now = UTC::now().timestamp();
+        let token = sign_token(&key, now - 60, now + 3600);
+        let server = JwtAuthorizationServer::new(key_set(&key, "testkey-es256"));
+
+        let mut scopes = HashSet::new();
+        scopes.insert(Scope::new("uid"));
+        scopes.insert(Scope::new("cn"));
+        let expected = AuthenticatedUser {
+            uid: Some(Uid::new("test2")),
+            scopes: scopes,
+            audiences: Vec::new(),
+        };
+
+        assert_eq!(expected, server.authenticate(&token).unwrap());
+    }
+
+    #[test]
+    fn an_expired_token_must_be_rejected() {
+        let key = generate_key();
+        let now = UTC::now().timestamp();
+        let token = sign_token(&key, now - 3600, now - 60);
+        let server = JwtAuthorizationServer::new(key_set(&key, "testkey-es256"));
+
+        assert!(not_authenticated(server.authenticate(&token)));
+    }
+
+    #[test]
+    fn an_expired_token_must_be_accepted_within_the_leeway() {
+        let key = generate_key();
+        let now = UTC::now().timestamp();
+        let token = sign_token(&key, now - 3600, now - 60);
+        let server = JwtAuthorizationServer::new(key_set(&key, "testkey-es256"))
+            .with_validation(Validation::new().with_leeway_seconds(120));
+
+        assert!(server.authenticate(&token).is_ok());
+    }
+
+    #[test]
+    fn a_token_from_an_unexpected_issuer_must_be_rejected() {
+        let key = generate_key();
+        let now = UTC::now().timestamp();
+        let token = sign_token(&key, now - 60, now + 3600);
+        let server = JwtAuthorizationServer::new(key_set(&key, "testkey-es256"))
+            .with_validation(Validation::new().with_issuer("A"));
+
+        assert!(not_authenticated(server.authenticate(&token)));
+    }
+
+    #[test]
+    fn a_token_issued_in_the_future_must_be_rejected() {
+        let key = generate_key();
+        let now = UTC::now().timestamp();
+        let token = sign_token(&key, now + 600, now + 3600);
+        let server = JwtAuthorizationServer::new(key_set(&key, "testkey-es256"));
+
+        assert!(not_authenticated(server.authenticate(&token)));
+    }
+
+    #[test]
+    fn a_token_signed_with_an_unknown_key_must_be_rejected() {
+        let key = generate_key();
+        let now = UTC::now().timestamp();
+        let token = sign_token(&generate_key(), now - 60, now + 3600);
+        let server = JwtAuthorizationServer::new(key_set(&key, "testkey-es256"));
+
+        assert!(not_authenticated(server.authenticate(&token)));
+    }
+
+    #[test]
+    fn a_token_that_is_no_jwt_must_be_rejected() {
+        let server = JwtAuthorizationServer::new(key_set(&generate_key(), "testkey-es256"));
+
+        assert!(not_authenticated(server.authenticate(&Token::new("opaque-token"))));
+    }
+
+    #[test]
+    fn an_unknown_key_id_must_be_rejected() {
+        let key = generate_key();
+        let now = UTC::now().timestamp();
+        let token = sign_token(&key, now - 60, now + 3600);
+        let server = JwtAuthorizationServer::new(JwkSet::new(vec![]));
+
+        assert!(not_authenticated(server.authenticate(&token)));
+    }
+
+    #[test]
+    fn a_token_must_use_the_algorithm_of_the_key() {
+        let key = generate_key();
+        let now = UTC::now().timestamp();
+        let token = sign_token(&key, now - 60, now + 3600);
+        let mut rs256_jwk = jwk(&key, "testkey-es256");
+        rs256_jwk.algorithm = Some(String::from("RS256"));
+        let server = JwtAuthorizationServer::new(JwkSet::new(vec![rs256_jwk]));
+
+        assert!(not_authenticated(server.authenticate(&token)));
+    }
+
+    #[test]
+    fn a_token_for_another_audience_must_be_rejected() {
+        let minted = PlanbTokenBuilder::new()
+            .with_audience("other-service")
+            .with_audience("my-service")
+            .build()
+            .unwrap();
+        let accepting = JwtAuthorizationServer::new(minted.key_set()).with_audience("my-service");
+        let rejecting = JwtAuthorizationServer::new(minted.key_set())
+            .with_audience("third-service");
+
+        assert_eq!(vec![String::from("other-service"), String::from("my-service")],
+                   accepting.authenticate(&minted.token).unwrap().audiences);
+        assert!(not_authenticated(rejecting.authenticate(&minted.token)));
+    }
+}
@@ -7,6 +7,9 @@
 //! that this Token is sent to an Authentication Server that will
 //! authenticate the user and provide you with the assigned Scopes which
 //! you can then use for authorization.
+//!
+//! If the tokens are signed JWTs the `JwtAuthorizationServer` can validate them
+//! locally instead, so that no request to an Authentication Server is needed.
 use std::error::Error;
 use std::fmt;
 use std::collections::HashSet;
@@ -14,17 +17,26 @@ use std::convert::{Into, From};
 use std::io;
 use super::{Scope, Token};
 use rustc_serialize::{Decoder, Decodable, json};
+use rustc_serialize::json::Json;
+use jwt::validation::parse_audiences;
 
 #[cfg(feature = "hyper")]
 mod hyperserver;
 
+mod jwtserver;
+mod pasetoserver;
+
+pub use resource_server::jwtserver::JwtAuthorizationServer;
+pub use resource_server::pasetoserver::{PasetoAuthorizationServer, JwtOrPasetoAuthorizationServer};
+
 #[cfg(feature = "hyper")]
 pub use resource_server::hyperserver::AuthorizationHyperServer;
 
 #[cfg(feature = "iron")]
 pub mod ironmiddleware;
 
-/// Authenticates a user by using a Token. In this proxies an external server.
+/// Authenticates a user by using a Token. In this proxies an external server
+/// or validates the token itself.
 pub trait AuthorizationServer {
     /// Authenticate a user by Token.
     fn authenticate(&self, token: &Token) -> Result<AuthenticatedUser, AuthorizationServerError>;
@@ -51,6 +63,9 @@ impl fmt::Display for Uid {
 pub struct AuthenticatedUser {
     pub uid: Option<Uid>,
     pub scopes: HashSet<Scope>,
+    /// The `aud` of the token, i.e. the services the token was issued for.
+    /// Empty if the token is not restricted.
+    pub audiences: Vec<String>,
 }
 
 impl AuthenticatedUser {
@@ -65,18 +80,27 @@ impl AuthenticatedUser {
         AuthenticatedUser {
             uid: Some(Uid::new(uid)),
             scopes: hs,
+            audiences: Vec::new(),
         }
     }
 
     /// Parse the given JSON and create a new AuthenticatedUser
+    ///
+    /// The audiences are taken from the optional `aud` field.
     pub fn from_json(json_response: &str) -> Result<AuthenticatedUser, AuthorizationServerError> {
-        match json::decode::<AuthenticatedUser>(json_response) {
-            Ok(authenticated_user) => Ok(authenticated_user),
-            Err(err) => {
-                Err(AuthorizationServerError::TokenInfoUnparsable {
-                    message: err.description().to_owned(),
-                })
+        let unparsable = |message: &str| {
+            AuthorizationServerError::TokenInfoUnparsable { message: message.to_owned() }
+        };
+        let json = try!{Json::from_str(json_response).map_err(|err| unparsable(err.description()))};
+        let audiences = try!{json.find("aud")
+            .map_or(Some(Vec::new()), parse_audiences)
+            .ok_or_else(|| unparsable("Field 'aud' is neither a String nor an array of Strings."))};
+        let mut decoder = json::Decoder::new(json);
+        match AuthenticatedUser::decode(&mut decoder) {
+            Ok(authenticated_user) => {
+                Ok(AuthenticatedUser { audiences: audiences, ..authenticated_user })
             }
+            Err(err) => Err(unparsable(err.description())),
         }
     }
 
@@ -90,6 +114,11 @@ impl AuthenticatedUser {
         scopes.iter().all(|scope| self.has_scope(scope))
     }
 
+    /// Checks whether the token was issued for one of the given audiences.
+    pub fn has_any_audience(&self, audiences: &[String]) -> bool {
+        self.audiences.iter().any(|audience| audiences.contains(audience))
+    }
+
     /// Authorize the user for an action defined by the given scope. If the user does not have the scope this method will fail.
     pub fn authorize(&self, scope: &Scope) -> Result<(), NotAuthorized> {
         if self.has_scope(scope) {
@@ -125,6 +154,7 @@ impl Decodable for AuthenticatedUser {
             Ok(AuthenticatedUser {
                 uid: Some(Uid(uid)),
                 scopes: scopes,
+                audiences: Vec::new(),
             })
         })
     }
@@ -243,6 +273,7 @@ mod test {
         let expected = AuthenticatedUser {
             uid: Some(Uid::new("my_app")),
             scopes: scopes,
+            audiences: Vec::new(),
         };
 
         let parsed = json::decode(test_info).unwrap();
@@ -262,6 +293,7 @@ mod test {
         let expected = AuthenticatedUser {
             uid: Some(Uid::new("my_app")),
             scopes: scopes,
+            audiences: Vec::new(),
         };
 
         let parsed = json::decode(test_info).unwrap();
@@ -279,10 +311,25 @@ mod test {
         let expected = AuthenticatedUser {
             uid: Some(Uid::new("my_app")),
             scopes: HashSet::new(),
+            audiences: Vec::new(),
         };
 
         let parsed = json::decode(test_info).unwrap();
 
         assert_eq!(expected, parsed);
     }
+
+    #[test]
+    fn the_audiences_should_be_parsed_from_the_token_info() {
+        let single = "{\"uid\":\"my_app\",\"scope\":[\"uid\"],\"aud\":\"my-service\"}";
+        let many = "{\"uid\":\"my_app\",\"scope\":[\"uid\"],\"aud\":[\"a\",\"b\"]}";
+        let invalid = "{\"uid\":\"my_app\",\"scope\":[\"uid\"],\"aud\":1}";
+
+        assert_eq!(vec![String::from("my-service")],
+                   AuthenticatedUser::from_json(single).unwrap().audiences);
+        assert_eq!(vec![String::from("a"), String::from("b")],
+                   AuthenticatedUser::from_json(many).unwrap().audiences);
+        assert!(AuthenticatedUser::from_json(invalid).is_err());
+        assert!(AuthenticatedUser::from_json(many).unwrap().has_any_audience(&[String::from("b")]));
+    }
 }
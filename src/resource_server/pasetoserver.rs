@@ -0,0 +1,201 @@
+//! An `AuthorizationServer` that validates PASETO `v4.public` tokens locally.
+use Token;
+use jwt::{Claim, RegisteredClaim};
+use jwt::validation::{Validation, parse_audiences};
+use paseto::{self, PasetoToken, PasetoPublicKey, PasetoError};
+use rustc_serialize::json::Json;
+use super::{AuthorizationServer, AuthenticatedUser, AuthorizationServerError, Uid, Scope};
+
+/// An `AuthorizationServer` that verifies the signature and the claims of `v4.public` tokens.
+///
+/// The `sub` claim becomes the `Uid` and the `scope` claim, an array or a space separated
+/// String, becomes the scopes.
+pub struct PasetoAuthorizationServer {
+    /// The keys to verify the signatures. A token is accepted if any of them matches.
+    pub keys: Vec<PasetoPublicKey>,
+    /// The checks for the registered claims like `exp` and `iat`
+    pub validation: Validation,
+}
+
+impl PasetoAuthorizationServer {
+    /// Create a new instance with the default `Validation`
+    pub fn new(keys: Vec<PasetoPublicKey>) -> PasetoAuthorizationServer {
+        PasetoAuthorizationServer {
+            keys: keys,
+            validation: Validation::new(),
+        }
+    }
+
+    /// Builder method. Set the checks for the registered claims.
+    pub fn with_validation(self, validation: Validation) -> Self {
+        let mut x = self;
+        x.validation = validation;
+        x
+    }
+
+    /// Builder method. Only accept tokens whose `aud` contains `audience`.
+    ///
+    /// Shorthand for adding the audience to the `Validation`.
+    pub fn with_audience<T: Into<String>>(self, audience: T) -> Self {
+        let mut x = self;
+        x.validation = x.validation.with_audience(audience);
+        x
+    }
+
+    fn verify_token(&self, token: &Token) -> Result<PasetoToken, AuthorizationServerError> {
+        let mut last_error = PasetoError::InvalidKey(String::from("No keys configured."));
+        for key in &self.keys {
+            match PasetoToken::verify_with(&token.0, key) {
+                Ok(paseto_token) => return Ok(paseto_token),
+                Err(PasetoError::InvalidSignature) => last_error = PasetoError::InvalidSignature,
+                Err(err) => {
+                    return Err(AuthorizationServerError::NotAuthenticated {
+                        message: format!("{}", err),
+                    })
+                }
+            }
+        }
+        Err(AuthorizationServerError::NotAuthenticated { message: format!("{}", last_error) })
+    }
+}
+
+impl AuthorizationServer for PasetoAuthorizationServer {
+    fn authenticate(&self, token: &Token) -> Result<AuthenticatedUser, AuthorizationServerError> {
+        let paseto_token = try!{self.verify_token(token)};
+        let not_authenticated = |message: String| {
+            AuthorizationServerError::NotAuthenticated { message: message }
+        };
+        let jwt_token = try!{paseto_token.to_json_web_token()
+            .map_err(|err| not_authenticated(format!("{}", err)))};
+        try!{self.validation
+            .validate(&jwt_token)
+            .map_err(|err| not_authenticated(format!("{}", err)))};
+
+        let uid = match paseto_token.get_claim(&Claim::Registered(RegisteredClaim::Subject)) {
+            Some(&Json::String(ref subject)) => Uid(subject.clone()),
+            Some(_) => return Err(not_authenticated(String::from("Field 'sub' is not a String."))),
+            None => return Err(not_authenticated(String::from("Field 'sub' is missing."))),
+        };
+        let scopes = match paseto_token.get_claim(&Claim::Custom("scope")) {
+            None => Vec::new(),
+            Some(&Json::String(ref scopes)) => {
+                scopes.split_whitespace().map(|scope| scope.to_owned()).collect()
+            }
+            Some(&Json::Array(ref scopes)) => {
+                try!{scopes.iter()
+                    .map(|scope| scope.as_string().map(|s| s.to_owned()))
+                    .collect::<Option<Vec<String>>>()
+                    .ok_or_else(|| not_authenticated(String::from("A scope is not a String.")))}
+            }
+            Some(_) => {
+                return Err(not_authenticated(String::from("Field 'scope' is neither a String \
+                                                           nor an array of Strings.")))
+            }
+        };
+        let aud = paseto_token.get_claim(&Claim::Registered(RegisteredClaim::Audience));
+        let audiences = try!{aud.map_or(Some(Vec::new()), parse_audiences).ok_or_else(|| {
+            not_authenticated(String::from("Field 'aud' is neither a String nor an array of \
+                                            Strings."))
+        })};
+        Ok(AuthenticatedUser {
+            uid: Some(uid),
+            scopes: scopes.into_iter().map(Scope).collect(),
+            audiences: audiences,
+        })
+    }
+}
+
+/// Accepts PASETO `v4.public` tokens and hands all other tokens to a JWT `AuthorizationServer`.
+pub struct JwtOrPasetoAuthorizationServer<J: AuthorizationServer> {
+    pub jwt_server: J,
+    pub paseto_server: PasetoAuthorizationServer,
+}
+
+impl<J: AuthorizationServer> JwtOrPasetoAuthorizationServer<J> {
+    pub fn new(jwt_server: J,
+               paseto_server: PasetoAuthorizationServer)
+               -> JwtOrPasetoAuthorizationServer<J> {
+        JwtOrPasetoAuthorizationServer {
+            jwt_server: jwt_server,
+            paseto_server: paseto_server,
+        }
+    }
+}
+
+impl<J: AuthorizationServer> AuthorizationServer for JwtOrPasetoAuthorizationServer<J> {
+    fn authenticate(&self, token: &Token) -> Result<AuthenticatedUser, AuthorizationServerError> {
+        if paseto::is_v4_public(&token.0) {
+            self.paseto_server.authenticate(token)
+        } else {
+            self.jwt_server.authenticate(token)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, UTC};
+    use rustc_serialize::json::Json;
+    use Token;
+    use jwt::{Claim, RegisteredClaim};
+    use paseto::{PasetoToken, PasetoSecretKey};
+    use resource_server::{AuthorizationServer, AuthenticatedUser, JwtAuthorizationServer, Uid};
+    use testing::PlanbTokenBuilder;
+    use super::{PasetoAuthorizationServer, JwtOrPasetoAuthorizationServer};
+
+    fn sign_token(key: &PasetoSecretKey, lifetime_seconds: i64) -> Token {
+        let now = UTC::now().naive_utc();
+        let token = PasetoToken::new()
+            .add_claim(&Claim::Registered(RegisteredClaim::Subject),
+                       Json::String(String::from("test2")))
+            .add_claim(&Claim::Custom("scope"), Json::String(String::from("uid cn")))
+            .add_claim(&Claim::Registered(RegisteredClaim::Audience),
+                       Json::String(String::from("my-service")))
+            .add_date_claim(&Claim::Registered(RegisteredClaim::IssuedAt), now)
+            .add_date_claim(&Claim::Registered(RegisteredClaim::ExpirationTime),
+                            now + Duration::seconds(lifetime_seconds));
+        Token(token.sign(key).unwrap())
+    }
+
+    #[test]
+    fn a_valid_token_must_authenticate_the_user() {
+        let old_key = PasetoSecretKey::generate().unwrap();
+        let key = PasetoSecretKey::generate().unwrap();
+        let server = PasetoAuthorizationServer::new(vec![old_key.public_key().unwrap(),
+                                                         key.public_key().unwrap()])
+            .with_audience("my-service");
+
+        let mut expected = AuthenticatedUser::from_strings("test2", &["uid", "cn"]);
+        expected.audiences = vec![String::from("my-service")];
+        assert_eq!(expected, server.authenticate(&sign_token(&key, 3600)).unwrap());
+    }
+
+    #[test]
+    fn invalid_tokens_must_be_rejected() {
+        let key = PasetoSecretKey::generate().unwrap();
+        let other_key = PasetoSecretKey::generate().unwrap();
+        let server = PasetoAuthorizationServer::new(vec![key.public_key().unwrap()]);
+
+        assert!(server.authenticate(&sign_token(&key, -60)).is_err());
+        assert!(server.authenticate(&sign_token(&other_key, 3600)).is_err());
+        assert!(server.authenticate(&Token::new("v4.public.e30")).is_err());
+        assert!(PasetoAuthorizationServer::new(vec![key.public_key().unwrap()])
+            .with_audience("other-service")
+            .authenticate(&sign_token(&key, 3600))
+            .is_err());
+    }
+
+    #[test]
+    fn both_formats_must_be_accepted() {
+        let key = PasetoSecretKey::generate().unwrap();
+        let minted = PlanbTokenBuilder::new().with_scope("uid").build().unwrap();
+        let jwt_server = JwtAuthorizationServer::new(minted.key_set());
+        let paseto_server = PasetoAuthorizationServer::new(vec![key.public_key().unwrap()]);
+        let server = JwtOrPasetoAuthorizationServer::new(jwt_server, paseto_server);
+
+        assert_eq!(AuthenticatedUser::from_strings("test2", &["uid"]),
+                   server.authenticate(&minted.token).unwrap());
+        assert_eq!(Some(Uid::new("test2")),
+                   server.authenticate(&sign_token(&key, 3600)).unwrap().uid);
+    }
+}
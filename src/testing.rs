@@ -0,0 +1,278 @@
+//! Minting of signed Plan B tokens for tests.
+//!
+//! Only available with the `testing` feature. A `PlanbTokenBuilder` signs a token with
+//! a P-256 key and returns the JWK to verify it, so that authentication can be tested
+//! without a real Plan B provider.
+//!
+//! ```
+//! use rusty_tokens::testing::PlanbTokenBuilder;
+//! use rusty_tokens::resource_server::{AuthorizationServer, JwtAuthorizationServer};
+//!
+//! let minted = PlanbTokenBuilder::new()
+//!     .with_subject("test-user")
+//!     .with_scope("uid")
+//!     .build()
+//!     .unwrap();
+//! let server = JwtAuthorizationServer::new(minted.key_set());
+//!
+//! let user = server.authenticate(&minted.token).unwrap();
+//! assert!(user.has_scope(&rusty_tokens::Scope::new("uid")));
+//! ```
+use std::str::FromStr;
+use chrono::{NaiveDateTime, UTC};
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::nid::Nid;
+use openssl::pkey::Private;
+use rustc_serialize::json::Json;
+use Token;
+use jwt::{JsonWebToken, Header, RegisteredHeader, Claim, RegisteredClaim};
+use jwt::planb::PlanbToken;
+use jwt::signature::{SigningKey, SignatureError};
+use jwt::jwk::{JsonWebKey, JwkSet, KeyType};
+
+/// Builds signed Plan B tokens.
+///
+/// Defaults to the key id `testkey-es256`, the subject `test2`, the realm `/services`,
+/// the issuer `B`, no scopes and a lifetime of one hour starting now.
+/// All tokens of a builder are signed with the same freshly generated key.
+pub struct PlanbTokenBuilder {
+    key_id: String,
+    subject: String,
+    realm: String,
+    scopes: Vec<String>,
+    issuer: String,
+    audiences: Vec<String>,
+    issue_date_utc: Option<NaiveDateTime>,
+    lifetime_seconds: i64,
+    signing_key: EcKey<Private>,
+}
+
+impl PlanbTokenBuilder {
+    /// Create a new instance with the defaults and a new signing key
+    pub fn new() -> PlanbTokenBuilder {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)
+            .expect("The P-256 curve is not available");
+        PlanbTokenBuilder {
+            key_id: String::from("testkey-es256"),
+            subject: String::from("test2"),
+            realm: String::from("/services"),
+            scopes: Vec::new(),
+            issuer: String::from("B"),
+            audiences: Vec::new(),
+            issue_date_utc: None,
+            lifetime_seconds: 3600,
+            signing_key: EcKey::generate(&group).expect("Failed to generate a P-256 key"),
+        }
+    }
+
+    /// Builder method. Set the `kid` of the token and the JWK.
+    pub fn with_key_id<T: Into<String>>(self, key_id: T) -> Self {
+        let mut x = self;
+        x.key_id = key_id.into();
+        x
+    }
+
+    /// Builder method. Set the `sub` claim.
+    pub fn with_subject<T: Into<String>>(self, subject: T) -> Self {
+        let mut x = self;
+        x.subject = subject.into();
+        x
+    }
+
+    /// Builder method. Set the `realm` claim.
+    pub fn with_realm<T: Into<String>>(self, realm: T) -> Self {
+        let mut x = self;
+        x.realm = realm.into();
+        x
+    }
+
+    /// Builder method. Add a scope to the `scope` claim.
+    pub fn with_scope<T: Into<String>>(self, scope: T) -> Self {
+        let mut x = self;
+        x.scopes.push(scope.into());
+        x
+    }
+
+    /// Builder method. Set the `iss` claim.
+    pub fn with_issuer<T: Into<String>>(self, issuer: T) -> Self {
+        let mut x = self;
+        x.issuer = issuer.into();
+        x
+    }
+
+    /// Builder method. Add an audience to the `aud` claim.
+    pub fn with_audience<T: Into<String>>(self, audience: T) -> Self {
+        let mut x = self;
+        x.audiences.push(audience.into());
+        x
+    }
+
+    /// Builder method. Set the `iat` claim instead of using the time of `build`.
+    pub fn with_issue_date_utc(self, issue_date_utc: NaiveDateTime) -> Self {
+        let mut x = self;
+        x.issue_date_utc = Some(issue_date_utc);
+        x
+    }
+
+    /// Builder method. Set the seconds from `iat` to `exp`. May be negative.
+    pub fn with_lifetime_seconds(self, lifetime_seconds: i64) -> Self {
+        let mut x = self;
+        x.lifetime_seconds = lifetime_seconds;
+        x
+    }
+
+    /// Builder method. Sign with the given key instead of a generated one.
+    pub fn with_signing_key(self, signing_key: EcKey<Private>) -> Self {
+        let mut x = self;
+        x.signing_key = signing_key;
+        x
+    }
+
+    /// Signs a token with the configured claims.
+    pub fn build(&self) -> Result<MintedToken, SignatureError> {
+        let issued_at = match self.issue_date_utc {
+            Some(issue_date_utc) => issue_date_utc.timestamp(),
+            None => UTC::now().timestamp(),
+        };
+        let scopes = self.scopes.iter().map(|scope| Json::String(scope.clone())).collect();
+        let mut jwt_token = JsonWebToken::new()
+            .add_header(&Header::Registered(RegisteredHeader::KeyId),
+                        Json::String(self.key_id.clone()))
+            .add_payload(&Claim::Registered(RegisteredClaim::Subject),
+                         Json::String(self.subject.clone()))
+            .add_payload(&Claim::Custom("realm"), Json::String(self.realm.clone()))
+            .add_payload(&Claim::Custom("scope"), Json::Array(scopes))
+            .add_payload(&Claim::Registered(RegisteredClaim::Issuer),
+                         Json::String(self.issuer.clone()))
+            .add_payload(&Claim::Registered(RegisteredClaim::IssuedAt),
+                         Json::I64(issued_at))
+            .add_payload(&Claim::Registered(RegisteredClaim::ExpirationTime),
+                         Json::I64(issued_at + self.lifetime_seconds));
+        if !self.audiences.is_empty() {
+            let audiences = self.audiences
+                .iter()
+                .map(|audience| Json::String(audience.clone()))
+                .collect();
+            jwt_token = jwt_token.add_payload(&Claim::Registered(RegisteredClaim::Audience),
+                                              Json::Array(audiences));
+        }
+        let token = try!{jwt_token.encode(&SigningKey::EcP256(self.signing_key.clone()))};
+        let planb_token = PlanbToken::from_str(&token).expect("A minted token is a Plan B token");
+        Ok(MintedToken {
+            token: Token(token),
+            jwk: try!{self.jwk()},
+            planb_token: planb_token,
+        })
+    }
+
+    fn jwk(&self) -> Result<JsonWebKey, SignatureError> {
+        let mut jwk = try!{ec_p256_jwk(&self.signing_key)}.with_key_id(self.key_id.clone());
+        jwk.key_use = Some(String::from("sig"));
+        jwk.algorithm = Some(String::from("ES256"));
+        Ok(jwk)
+    }
+}
+
+/// The public key of a P-256 key as a JWK without any optional parameters.
+///
+/// The coordinates are padded to 32 bytes as required by
+/// [RFC 7518](https://tools.ietf.org/html/rfc7518#section-6.2.1.2).
+pub fn ec_p256_jwk(key: &EcKey<Private>) -> Result<JsonWebKey, SignatureError> {
+    let mut ctx = try!{BigNumContext::new()};
+    let mut x = try!{BigNum::new()};
+    let mut y = try!{BigNum::new()};
+    try!{key.public_key().affine_coordinates_gfp(key.group(), &mut x, &mut y, &mut ctx)};
+    Ok(JsonWebKey::new(KeyType::Ec {
+        curve: String::from("P-256"),
+        x: pad_coordinate(&x),
+        y: pad_coordinate(&y),
+    }))
+}
+
+fn pad_coordinate(coordinate: &BigNum) -> Vec<u8> {
+    let bytes = coordinate.to_vec();
+    let mut padded = vec![0u8; 32];
+    padded[32 - bytes.len()..].copy_from_slice(&bytes);
+    padded
+}
+
+/// A signed token and the key to verify it
+pub struct MintedToken {
+    /// The compact serialization of the token
+    pub token: Token,
+    /// The public key with the `kid` of the token
+    pub jwk: JsonWebKey,
+    /// The claims of the token
+    pub planb_token: PlanbToken,
+}
+
+impl MintedToken {
+    /// A set with only the key of the token, e.g. to use as a `KeyStore`.
+    pub fn key_set(&self) -> JwkSet {
+        JwkSet::new(vec![self.jwk.clone()])
+    }
+
+    /// The key set as served by a Plan B provider, e.g. for a fake key endpoint.
+    pub fn key_set_json(&self) -> String {
+        self.key_set().to_json().to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use chrono::{NaiveDate, NaiveTime, NaiveDateTime};
+    use jwt::planb::PlanbToken;
+    use jwt::jwk::JwkSet;
+    use resource_server::{AuthorizationServer, AuthenticatedUser, JwtAuthorizationServer};
+    use super::PlanbTokenBuilder;
+
+    #[test]
+    fn a_minted_token_must_authenticate_the_user() {
+        let minted = PlanbTokenBuilder::new()
+            .with_subject("test-user")
+            .with_scope("uid")
+            .with_scope("cn")
+            .build()
+            .unwrap();
+        let server = JwtAuthorizationServer::new(minted.key_set());
+
+        assert_eq!(AuthenticatedUser::from_strings("test-user", &["uid", "cn"]),
+                   server.authenticate(&minted.token).unwrap());
+    }
+
+    #[test]
+    fn the_claims_must_be_deterministic() {
+        let issue_date = NaiveDateTime::new(NaiveDate::from_ymd(2016, 3, 6),
+                                            NaiveTime::from_hms(19, 3, 34));
+        let builder = PlanbTokenBuilder::new()
+            .with_key_id("my-key")
+            .with_realm("/employees")
+            .with_issuer("A")
+            .with_issue_date_utc(issue_date)
+            .with_lifetime_seconds(28800);
+
+        let minted = builder.build().unwrap();
+        let key_set = JwkSet::from_str(&minted.key_set_json()).unwrap();
+        let verified = PlanbToken::verify_with_key_set(&minted.token.0, &key_set).unwrap();
+
+        assert_eq!(minted.planb_token, verified);
+        assert_eq!(builder.build().unwrap().planb_token, verified);
+        assert_eq!("my-key", verified.header.key_id);
+        assert_eq!("/employees", verified.payload.realm);
+        assert_eq!("A", verified.payload.issuer);
+        assert_eq!(issue_date, verified.payload.issue_date_utc);
+        assert_eq!(NaiveDateTime::new(NaiveDate::from_ymd(2016, 3, 7),
+                                      NaiveTime::from_hms(3, 3, 34)),
+                   verified.payload.expiration_date_utc);
+    }
+
+    #[test]
+    fn an_expired_token_can_be_minted() {
+        let minted = PlanbTokenBuilder::new().with_lifetime_seconds(-60).build().unwrap();
+        let server = JwtAuthorizationServer::new(minted.key_set());
+
+        assert!(server.authenticate(&minted.token).is_err());
+    }
+}